@@ -21,6 +21,7 @@ mod transport;
 use hardware::io::RadioReceiverPin;
 use hardware::io::RadioSenderPin;
 use hardware::{Hardware, HardwareSetup};
+use network::error::{DataConstructionError, NetworkError};
 use network::transport::{TransportReceiver, TransportSender};
 use network::Address;
 
@@ -48,9 +49,14 @@ async fn read_task(mut simple_receiver: transport::ReceiverFactory<'static>) {
 
         Timer::after(Duration::from_millis(500)).await;
 
-        if data.is_err() {
-            // If for example full windows, give it more time before start listening again.
-            Timer::after(Duration::from_secs(5)).await;
+        match data {
+            // Stale stream was already dropped, we can listen again right away
+            Err(NetworkError::DataConstructingError(DataConstructionError::StreamTimeout)) => {}
+            Err(_) => {
+                // If for example full windows, give it more time before start listening again.
+                Timer::after(Duration::from_secs(5)).await;
+            }
+            Ok(_) => {}
         }
     }
 }
@@ -62,7 +68,7 @@ async fn main(spawner: Spawner) -> ! {
     ///////////////////
     // Init reader
 
-    let receiver_address = Address::new(0x01, 0x0f);
+    let receiver_address = Address::new(0x01, 0x02);
     let simple_receiver =
         transport::create_transport_receiver(hardware, &RF_INPUT_PIN, receiver_address);
     spawner.spawn(read_task(simple_receiver)).unwrap();
@@ -70,7 +76,7 @@ async fn main(spawner: Spawner) -> ! {
     ///////////////////
     // Init sender
 
-    let sender_address = Address::new(0x0f, 0x01);
+    let sender_address = Address::new(0x02, 0x01);
     let mut simple_sender =
         transport::create_transport_sender(hardware, &RF_OUTPUT_PIN, sender_address);
     let mut transport = simple_sender.create_transport();
@@ -17,6 +17,7 @@ use crate::hardware::io::{RadioReceiverPin, RadioSenderPin};
 
 use codec::lzss::LzssCompression;
 use codec::reed_solomon::ReedSolomon;
+use network::packet::Packet64;
 use network::simple::receiver::SimpleReceiver;
 use network::simple::sender::SimpleSender;
 use network::Address;
@@ -52,12 +53,18 @@ fn get_reader_timing() -> ReaderTiming {
 // type CodecType = FourToSixBits<18>;
 // type CodecType = ReedSolomon<4, 8>;
 type CodecType = Identity;
-// FIXME FourToSixBits buffer weird
-// type CodecType = Chain<ReedSolomon<4, 4>, FourToSixBits<20>, 4>;
+// Every stage is sized for the output of the previous one, Reed-Solomon turns 8 bytes into 12
+// type CodecType = Chain<ReedSolomon<4, 8>, FourToSixBits<12>, 8>;
+// Codec selected at runtime, e.g. `DynamicCodec::from_ids` with ids from the configuration.
+// Frame size is not known, so the reader waits for the end of every transmission.
+// type CodecType = codec::dynamic::DynamicCodec;
 
 // type CompressionType = LzssCompression;
 type CompressionType = Identity;
 
+// type PacketType = Packet32;
+type PacketType = Packet64;
+
 fn create_codec() -> CodecType {
     CodecType::default()
 }
@@ -66,6 +73,7 @@ fn create_compression() -> CompressionType {
 }
 
 pub type SenderFactory<'a> = SimpleSender<
+    PacketType,
     SyncWriter<
         // PinPwmWriter<'a, io::RadioSenderPin, false>,
         ManchesterWriter<'a, io::RadioSenderPin>,
@@ -75,6 +83,7 @@ pub type SenderFactory<'a> = SimpleSender<
     CompressionType,
 >;
 pub type ReceiverFactory<'a> = SimpleReceiver<
+    PacketType,
     SyncReader<
         // PinPwmReader<'a, io::RadioReceiverPin, false>,
         ManchesterReader<'a, io::RadioReceiverPin>,
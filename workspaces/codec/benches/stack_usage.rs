@@ -0,0 +1,175 @@
+//! Stack used by the iterator and the buffer API of the codecs, run on the host with
+//! `cargo bench -p codec --bench stack_usage`.
+//!
+//! Stack below the current frame is painted with a pattern, then the measured code runs
+//! and the lowest overwritten byte tells how deep it went. Numbers are for the host build,
+//! the firmware uses less, but the difference between the two APIs is similar.
+
+use core::hint::black_box;
+use core::ptr::{read_volatile, write_volatile};
+
+use codec::chain::Chain2;
+use codec::convolutional::Convolutional;
+use codec::dynamic::{CodecKind, DynamicCodec};
+use codec::four_to_six::FourToSixBits;
+use codec::lzss::LzssCompression;
+use codec::reed_solomon::ReedSolomon;
+use codec::{Codec, Identity};
+
+const PAINTED_SIZE: usize = 32 * 1024;
+const PAINT: u8 = 0xa5;
+/// Stack of the painting function itself is not painted
+const PAINT_MARGIN: usize = 64;
+
+const PAYLOAD_SIZE: usize = 16;
+const BUFFER_SIZE: usize = 128;
+
+#[inline(never)]
+fn paint_stack() -> usize {
+    let marker = 0u8;
+    let top = black_box(&marker) as *const u8 as usize - PAINT_MARGIN;
+    for address in top - PAINTED_SIZE..top {
+        // SAFETY: memory below the stack pointer is not used by anyone,
+        // the measuring thread has a stack much bigger than the painted part
+        unsafe { write_volatile(address as *mut u8, PAINT) };
+    }
+    top
+}
+
+/// Bytes of stack used by `code`, including the call itself
+#[inline(never)]
+fn measure(code: &mut dyn FnMut()) -> usize {
+    let top = paint_stack();
+    // Hidden from the optimizer, so the code is not inlined into this frame
+    let code = black_box(code);
+    code();
+
+    let lowest = (top - PAINTED_SIZE..top)
+        // SAFETY: the same memory which was painted
+        .find(|address| unsafe { read_volatile(*address as *const u8) } != PAINT)
+        .unwrap_or(top);
+    top - lowest
+}
+
+struct Usage {
+    encode_iterator: usize,
+    encode_buffer: usize,
+    decode_iterator: usize,
+    decode_buffer: usize,
+}
+
+fn measure_codec<C: Codec>(codec: &C, payload: &[u8]) -> Usage {
+    let mut encoded = [0u8; BUFFER_SIZE];
+    let size = codec
+        .encode_into(payload, &mut encoded)
+        .expect("Payload should be encoded");
+    let encoded = &encoded[..size];
+
+    // Both APIs write into the same output, so only the codec makes the difference
+    let encode_iterator = measure(&mut || {
+        let mut output = [0u8; BUFFER_SIZE];
+        for (index, byte) in codec.encode(black_box(payload)).unwrap().enumerate() {
+            output[index] = byte;
+        }
+        black_box(&output);
+    });
+    let encode_buffer = measure(&mut || {
+        let mut output = [0u8; BUFFER_SIZE];
+        codec.encode_into(black_box(payload), &mut output).unwrap();
+        black_box(&output);
+    });
+    let decode_iterator = measure(&mut || {
+        let mut output = [0u8; BUFFER_SIZE];
+        for (index, byte) in codec.decode(black_box(encoded)).unwrap().enumerate() {
+            output[index] = byte;
+        }
+        black_box(&output);
+    });
+    let decode_buffer = measure(&mut || {
+        let mut output = [0u8; BUFFER_SIZE];
+        codec.decode_into(black_box(encoded), &mut output).unwrap();
+        black_box(&output);
+    });
+
+    Usage {
+        encode_iterator,
+        encode_buffer,
+        decode_iterator,
+        decode_buffer,
+    }
+}
+
+fn print_usage(name: &str, usage: Usage, baseline: usize) {
+    println!(
+        "{:<24} {:>12} {:>12} {:>12} {:>12}",
+        name,
+        usage.encode_iterator.saturating_sub(baseline),
+        usage.encode_buffer.saturating_sub(baseline),
+        usage.decode_iterator.saturating_sub(baseline),
+        usage.decode_buffer.saturating_sub(baseline),
+    );
+}
+
+fn run() {
+    let payload: Vec<u8> = (0..PAYLOAD_SIZE as u8).map(|i| i % 4).collect();
+
+    // Stack of the empty closure with the output buffer is subtracted from every number
+    let baseline = measure(&mut || {
+        let output = [0u8; BUFFER_SIZE];
+        black_box(&output);
+    });
+
+    println!("Stack usage in bytes, {} bytes of payload", PAYLOAD_SIZE);
+    println!(
+        "{:<24} {:>12} {:>12} {:>12} {:>12}",
+        "codec", "encode iter", "encode buf", "decode iter", "decode buf"
+    );
+
+    let identity = Identity::default();
+    print_usage("Identity", measure_codec(&identity, &payload), baseline);
+
+    let reed_solomon = ReedSolomon::<4, PAYLOAD_SIZE>::default();
+    print_usage(
+        "ReedSolomon<4>",
+        measure_codec(&reed_solomon, &payload),
+        baseline,
+    );
+
+    let four_to_six = FourToSixBits::<PAYLOAD_SIZE>::default();
+    print_usage(
+        "FourToSixBits",
+        measure_codec(&four_to_six, &payload),
+        baseline,
+    );
+
+    let convolutional = Convolutional::<PAYLOAD_SIZE>::default();
+    print_usage(
+        "Convolutional",
+        measure_codec(&convolutional, &payload),
+        baseline,
+    );
+
+    let lzss = LzssCompression::default();
+    print_usage("LzssCompression", measure_codec(&lzss, &payload), baseline);
+
+    let chain = Chain2::<ReedSolomon<4, PAYLOAD_SIZE>, FourToSixBits<20>, PAYLOAD_SIZE>::default();
+    print_usage("Chain<RS, 4to6>", measure_codec(&chain, &payload), baseline);
+
+    let dynamic = DynamicCodec::chain(&[CodecKind::ReedSolomon4, CodecKind::FourToSix])
+        .expect("Two stages are fine");
+    print_usage(
+        "DynamicCodec<RS, 4to6>",
+        measure_codec(&dynamic, &payload),
+        baseline,
+    );
+}
+
+fn main() {
+    // Own thread, so the painted stack is surely mapped
+    std::thread::Builder::new()
+        .stack_size(1 << 20)
+        .spawn(run)
+        .expect("Thread should start")
+        .join()
+        .expect("Benchmark should not panic");
+}
@@ -1,7 +1,11 @@
-use crate::{Codec, CodecError, CodecSize};
-
-use heapless::Vec;
+use crate::{write_vec, Codec, CodecError, CodecSize, DecodeReport};
 
+/// Two codecs used one after another, `CodecA` encodes first and decodes last.
+///
+/// `INPUT_DATA_SIZE` is the biggest payload of the whole chain, buffers between
+/// the stages are computed from `CodecSize`. More stages are added with `then`,
+/// every codec has to fit the output of the previous stage (e.g. `ReedSolomon<4, 8>`
+/// produces 12 bytes, so `FourToSixBits<12>` should follow it).
 #[derive(Default)]
 pub struct Chain<CodecA: Default, CodecB: Default, const INPUT_DATA_SIZE: usize> {
     codec_a: CodecA,
@@ -22,39 +26,104 @@ pub const fn max_size_1<CodecA: ~const CodecSize>(input: usize) -> usize {
     CodecA::get_encode_const_size(input)
 }
 
-pub const fn max_size_2<CodecA: ~const CodecSize, CodecB: ~const CodecSize>(input: usize) -> usize {
+pub const fn max_size_2<CodecA: ~const CodecSize, CodecB: ~const CodecSize>(
+    input: usize,
+) -> usize {
     CodecB::get_encode_const_size(CodecA::get_encode_const_size(input))
 }
 
+impl<CodecA: Default, CodecB: Default, const INPUT_DATA_SIZE: usize>
+    Chain<CodecA, CodecB, INPUT_DATA_SIZE>
+{
+    pub fn new(codec_a: CodecA, codec_b: CodecB) -> Self {
+        Self { codec_a, codec_b }
+    }
+
+    /// Add another stage after the whole chain, e.g.
+    /// `Chain::<A, B, 8>::default().then(C::default())` encodes with A, B and then C
+    pub fn then<CodecC: Default>(self, codec_c: CodecC) -> Chain<Self, CodecC, INPUT_DATA_SIZE> {
+        Chain::new(self, codec_c)
+    }
+}
+
 impl<CodecA, CodecB, const INPUT_DATA_SIZE: usize> Codec for Chain<CodecA, CodecB, INPUT_DATA_SIZE>
 where
     CodecA: Default + Codec + ~const CodecSize,
     CodecB: Default + Codec + ~const CodecSize,
 
     [(); max_size_1::<CodecA>(INPUT_DATA_SIZE)]: Sized,
-    // [(); max_size_2::<CodecA, CodecB>(INPUT_DATA_SIZE)]: Sized,
+    [(); max_size_2::<CodecA, CodecB>(INPUT_DATA_SIZE)]: Sized,
 {
-    type Encoded<'a> = impl Iterator<Item = u8> + 'a;
-    type Decoded<'a> = impl Iterator<Item = u8> + 'a;
+    // Size of the encoded buffer depends on both codecs, so the iterators can't outlive them
+    type Encoded<'a>
+        = impl Iterator<Item = u8> + 'a
+    where
+        Self: 'a;
+    type Decoded<'a>
+        = impl Iterator<Item = u8> + 'a
+    where
+        Self: 'a;
 
     fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
-        let a_encoded: Vec<_, { max_size_1::<CodecA>(INPUT_DATA_SIZE) }> =
-            self.codec_a.encode(payload)?.collect();
+        let (encoded, _) =
+            write_vec::<_, { max_size_2::<CodecA, CodecB>(INPUT_DATA_SIZE) }>(|output| {
+                Ok((self.encode_into(payload, output)?, ()))
+            })?;
+        Ok(encoded.into_iter())
+    }
 
-        // TODO some weird error with lifetime when we use const generics here. WTF?
-        // let b_encoded: Vec<_, { max_size_2::<CodecA, CodecB>(INPUT_DATA_SIZE) }> =
-        //     self.codec_b.encode(&a_encoded[..])?.collect();
-        let b_encoded: Vec<_, 128> = self.codec_b.encode(&a_encoded[..])?.collect();
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = write_vec::<_, INPUT_DATA_SIZE>(|output| {
+            self.decode_into_with_report(payload, &[], output)
+        })?;
+        Ok(decoded.into_iter())
+    }
 
-        Ok(b_encoded.into_iter())
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = write_vec::<_, INPUT_DATA_SIZE>(|output| {
+            self.decode_into_with_report(payload, erasures, output)
+        })?;
+        Ok((decoded.into_iter(), report))
     }
 
-    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
-        let b_decoded: Vec<_, { max_size_1::<CodecA>(INPUT_DATA_SIZE) }> =
-            self.codec_b.decode(payload)?.collect();
-        let a_decoded: Vec<_, { INPUT_DATA_SIZE }> = self.codec_a.decode(&b_decoded[..])?.collect();
+    /// Only the data between the stages is buffered
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > INPUT_DATA_SIZE {
+            return Err(CodecError::EncodeError);
+        }
+
+        let mut a_encoded = [0u8; max_size_1::<CodecA>(INPUT_DATA_SIZE)];
+        let a_size = self.codec_a.encode_into(payload, &mut a_encoded)?;
+        self.codec_b.encode_into(&a_encoded[..a_size], output)
+    }
+
+    /// Erasures reported by the second codec are passed to the first one,
+    /// corrections of both codecs are summed up
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        // Broken data can decode into more bytes than valid one
+        let mut b_decoded = [0u8; max_size_1::<CodecA>(INPUT_DATA_SIZE)];
+        let (b_size, b_report) =
+            self.codec_b
+                .decode_into_with_report(payload, erasures, &mut b_decoded)?;
+
+        let output_size = output.len().min(INPUT_DATA_SIZE);
+        let (size, mut report) = self.codec_a.decode_into_with_report(
+            &b_decoded[..b_size],
+            b_report.erasures(),
+            &mut output[..output_size],
+        )?;
 
-        Ok(a_decoded.into_iter())
+        report.corrected += b_report.corrected;
+        Ok((size, report))
     }
 
     fn get_encode_size(payload_size: usize) -> usize {
@@ -78,6 +147,9 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::convolutional::Convolutional;
+    use crate::four_to_six::FourToSixBits;
+    use crate::ldpc;
     use crate::lzss::LzssCompression;
     use crate::reed_solomon::ReedSolomon;
     use crate::Identity;
@@ -152,4 +224,204 @@ mod test {
             .collect();
         assert_eq!(payload, decoded);
     }
+
+    #[test]
+    fn test_chain_passes_erasures() {
+        let codec = Chain2::<ReedSolomon<4, 4>, FourToSixBits<16>, 4>::default();
+        let payload = vec![1u8, 2, 3, 4];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        // Break symbols of the first four bytes, too many errors for the
+        // Reed-Solomon alone, but fine when it knows where they are
+        encoded[0] |= 0x3f;
+        encoded[1] |= 0xf0;
+        encoded[2] |= 0x03;
+        encoded[3] |= 0x3f;
+        encoded[4] |= 0xf0;
+        encoded[5] |= 0x03;
+
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(payload, decoded.collect::<Vec<_>>());
+        assert!(report.corrected >= 4);
+        assert!(report.erasures().is_empty());
+    }
+
+    const PROPERTY_INPUT_SIZE: usize = 16;
+    const PROPERTY_CASES: usize = 200;
+
+    type Rs = ReedSolomon<4, 64>;
+    type FourToSix = FourToSixBits<64>;
+    type Ldpc = ldpc::Ldpc<64>;
+    type Conv = Convolutional<64>;
+
+    /// Random payloads of every size up to `max_size`, small alphabets give Lzss something to compress.
+    /// Roundtrip has to work, encoded size has to fit `get_encode_size` and decoding garbage must not panic
+    fn check_properties<C: Codec>(codec: &C, max_size: usize, seed: u64) {
+        let mut random = simulated_channel::XorShiftRng::new(seed);
+
+        for _ in 0..PROPERTY_CASES {
+            let size = random.next_u32() as usize % (max_size + 1);
+            let alphabet = 1 + random.next_u32() % 256;
+            let payload: Vec<u8> = (0..size)
+                .map(|_| (random.next_u32() % alphabet) as u8)
+                .collect();
+
+            let encoded: Vec<_> = codec
+                .encode(&payload[..])
+                .expect("There should be no error")
+                .collect();
+            assert!(
+                encoded.len() <= C::get_encode_size(size),
+                "{} bytes encoded into {}, expected at most {}",
+                size,
+                encoded.len(),
+                C::get_encode_size(size)
+            );
+
+            let decoded: Vec<_> = codec
+                .decode(&encoded[..])
+                .expect("There should be no error")
+                .collect();
+            assert_eq!(payload, decoded);
+
+            // Buffer API writes the same data
+            let mut buffer = [0u8; 256];
+            let size = codec
+                .encode_into(&payload[..], &mut buffer[..C::get_encode_size(size)])
+                .expect("There should be no error");
+            assert_eq!(encoded, &buffer[..size]);
+            let size = codec
+                .decode_into(&encoded[..], &mut buffer[..payload.len()])
+                .expect("There should be no error");
+            assert_eq!(payload, &buffer[..size]);
+
+            let garbage_size = random.next_u32() as usize % (2 * C::get_encode_size(max_size));
+            let garbage: Vec<u8> = (0..garbage_size).map(|_| random.next_u32() as u8).collect();
+            let _ = codec.decode(&garbage[..]).map(|decoded| decoded.count());
+            let _ = codec.decode_into(&garbage[..], &mut buffer);
+        }
+    }
+
+    macro_rules! chain_properties {
+        ($($name:ident: $a:ty, $b:ty;)*) => {
+            $(
+                #[test]
+                fn $name() {
+                    let codec = Chain2::<$a, $b, PROPERTY_INPUT_SIZE>::default();
+                    check_properties(&codec, PROPERTY_INPUT_SIZE, 0x5eed);
+                }
+            )*
+        };
+    }
+
+    chain_properties! {
+        test_properties_identity_identity: Identity, Identity;
+        test_properties_identity_rs: Identity, Rs;
+        test_properties_identity_four_to_six: Identity, FourToSix;
+        test_properties_identity_lzss: Identity, LzssCompression;
+        test_properties_rs_identity: Rs, Identity;
+        test_properties_rs_rs: Rs, Rs;
+        test_properties_rs_four_to_six: Rs, FourToSix;
+        test_properties_rs_lzss: Rs, LzssCompression;
+        test_properties_four_to_six_identity: FourToSix, Identity;
+        test_properties_four_to_six_rs: FourToSix, Rs;
+        test_properties_four_to_six_four_to_six: FourToSix, FourToSix;
+        test_properties_four_to_six_lzss: FourToSix, LzssCompression;
+        test_properties_lzss_identity: LzssCompression, Identity;
+        test_properties_lzss_rs: LzssCompression, Rs;
+        test_properties_lzss_four_to_six: LzssCompression, FourToSix;
+        test_properties_lzss_lzss: LzssCompression, LzssCompression;
+        test_properties_rs_ldpc: Rs, Ldpc;
+        test_properties_ldpc_rs: Ldpc, Rs;
+        test_properties_four_to_six_ldpc: FourToSix, Ldpc;
+        test_properties_ldpc_four_to_six: Ldpc, FourToSix;
+        test_properties_rs_conv: Rs, Conv;
+        test_properties_conv_rs: Conv, Rs;
+        test_properties_four_to_six_conv: FourToSix, Conv;
+        test_properties_conv_four_to_six: Conv, FourToSix;
+    }
+
+    #[test]
+    fn test_properties_then() {
+        let codec = Chain2::<LzssCompression, Rs, PROPERTY_INPUT_SIZE>::default()
+            .then(FourToSix::default());
+        check_properties(&codec, PROPERTY_INPUT_SIZE, 0xc0dec);
+    }
+
+    /// Codec borrowing its configuration, xors the data with the key
+    #[derive(Default)]
+    struct Whitening<'k> {
+        key: &'k [u8],
+    }
+
+    impl<'k> Codec for Whitening<'k> {
+        type Encoded<'a>
+            = impl Iterator<Item = u8> + 'a
+        where
+            Self: 'a;
+        type Decoded<'a>
+            = impl Iterator<Item = u8> + 'a
+        where
+            Self: 'a;
+
+        fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
+            let key = self.key;
+            Ok(payload
+                .iter()
+                .zip(key.iter().cycle())
+                .map(|(byte, key)| byte ^ key))
+        }
+
+        fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+            self.encode(payload)
+        }
+
+        fn get_encode_size(payload_size: usize) -> usize {
+            payload_size
+        }
+    }
+
+    impl<'k> const CodecSize for Whitening<'k> {
+        fn get_encode_const_size(payload_size: usize) -> usize {
+            payload_size
+        }
+    }
+
+    #[test]
+    fn test_properties_borrowed_codec() {
+        let key = [0x5au8, 0xa5, 0x3c];
+        let codec = Chain2::<Whitening, Rs, PROPERTY_INPUT_SIZE>::new(
+            Whitening { key: &key },
+            Rs::default(),
+        );
+        check_properties(&codec, PROPERTY_INPUT_SIZE, 0xb0a7);
+    }
+
+    #[test]
+    fn test_exact_stage_sizes() {
+        // Every buffer is exactly as big as the previous stage output
+        let codec = Chain2::<ReedSolomon<4, 8>, FourToSixBits<12>, 8>::default();
+        check_properties(&codec, 8, 0xf00d);
+    }
+
+    #[test]
+    fn test_too_big_payload() {
+        let codec = Chain2::<ReedSolomon<4, 8>, FourToSixBits<12>, 8>::default();
+        assert!(codec.encode(&[0u8; 9]).is_err());
+        assert!(codec.decode(&[0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn test_too_small_buffer() {
+        let codec = Chain2::<ReedSolomon<4, 8>, FourToSixBits<12>, 8>::default();
+        let payload = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut buffer = [0u8; 32];
+        let size = codec.encode_into(&payload, &mut buffer).unwrap();
+
+        let mut small = [0u8; 17];
+        assert!(codec.encode_into(&payload, &mut small[..size - 1]).is_err());
+        assert!(codec.decode_into(&buffer[..size], &mut small[..7]).is_err());
+        assert_eq!(codec.decode_into(&buffer[..size], &mut small).unwrap(), 8);
+        assert_eq!(&small[..8], &payload);
+    }
 }
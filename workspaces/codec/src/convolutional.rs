@@ -0,0 +1,297 @@
+use crate::{write_vec, Codec, CodecError, CodecSize, DecodeReport};
+
+/// Constraint length, 16 states are cheap enough for Cortex-M0+
+const CONSTRAINT_LENGTH: usize = 5;
+const STATES: usize = 1 << (CONSTRAINT_LENGTH - 1);
+/// Generator polynomials 23 and 35 (octal), best rate 1/2 code for K=5 with free distance 7
+const GENERATORS: [u8; 2] = [0o23, 0o35];
+/// Zero bits flushing the encoder back to the zero state
+const TAIL_BITS: usize = CONSTRAINT_LENGTH - 1;
+/// Steps of the trellis for every encoded byte, every step sends two bits
+const STEPS_PER_BYTE: usize = 4;
+/// Path metric of states the decoder can't be in
+const UNREACHABLE: u16 = u16::MAX / 2;
+
+/// Rate 1/2 convolutional code with a hard decision Viterbi decoder.
+///
+/// Unlike Reed-Solomon it doesn't care about byte boundaries, so it fixes scattered
+/// bit flips and short bursts up to about 3 bits. Longer bursts are left to an outer code
+/// in a `Chain`, e.g. Reed-Solomon. Erased bytes of the encoded data are ignored by the decoder.
+#[derive(Default)]
+pub struct Convolutional<const MAX_INPUT_SIZE: usize> {}
+
+impl<const MAX_INPUT_SIZE: usize> Convolutional<MAX_INPUT_SIZE> {
+    /// Two bits sent when `bit` enters the encoder in `state`
+    fn branch_output(state: usize, bit: u8) -> u8 {
+        let register = ((state as u8) << 1) | bit;
+        let first = (register & GENERATORS[0]).count_ones() as u8 & 1;
+        let second = (register & GENERATORS[1]).count_ones() as u8 & 1;
+        (first << 1) | second
+    }
+
+    fn next_state(state: usize, bit: u8) -> usize {
+        ((state << 1) | bit as usize) & (STATES - 1)
+    }
+}
+
+/// Bits are written most significant first
+fn set_bit(data: &mut [u8], index: usize, bit: u8) {
+    data[index / 8] |= bit << (7 - index % 8);
+}
+
+fn get_bit(data: &[u8], index: usize) -> u8 {
+    (data[index / 8] >> (7 - index % 8)) & 1
+}
+
+impl<const MAX_INPUT_SIZE: usize> Codec for Convolutional<MAX_INPUT_SIZE>
+where
+    [(); Self::get_encode_const_size(MAX_INPUT_SIZE)]: Sized,
+{
+    type Encoded<'a> = impl Iterator<Item = u8> + 'a;
+    type Decoded<'a> = impl Iterator<Item = u8> + 'a;
+
+    fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
+        let (encoded, _) =
+            write_vec::<_, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>(|output| {
+                Ok((self.encode_into(payload, output)?, ()))
+            })?;
+        Ok(encoded.into_iter())
+    }
+
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = write_vec::<_, MAX_INPUT_SIZE>(|output| {
+            self.decode_into_with_report(payload, &[], output)
+        })?;
+        Ok(decoded.into_iter())
+    }
+
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = write_vec::<_, MAX_INPUT_SIZE>(|output| {
+            self.decode_into_with_report(payload, erasures, output)
+        })?;
+        Ok((decoded.into_iter(), report))
+    }
+
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > MAX_INPUT_SIZE {
+            return Err(CodecError::EncodeError);
+        }
+
+        let size = Self::get_encode_const_size(payload.len());
+        let output = output.get_mut(..size).ok_or(CodecError::EncodeError)?;
+        output.fill(0);
+
+        let mut state = 0usize;
+        let input_bits = payload.len() * 8;
+        for step in 0..input_bits + TAIL_BITS {
+            let bit = if step < input_bits {
+                get_bit(payload, step)
+            } else {
+                0
+            };
+
+            let branch = Self::branch_output(state, bit);
+            set_bit(output, 2 * step, branch >> 1);
+            set_bit(output, 2 * step + 1, branch & 1);
+            state = Self::next_state(state, bit);
+        }
+
+        Ok(size)
+    }
+
+    /// Corrected symbols are bits which differ from the most likely path
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        // Every frame is a whole number of input bytes and the tail
+        if payload.len() % 2 == 0 || payload.len() > Self::get_encode_const_size(MAX_INPUT_SIZE) {
+            return Err(CodecError::DecodeError);
+        }
+        let size = payload.len() / 2;
+        let output = output.get_mut(..size).ok_or(CodecError::DecodeError)?;
+
+        // Bit of every state for every step, which of the two previous states was better
+        let mut decisions = [[0u16; STEPS_PER_BYTE]; Self::get_encode_const_size(MAX_INPUT_SIZE)];
+        let mut metrics = [UNREACHABLE; STATES];
+        metrics[0] = 0;
+
+        let steps = payload.len() * STEPS_PER_BYTE;
+        for step in 0..steps {
+            let erased = u8::try_from(step / STEPS_PER_BYTE)
+                .is_ok_and(|position| erasures.contains(&position));
+            let received = (get_bit(payload, 2 * step) << 1) | get_bit(payload, 2 * step + 1);
+
+            let mut next_metrics = [UNREACHABLE; STATES];
+            let mut step_decisions = 0u16;
+            for (state, next_metric) in next_metrics.iter_mut().enumerate() {
+                // Both previous states shift in the same bit, they differ in the oldest one
+                let bit = (state & 1) as u8;
+                let previous = [state >> 1, (state >> 1) | (STATES >> 1)];
+
+                let candidates = previous.map(|previous| {
+                    let distance = if erased {
+                        0
+                    } else {
+                        (Self::branch_output(previous, bit) ^ received).count_ones() as u16
+                    };
+                    metrics[previous].saturating_add(distance)
+                });
+
+                if candidates[1] < candidates[0] {
+                    *next_metric = candidates[1];
+                    step_decisions |= 1 << state;
+                } else {
+                    *next_metric = candidates[0];
+                }
+            }
+
+            decisions[step / STEPS_PER_BYTE][step % STEPS_PER_BYTE] = step_decisions;
+            metrics = next_metrics;
+        }
+
+        // Tail returns the encoder to the zero state, trace the path back from it
+        output.fill(0);
+        let mut state = 0usize;
+        for step in (0..steps).rev() {
+            let bit = (state & 1) as u8;
+            if step < size * 8 {
+                set_bit(output, step, bit);
+            }
+
+            let decision = (decisions[step / STEPS_PER_BYTE][step % STEPS_PER_BYTE] >> state) & 1;
+            state = (state >> 1) | ((decision as usize) * (STATES >> 1));
+        }
+
+        Ok((size, DecodeReport::corrected(metrics[0] as usize)))
+    }
+
+    fn get_encode_size(payload_size: usize) -> usize {
+        Self::get_encode_const_size(payload_size)
+    }
+}
+
+impl<const MAX_INPUT_SIZE: usize> const CodecSize for Convolutional<MAX_INPUT_SIZE> {
+    /// Two bits for every bit and for every bit of the tail
+    fn get_encode_const_size(payload_size: usize) -> usize {
+        debug_assert!(payload_size <= MAX_INPUT_SIZE);
+
+        (2 * (payload_size * 8 + TAIL_BITS)).div_ceil(8)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chain::Chain2;
+    use crate::reed_solomon::ReedSolomon;
+    use simulated_channel::XorShiftRng;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_encode_decode() {
+        let codec = Convolutional::<16>::default();
+        for size in 0..=16usize {
+            let payload: Vec<u8> = (0..size).map(|i| (i * 37) as u8).collect();
+
+            let encoded: Vec<_> = codec
+                .encode(&payload[..])
+                .expect("There should be no error")
+                .collect();
+            assert_eq!(encoded.len(), 2 * size + 1);
+
+            let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+            assert_eq!(payload, decoded.collect::<Vec<_>>());
+            assert_eq!(report.corrected, 0);
+        }
+    }
+
+    #[test]
+    fn test_const_size() {
+        // Fits const sized buffers, e.g. the receive buffer of the transport reader
+        const SIZE: usize = Convolutional::<8>::get_encode_const_size(8);
+        let mut buffer = [0u8; SIZE];
+        let codec = Convolutional::<8>::default();
+        assert_eq!(codec.encode_into(&[0xa5; 8], &mut buffer).unwrap(), SIZE);
+        assert!(codec.encode_into(&[0xa5; 9], &mut [0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_scattered_bit_flips() {
+        let codec = Convolutional::<8>::default();
+        let payload = [0x12u8, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        // One broken bit in every three bytes
+        for byte in (0..encoded.len()).step_by(3) {
+            encoded[byte] ^= 1 << (byte % 8);
+        }
+
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(decoded.collect::<Vec<_>>(), payload);
+        assert_eq!(report.corrected, encoded.len().div_ceil(3));
+    }
+
+    #[test]
+    fn test_short_burst() {
+        let codec = Convolutional::<8>::default();
+        let payload = [0xffu8, 0x00, 0xff, 0x00, 0x55, 0xaa, 0x55, 0xaa];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        // Three bits in a row over the byte boundary
+        encoded[6] ^= 0x03;
+        encoded[7] ^= 0x80;
+
+        let decoded: Vec<_> = codec.decode(&encoded[..]).unwrap().collect();
+        assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn test_erasures() {
+        let codec = Convolutional::<8>::default();
+        let payload = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        // Whole bytes broken, the previous stage of the chain knows which
+        encoded[3] ^= 0xff;
+        encoded[10] ^= 0xff;
+
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[3, 10]).unwrap();
+        assert_eq!(decoded.collect::<Vec<_>>(), payload);
+        assert_eq!(report.corrected, 0);
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        let codec = Convolutional::<8>::default();
+        assert!(codec.decode(&[0u8; 16]).is_err());
+        assert!(codec.decode(&[0u8; 19]).is_err());
+        assert!(codec.decode_into(&[0u8; 17], &mut [0u8; 7]).is_err());
+        assert!(codec.decode(&[]).is_err());
+    }
+
+    #[test]
+    fn test_chain_with_reed_solomon() {
+        // Viterbi fixes scattered bits, Reed-Solomon the bytes it leaves broken
+        let codec = Chain2::<ReedSolomon<4, 8>, Convolutional<12>, 8>::default();
+        let mut random = XorShiftRng::new(0xc0ff_ee00);
+
+        for _ in 0..100 {
+            let payload: Vec<u8> = (0..8).map(|_| random.next_u32() as u8).collect();
+            let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+            assert_eq!(encoded.len(), 25);
+
+            let bit = random.next_u32() as usize % (encoded.len() * 8);
+            encoded[bit / 8] ^= 1 << (bit % 8);
+
+            let decoded: Vec<_> = codec.decode(&encoded[..]).unwrap().collect();
+            assert_eq!(decoded, payload);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use heapless::Vec;
+
+use crate::four_to_six::FourToSixBits;
+use crate::lzss::LzssCompression;
+use crate::reed_solomon::ReedSolomon;
+use crate::{copy_into, write_vec, Codec, CodecError, CodecSize, DecodeReport, Identity};
+
+/// Biggest data going in or out of any stage of the dynamic codec
+pub const DYNAMIC_BUFFER_SIZE: usize = 128;
+
+/// How many codecs can be chained at runtime
+pub const MAX_CHAIN_STAGES: usize = 4;
+
+/// Codecs the dynamic codec can use, ids are stable so they can be stored in config or sent
+#[derive(defmt::Format, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecKind {
+    Identity,
+    ReedSolomon4,
+    ReedSolomon8,
+    FourToSix,
+    Lzss,
+}
+
+impl CodecKind {
+    pub const ALL: [CodecKind; 5] = [
+        CodecKind::Identity,
+        CodecKind::ReedSolomon4,
+        CodecKind::ReedSolomon8,
+        CodecKind::FourToSix,
+        CodecKind::Lzss,
+    ];
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+}
+
+/// Codec selected at runtime, e.g. from configuration or from a received header.
+///
+/// Static codecs are better when the codec is known at compile time, this one
+/// copies data between stages and its encode size is always `DYNAMIC_BUFFER_SIZE`,
+/// so readers have to return frames shorter than the buffer.
+/// Data which doesn't fit into the buffer is an error, not a panic.
+pub enum DynamicCodec {
+    Identity(Identity),
+    ReedSolomon4(ReedSolomon<4, DYNAMIC_BUFFER_SIZE>),
+    ReedSolomon8(ReedSolomon<8, DYNAMIC_BUFFER_SIZE>),
+    FourToSix(FourToSixBits<DYNAMIC_BUFFER_SIZE>),
+    Lzss(LzssCompression),
+    /// Stages are encoded in order and decoded in reverse, like `chain::Chain`
+    Chain(Vec<CodecKind, MAX_CHAIN_STAGES>),
+}
+
+impl DynamicCodec {
+    /// Returns `None` when there are more than `MAX_CHAIN_STAGES` stages
+    pub fn chain(stages: &[CodecKind]) -> Option<Self> {
+        Vec::from_slice(stages).ok().map(DynamicCodec::Chain)
+    }
+
+    /// Create codec from ids of `CodecKind`, more ids make a chain
+    pub fn from_ids(ids: &[u8]) -> Option<Self> {
+        let mut stages = Vec::<CodecKind, MAX_CHAIN_STAGES>::new();
+        for id in ids {
+            stages.push(CodecKind::from_id(*id)?).ok()?;
+        }
+
+        match stages[..] {
+            [kind] => Some(kind.into()),
+            _ => Some(DynamicCodec::Chain(stages)),
+        }
+    }
+}
+
+impl Default for DynamicCodec {
+    fn default() -> Self {
+        DynamicCodec::Identity(Identity::default())
+    }
+}
+
+impl From<CodecKind> for DynamicCodec {
+    fn from(kind: CodecKind) -> Self {
+        match kind {
+            CodecKind::Identity => DynamicCodec::Identity(Identity::default()),
+            CodecKind::ReedSolomon4 => DynamicCodec::ReedSolomon4(ReedSolomon::default()),
+            CodecKind::ReedSolomon8 => DynamicCodec::ReedSolomon8(ReedSolomon::default()),
+            CodecKind::FourToSix => DynamicCodec::FourToSix(FourToSixBits::default()),
+            CodecKind::Lzss => DynamicCodec::Lzss(LzssCompression::default()),
+        }
+    }
+}
+
+fn encode_stage<C: Codec>(
+    codec: &C,
+    payload: &[u8],
+    output: &mut [u8],
+) -> Result<usize, CodecError> {
+    // Static codecs panic when the payload is bigger than their buffers
+    if payload.len() > DYNAMIC_BUFFER_SIZE
+        || C::get_encode_size(payload.len()) > DYNAMIC_BUFFER_SIZE
+    {
+        return Err(CodecError::EncodeError);
+    }
+
+    let output_size = output.len().min(DYNAMIC_BUFFER_SIZE);
+    codec.encode_into(payload, &mut output[..output_size])
+}
+
+/// Every stage reads one buffer and writes the other one
+fn encode_chain(
+    stages: &[CodecKind],
+    payload: &[u8],
+    output: &mut [u8],
+) -> Result<usize, CodecError> {
+    let mut buffers = [[0u8; DYNAMIC_BUFFER_SIZE]; 2];
+    let mut size = copy_into(payload, &mut buffers[0]).ok_or(CodecError::EncodeError)?;
+
+    for (index, stage) in stages.iter().enumerate() {
+        let [even, odd] = &mut buffers;
+        let (input, encoded) = if index % 2 == 0 {
+            (even, odd)
+        } else {
+            (odd, even)
+        };
+        size = DynamicCodec::from(*stage).encode_into(&input[..size], encoded)?;
+    }
+
+    copy_into(&buffers[stages.len() % 2][..size], output).ok_or(CodecError::EncodeError)
+}
+
+/// Erasures of every stage are passed to the next one
+fn decode_chain(
+    stages: &[CodecKind],
+    payload: &[u8],
+    erasures: &[u8],
+    output: &mut [u8],
+) -> Result<(usize, DecodeReport), CodecError> {
+    let mut buffers = [[0u8; DYNAMIC_BUFFER_SIZE]; 2];
+    let mut size = copy_into(payload, &mut buffers[0]).ok_or(CodecError::DecodeError)?;
+    let mut report = DecodeReport::default();
+    for erasure in erasures {
+        report.add_erasure(*erasure);
+    }
+
+    let mut corrected = 0usize;
+    for (index, stage) in stages.iter().rev().enumerate() {
+        let [even, odd] = &mut buffers;
+        let (input, decoded) = if index % 2 == 0 {
+            (even, odd)
+        } else {
+            (odd, even)
+        };
+        (size, report) = DynamicCodec::from(*stage).decode_into_with_report(
+            &input[..size],
+            report.erasures(),
+            decoded,
+        )?;
+        corrected += report.corrected;
+    }
+
+    report.corrected = corrected;
+    let size =
+        copy_into(&buffers[stages.len() % 2][..size], output).ok_or(CodecError::DecodeError)?;
+    Ok((size, report))
+}
+
+impl Codec for DynamicCodec {
+    type Encoded<'a> = impl Iterator<Item = u8> + 'a;
+    type Decoded<'a> = impl Iterator<Item = u8> + 'a;
+
+    fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
+        let (encoded, _) = write_vec::<_, DYNAMIC_BUFFER_SIZE>(|output| {
+            Ok((self.encode_into(payload, output)?, ()))
+        })?;
+        Ok(encoded.into_iter())
+    }
+
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = write_vec::<_, DYNAMIC_BUFFER_SIZE>(|output| {
+            self.decode_into_with_report(payload, &[], output)
+        })?;
+        Ok(decoded.into_iter())
+    }
+
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = write_vec::<_, DYNAMIC_BUFFER_SIZE>(|output| {
+            self.decode_into_with_report(payload, erasures, output)
+        })?;
+        Ok((decoded.into_iter(), report))
+    }
+
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        match self {
+            DynamicCodec::Identity(codec) => encode_stage(codec, payload, output),
+            DynamicCodec::ReedSolomon4(codec) => encode_stage(codec, payload, output),
+            DynamicCodec::ReedSolomon8(codec) => encode_stage(codec, payload, output),
+            DynamicCodec::FourToSix(codec) => encode_stage(codec, payload, output),
+            DynamicCodec::Lzss(codec) => encode_stage(codec, payload, output),
+            DynamicCodec::Chain(stages) => encode_chain(stages, payload, output),
+        }
+    }
+
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        if payload.len() > DYNAMIC_BUFFER_SIZE {
+            return Err(CodecError::DecodeError);
+        }
+
+        let output_size = output.len().min(DYNAMIC_BUFFER_SIZE);
+        let output = &mut output[..output_size];
+        match self {
+            DynamicCodec::Identity(codec) => {
+                codec.decode_into_with_report(payload, erasures, output)
+            }
+            DynamicCodec::ReedSolomon4(codec) => {
+                codec.decode_into_with_report(payload, erasures, output)
+            }
+            DynamicCodec::ReedSolomon8(codec) => {
+                codec.decode_into_with_report(payload, erasures, output)
+            }
+            DynamicCodec::FourToSix(codec) => {
+                codec.decode_into_with_report(payload, erasures, output)
+            }
+            DynamicCodec::Lzss(codec) => codec.decode_into_with_report(payload, erasures, output),
+            DynamicCodec::Chain(stages) => decode_chain(stages, payload, erasures, output),
+        }
+    }
+
+    fn get_encode_size(payload_size: usize) -> usize {
+        Self::get_encode_const_size(payload_size)
+    }
+}
+
+impl const CodecSize for DynamicCodec {
+    /// Codec is not known at compile time, so this is the biggest size any codec can produce
+    fn get_encode_const_size(_payload_size: usize) -> usize {
+        DYNAMIC_BUFFER_SIZE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chain::Chain2;
+    use std::vec::Vec;
+
+    fn roundtrip(codec: &DynamicCodec, payload: &[u8]) -> Vec<u8> {
+        let encoded: Vec<_> = codec
+            .encode(payload)
+            .expect("There should be no error")
+            .collect();
+
+        let decoded: Vec<_> = codec
+            .decode(&encoded[..])
+            .expect("There should be no error")
+            .collect();
+        assert_eq!(payload, &decoded[..]);
+        encoded
+    }
+
+    #[test]
+    fn test_encode_decode_every_kind() {
+        let payload = vec![1u8, 1, 1, 1, 1, 1, 7, 8, 9, 10];
+        for kind in CodecKind::ALL {
+            assert_eq!(CodecKind::from_id(kind.id()), Some(kind));
+            roundtrip(&DynamicCodec::from(kind), &payload[..]);
+        }
+    }
+
+    #[test]
+    fn test_chain_same_as_static() {
+        let payload = vec![1u8, 2, 3, 4];
+        let codec =
+            DynamicCodec::from_ids(&[CodecKind::ReedSolomon4.id(), CodecKind::FourToSix.id()])
+                .unwrap();
+
+        let encoded = roundtrip(&codec, &payload[..]);
+        let static_codec = Chain2::<ReedSolomon<4, 4>, FourToSixBits<16>, 4>::default();
+        let static_encoded: Vec<_> = static_codec.encode(&payload[..]).unwrap().collect();
+        assert_eq!(encoded, static_encoded);
+    }
+
+    #[test]
+    fn test_chain_passes_erasures() {
+        let codec = DynamicCodec::chain(&[CodecKind::ReedSolomon4, CodecKind::FourToSix]).unwrap();
+        let payload = vec![1u8, 2, 3, 4];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        // Same as for the static chain, too many errors without erasures
+        encoded[0] |= 0x3f;
+        encoded[1] |= 0xf0;
+        encoded[2] |= 0x03;
+        encoded[3] |= 0x3f;
+        encoded[4] |= 0xf0;
+        encoded[5] |= 0x03;
+
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(decoded.collect::<Vec<_>>(), payload);
+        assert!(report.corrected >= 4);
+    }
+
+    #[test]
+    fn test_too_many_stages() {
+        assert!(DynamicCodec::chain(&[CodecKind::Identity; MAX_CHAIN_STAGES]).is_some());
+        assert!(DynamicCodec::chain(&[CodecKind::Identity; MAX_CHAIN_STAGES + 1]).is_none());
+        assert!(DynamicCodec::from_ids(&[CodecKind::ALL.len() as u8]).is_none());
+    }
+
+    #[test]
+    fn test_too_big_data_is_error() {
+        let payload = [0x55u8; DYNAMIC_BUFFER_SIZE];
+        assert!(DynamicCodec::from(CodecKind::Identity)
+            .encode(&payload[..])
+            .is_ok());
+        assert!(DynamicCodec::from(CodecKind::ReedSolomon8)
+            .encode(&payload[..])
+            .is_err());
+        assert!(DynamicCodec::from(CodecKind::FourToSix)
+            .encode(&payload[..])
+            .is_err());
+
+        let too_big = [0x55u8; DYNAMIC_BUFFER_SIZE + 1];
+        assert!(DynamicCodec::default().decode(&too_big[..]).is_err());
+        // Shorter than the ecc
+        assert!(DynamicCodec::from(CodecKind::ReedSolomon8)
+            .decode(&[1u8, 2, 3])
+            .is_err());
+    }
+}
@@ -1,7 +1,11 @@
 use core::iter::Iterator;
-use itertools::Itertools;
 
-use crate::{Codec, CodecError, CodecSize};
+use crate::{write_vec, Codec, CodecError, CodecSize, DecodeReport};
+
+/// 6 bit symbols with three ones and three zeroes, one for every nibble
+const SYMBOLS: [u8; 16] = [
+    0xd, 0xe, 0x13, 0x15, 0x16, 0x19, 0x1a, 0x1c, 0x23, 0x25, 0x26, 0x29, 0x2a, 0x2c, 0x32, 0x34,
+];
 
 #[derive(Default)]
 pub struct FourToSixBits<const MAX_INPUT_SIZE: usize> {}
@@ -14,134 +18,111 @@ where
     type Decoded<'a> = impl Iterator<Item = u8> + 'a;
 
     fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
-        const SYMBOLS: [u8; 16] = [
-            0xd, 0xe, 0x13, 0x15, 0x16, 0x19, 0x1a, 0x1c, 0x23, 0x25, 0x26, 0x29, 0x2a, 0x2c, 0x32,
-            0x34,
-        ];
-
-        let mut finished = true;
-        let mut prev_value: u32 = 0;
-        let mut prev_bits_used = 0u8;
-
-        let mut data = payload
-            .iter()
-            .copied()
-            .map(|v: u8| {
-                ((SYMBOLS[(v >> 4) as usize] as u16) << 6) | SYMBOLS[(v & 0xf) as usize] as u16
-            })
-            .fold(
-                heapless::Vec::<u8, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>::new(),
-                |mut acc, v: u16| {
-                    let mut value: u32 = v as u32;
-                    let mut bits_used = 12u8;
-
-                    if !finished {
-                        value = (value << prev_bits_used) | prev_value;
-                        bits_used += prev_bits_used;
-                        finished = true;
-                    }
-
-                    // println!("{:#034b} {}", value, bits_used);
-                    while bits_used >= 8 {
-                        let v = (value & 0xff) as u8;
-                        acc.push(v).unwrap();
+        let (encoded, _) =
+            write_vec::<_, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>(|output| {
+                Ok((self.encode_into(payload, output)?, ()))
+            })?;
+        Ok(encoded.into_iter())
+    }
 
-                        value >>= 8;
-                        bits_used -= 8;
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = self.decode_vec(payload, &[])?;
+        Ok(decoded.into_iter())
+    }
 
-                        // println!("{:#034b} {}", value, bits_used);
-                    }
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = self.decode_vec(payload, erasures)?;
+        Ok((decoded.into_iter(), report))
+    }
 
-                    if bits_used > 0 {
-                        // println!("Not finished");
-                        finished = false;
-                        prev_bits_used = bits_used;
-                        prev_value = value;
-                    }
+    /// Symbols of every byte are written as 12 bits, least significant bits first
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > MAX_INPUT_SIZE {
+            return Err(CodecError::EncodeError);
+        }
 
-                    acc
-                },
-            );
+        let mut size = 0usize;
+        let mut value = 0u32;
+        let mut bits_used = 0u8;
+        for byte in payload {
+            let symbols = ((SYMBOLS[(byte >> 4) as usize] as u32) << 6)
+                | SYMBOLS[(byte & 0xf) as usize] as u32;
+            value |= symbols << bits_used;
+            bits_used += 12;
+
+            while bits_used >= 8 {
+                *output.get_mut(size).ok_or(CodecError::EncodeError)? = (value & 0xff) as u8;
+                size += 1;
+                value >>= 8;
+                bits_used -= 8;
+            }
+        }
 
-        if !finished {
-            data.push((prev_value & 0xff) as u8).unwrap();
+        if bits_used > 0 {
+            *output.get_mut(size).ok_or(CodecError::EncodeError)? = (value & 0xff) as u8;
+            size += 1;
         }
 
-        Ok(data.into_iter())
+        Ok(size)
     }
 
-    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
-        const SYMBOLS: [u8; 16] = [
-            0xd, 0xe, 0x13, 0x15, 0x16, 0x19, 0x1a, 0x1c, 0x23, 0x25, 0x26, 0x29, 0x2a, 0x2c, 0x32,
-            0x34,
-        ];
-
-        let mut finished = true;
-        let mut prev_value: u16 = 0;
-        let mut prev_bits_used = 0u8;
-
-        let result = payload
-            .iter()
-            .copied()
-            .fold(
-                heapless::Vec::<u8, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>::new(),
-                |mut acc, v: u8| {
-                    let mut value = v as u16;
-                    let mut bits_used = 8u8;
-
-                    if !finished {
-                        value = (value << prev_bits_used) | prev_value;
-                        bits_used += prev_bits_used;
-                        finished = true;
-                    }
-
-                    // println!("{:#034b} {}", value, bits_used);
-                    while bits_used >= 6 {
-                        let v = (value & 0x3f) as u8;
-                        acc.push(v).unwrap();
+    /// Invalid symbols are replaced with the closest valid one,
+    /// bytes containing them are reported as erasures
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let _ = erasures;
+        if payload.len() > Self::get_encode_const_size(MAX_INPUT_SIZE) {
+            return Err(CodecError::DecodeError);
+        }
 
-                        value >>= 6;
-                        bits_used -= 6;
-                        // println!("{:#034b} {}", value, bits_used);
+        let mut size = 0usize;
+        let mut report = DecodeReport::default();
+
+        let mut value = 0u32;
+        let mut bits_used = 0u8;
+        let mut low_symbol = None;
+        for byte in payload {
+            value |= (*byte as u32) << bits_used;
+            bits_used += 8;
+
+            while bits_used >= 6 {
+                let symbol = (value & 0x3f) as u8;
+                value >>= 6;
+                bits_used -= 6;
+
+                // Only whole pairs are decoded into bytes
+                let low = match low_symbol.take() {
+                    None => {
+                        low_symbol = Some(symbol);
+                        continue;
                     }
-
-                    if bits_used > 0 {
-                        finished = false;
-                        prev_bits_used = bits_used;
-                        prev_value = value;
+                    Some(low) => low,
+                };
+
+                let (low, low_valid) = Self::decode_symbol(low);
+                let (high, high_valid) = Self::decode_symbol(symbol);
+                let invalid = (!low_valid) as usize + (!high_valid) as usize;
+                if invalid > 0 {
+                    report.corrected += invalid;
+                    if let Ok(position) = u8::try_from(size) {
+                        report.add_erasure(position);
                     }
+                }
+
+                *output.get_mut(size).ok_or(CodecError::DecodeError)? = (high << 4) | low;
+                size += 1;
+            }
+        }
 
-                    acc
-                },
-            )
-            .into_iter()
-            .tuples::<(u8, u8)>()
-            .map(|(i1, i2): (u8, u8)| {
-                let a = SYMBOLS.iter().position(|&s| s == i1).unwrap_or_else(|| {
-                    SYMBOLS
-                        .iter()
-                        .enumerate()
-                        .map(|(pos, &value)| (pos, (value ^ i1).count_ones()))
-                        .min_by(|a, b| a.1.cmp(&b.1))
-                        .take()
-                        .unwrap()
-                        .0
-                }) as u8;
-                let b = SYMBOLS.iter().position(|&s| s == i2).unwrap_or_else(|| {
-                    SYMBOLS
-                        .iter()
-                        .enumerate()
-                        .map(|(pos, &value)| (pos, (value ^ i2).count_ones()))
-                        .min_by(|a, b| a.1.cmp(&b.1))
-                        .take()
-                        .unwrap()
-                        .0
-                }) as u8;
-
-                (b << 4) | (a & 0xf)
-            });
-
-        Ok(result)
+        Ok((size, report))
     }
 
     fn get_encode_size(payload_size: usize) -> usize {
@@ -149,6 +130,42 @@ where
     }
 }
 
+impl<const MAX_INPUT_SIZE: usize> FourToSixBits<MAX_INPUT_SIZE>
+where
+    [(); Self::get_encode_const_size(MAX_INPUT_SIZE)]: Sized,
+{
+    /// Every byte has fewer bits than its symbols, so the output always fits into the encode buffer
+    fn decode_vec(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+    ) -> Result<
+        (
+            heapless::Vec<u8, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>,
+            DecodeReport,
+        ),
+        CodecError,
+    > {
+        write_vec(|output| self.decode_into_with_report(payload, erasures, output))
+    }
+
+    /// Nibble of the symbol, or of the closest valid one when the symbol is invalid
+    fn decode_symbol(symbol: u8) -> (u8, bool) {
+        match SYMBOLS.iter().position(|&s| s == symbol) {
+            Some(nibble) => (nibble as u8, true),
+            None => {
+                let nibble = SYMBOLS
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, &valid)| (valid ^ symbol).count_ones())
+                    .map(|(nibble, _)| nibble)
+                    .expect("There is always some symbol");
+                (nibble as u8, false)
+            }
+        }
+    }
+}
+
 impl<const MAX_INPUT_SIZE: usize> const CodecSize for FourToSixBits<MAX_INPUT_SIZE> {
     fn get_encode_const_size(payload_size: usize) -> usize {
         debug_assert!(payload_size <= MAX_INPUT_SIZE);
@@ -179,4 +196,47 @@ mod test {
             .collect();
         assert_eq!(payload, decoded);
     }
+
+    #[test]
+    fn test_report_invalid_symbols() {
+        let codec = FourToSixBits::<3>::default();
+        let payload = vec![1u8, 2, 3];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        let (_, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(report.corrected, 0);
+        assert!(report.erasures().is_empty());
+
+        // Symbols of the second byte start at bit 12, all ones is never valid
+        encoded[1] |= 0xf0;
+        encoded[2] |= 0x03;
+        let (_, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(report.corrected, 1);
+        assert_eq!(report.erasures(), &[1]);
+    }
+
+    #[test]
+    fn test_encode_decode_into() {
+        let codec = FourToSixBits::<3>::default();
+        let payload = [1u8, 2, 3];
+        let mut encoded = [0u8; 6];
+        let size = codec.encode_into(&payload, &mut encoded).unwrap();
+        assert_eq!(
+            &encoded[..size],
+            &codec.encode(&payload).unwrap().collect::<Vec<_>>()[..]
+        );
+        assert!(codec
+            .encode_into(&payload, &mut encoded[..size - 1])
+            .is_err());
+
+        let mut decoded = [0u8; 3];
+        assert_eq!(
+            codec.decode_into(&encoded[..size], &mut decoded).unwrap(),
+            3
+        );
+        assert_eq!(decoded, payload);
+        assert!(codec
+            .decode_into(&encoded[..size], &mut decoded[..2])
+            .is_err());
+    }
 }
@@ -0,0 +1,289 @@
+use labrador_ldpc::LDPCCode;
+
+use crate::{write_vec, Codec, CodecError, CodecSize, DecodeReport};
+
+const CODE: LDPCCode = LDPCCode::TC256;
+/// Data bytes of one TC256 block
+const BLOCK_DATA_SIZE: usize = 16;
+/// Encoded bytes of one TC256 block, data and parity
+const BLOCK_SIZE: usize = 32;
+/// Bit flipping decoder counts votes for every bit of the block
+const WORKING_SIZE: usize = BLOCK_SIZE * 8;
+const MAX_ITERATIONS: usize = 40;
+/// Payload is prefixed with its length, blocks are padded with zeroes
+const LENGTH_SIZE: usize = 1;
+
+/// LDPC code TC256 (CCSDS telecommand), every 16 bytes of data are sent as 32 bytes.
+///
+/// Decoding is hard decision bit flipping, the physical layer gives us only bits.
+/// Bursts breaking whole bytes are left to Reed-Solomon.
+#[derive(Default)]
+pub struct Ldpc<const MAX_INPUT_SIZE: usize> {}
+
+impl<const MAX_INPUT_SIZE: usize> Ldpc<MAX_INPUT_SIZE> {
+    /// Decode one block, returns data bytes and how many bytes were corrected
+    fn decode_block(
+        block: &[u8],
+        working: &mut [u8; WORKING_SIZE],
+    ) -> Result<([u8; BLOCK_SIZE], usize), CodecError> {
+        let mut decoded = [0u8; BLOCK_SIZE];
+        let (success, _) = CODE.decode_bf(block, &mut decoded, working, MAX_ITERATIONS);
+        if !success {
+            return Err(CodecError::DecodeError);
+        }
+
+        let corrected = block
+            .iter()
+            .zip(decoded.iter())
+            .filter(|(received, decoded)| received != decoded)
+            .count();
+        Ok((decoded, corrected))
+    }
+}
+
+impl<const MAX_INPUT_SIZE: usize> Codec for Ldpc<MAX_INPUT_SIZE>
+where
+    [(); Self::get_encode_const_size(MAX_INPUT_SIZE)]: Sized,
+{
+    type Encoded<'a> = impl Iterator<Item = u8> + 'a;
+    type Decoded<'a> = impl Iterator<Item = u8> + 'a;
+
+    fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
+        let (encoded, _) =
+            write_vec::<_, { Self::get_encode_const_size(MAX_INPUT_SIZE) }>(|output| {
+                Ok((self.encode_into(payload, output)?, ()))
+            })?;
+        Ok(encoded.into_iter())
+    }
+
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = write_vec::<_, MAX_INPUT_SIZE>(|output| {
+            self.decode_into_with_report(payload, &[], output)
+        })?;
+        Ok(decoded.into_iter())
+    }
+
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = write_vec::<_, MAX_INPUT_SIZE>(|output| {
+            self.decode_into_with_report(payload, erasures, output)
+        })?;
+        Ok((decoded.into_iter(), report))
+    }
+
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > MAX_INPUT_SIZE || payload.len() > u8::MAX as usize {
+            return Err(CodecError::EncodeError);
+        }
+
+        let size = Self::get_encode_const_size(payload.len());
+        let output = output.get_mut(..size).ok_or(CodecError::EncodeError)?;
+
+        let mut data = core::iter::once(payload.len() as u8).chain(payload.iter().copied());
+        let mut block_data = [0u8; BLOCK_DATA_SIZE];
+        for block in output.chunks_exact_mut(BLOCK_SIZE) {
+            for byte in block_data.iter_mut() {
+                *byte = data.next().unwrap_or(0);
+            }
+            CODE.copy_encode(&block_data, block);
+        }
+
+        Ok(size)
+    }
+
+    /// Corrected symbols are bytes of the blocks, erasures are not used
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let _ = erasures;
+        if payload.is_empty()
+            || payload.len() % BLOCK_SIZE != 0
+            || payload.len() > Self::get_encode_const_size(MAX_INPUT_SIZE)
+        {
+            return Err(CodecError::DecodeError);
+        }
+
+        let mut working = [0u8; WORKING_SIZE];
+        let mut report = DecodeReport::default();
+        let mut length = 0usize;
+        let mut size = 0usize;
+        for (index, block) in payload.chunks_exact(BLOCK_SIZE).enumerate() {
+            let (decoded, corrected) = Self::decode_block(block, &mut working)?;
+            report.corrected += corrected;
+
+            let mut data = &decoded[..BLOCK_DATA_SIZE];
+            if index == 0 {
+                // Length has to match the number of received blocks
+                length = data[0] as usize;
+                if length > MAX_INPUT_SIZE || Self::get_encode_const_size(length) != payload.len() {
+                    return Err(CodecError::DecodeError);
+                }
+                data = &data[LENGTH_SIZE..];
+            }
+
+            let data = &data[..data.len().min(length - size)];
+            output
+                .get_mut(size..size + data.len())
+                .ok_or(CodecError::DecodeError)?
+                .copy_from_slice(data);
+            size += data.len();
+        }
+
+        Ok((size, report))
+    }
+
+    fn get_encode_size(payload_size: usize) -> usize {
+        Self::get_encode_const_size(payload_size)
+    }
+}
+
+impl<const MAX_INPUT_SIZE: usize> const CodecSize for Ldpc<MAX_INPUT_SIZE> {
+    fn get_encode_const_size(payload_size: usize) -> usize {
+        debug_assert!(payload_size <= MAX_INPUT_SIZE);
+
+        (payload_size + LENGTH_SIZE).div_ceil(BLOCK_DATA_SIZE) * BLOCK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reed_solomon::ReedSolomon;
+    use simulated_channel::{NoiseConfig, NoiseModel, XorShiftRng};
+    use std::vec::Vec;
+
+    #[test]
+    fn test_encode_decode() {
+        let codec = Ldpc::<40>::default();
+        for size in [0usize, 1, 15, 16, 31, 40] {
+            let payload: Vec<u8> = (0..size as u8).collect();
+
+            let encoded: Vec<_> = codec
+                .encode(&payload[..])
+                .expect("There should be no error")
+                .collect();
+            assert_eq!(encoded.len(), Ldpc::<40>::get_encode_size(size));
+            assert_eq!(&encoded[1..=size.min(15)], &payload[..size.min(15)]);
+
+            let decoded: Vec<_> = codec
+                .decode(&encoded[..])
+                .expect("There should be no error")
+                .collect();
+            assert_eq!(payload, decoded);
+        }
+    }
+
+    #[test]
+    fn test_bit_flips() {
+        let codec = Ldpc::<8>::default();
+        let payload = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut encoded = [0u8; 32];
+        assert_eq!(codec.encode_into(&payload, &mut encoded).unwrap(), 32);
+
+        let (_, report) = codec.decode_with_report(&encoded, &[]).unwrap();
+        assert_eq!(report.corrected, 0);
+
+        // Single bits in data and in parity
+        encoded[3] ^= 0x10;
+        encoded[25] ^= 0x01;
+        let mut decoded = [0u8; 8];
+        let (size, report) = codec
+            .decode_into_with_report(&encoded, &[], &mut decoded)
+            .unwrap();
+        assert_eq!(&decoded[..size], &payload);
+        assert_eq!(report.corrected, 2);
+    }
+
+    #[test]
+    fn test_wrong_size() {
+        let codec = Ldpc::<20>::default();
+        assert!(codec.encode(&[0u8; 21]).is_err());
+        assert!(codec.encode_into(&[0u8; 20], &mut [0u8; 63]).is_err());
+
+        let encoded: Vec<_> = codec.encode(&[7u8; 20]).unwrap().collect();
+        assert!(codec.decode(&encoded[..32]).is_err());
+        assert!(codec.decode(&encoded[..63]).is_err());
+        assert!(codec.decode(&[]).is_err());
+        assert!(codec.decode_into(&encoded, &mut [0u8; 19]).is_err());
+    }
+
+    const FRAMES: usize = 200;
+
+    /// Frames decoded correctly after every bit of them was flipped with the given probability
+    fn recovered_frames<C: Codec>(codec: &C, bit_error_rate: f64, seed: u64) -> usize {
+        let mut random = XorShiftRng::new(seed);
+        let mut noise = NoiseModel::new(NoiseConfig::with_bit_flips(bit_error_rate), seed);
+        let mut recovered = 0usize;
+
+        for _ in 0..FRAMES {
+            let payload: Vec<u8> = (0..15).map(|_| random.next_u32() as u8).collect();
+            let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+            noise.corrupt_bytes(&mut encoded);
+
+            let decoded: Option<Vec<_>> = codec
+                .decode(&encoded[..])
+                .ok()
+                .map(|decoded| decoded.collect());
+            if decoded.as_deref() == Some(&payload[..]) {
+                recovered += 1;
+            }
+        }
+        recovered
+    }
+
+    #[test]
+    fn test_compare_with_reed_solomon() {
+        // Both send 15 bytes of payload in 32 bytes
+        let ldpc = Ldpc::<15>::default();
+        let reed_solomon = ReedSolomon::<17, 15>::default();
+        assert_eq!(Ldpc::<15>::get_encode_size(15), 32);
+        assert_eq!(ReedSolomon::<17, 15>::get_encode_size(15), 32);
+
+        // Hard decision bit flipping gives up sooner than Reed-Solomon on random bit errors
+        // (bit error rate, seed, least frames recovered by LDPC, by Reed-Solomon)
+        for (bit_error_rate, seed, ldpc_minimum, reed_solomon_minimum) in [
+            (0.002, 0x1d9c, FRAMES * 95 / 100, FRAMES * 95 / 100),
+            (0.01, 0x2d9c, FRAMES * 80 / 100, FRAMES * 95 / 100),
+            (0.03, 0x3d9c, FRAMES * 40 / 100, FRAMES * 65 / 100),
+        ] {
+            let ldpc_recovered = recovered_frames(&ldpc, bit_error_rate, seed);
+            let reed_solomon_recovered = recovered_frames(&reed_solomon, bit_error_rate, seed);
+
+            assert!(ldpc_recovered >= ldpc_minimum);
+            assert!(reed_solomon_recovered >= reed_solomon_minimum);
+            assert!(reed_solomon_recovered >= ldpc_recovered);
+        }
+    }
+
+    #[test]
+    fn test_burst_reed_solomon_wins() {
+        let ldpc = Ldpc::<15>::default();
+        let reed_solomon = ReedSolomon::<17, 15>::default();
+        let payload = [0x5au8; 15];
+
+        // Whole bytes broken in a row, the way interference breaks the signal
+        let burst = |mut encoded: Vec<u8>| {
+            for byte in encoded[4..8].iter_mut() {
+                *byte ^= 0xff;
+            }
+            encoded
+        };
+
+        let encoded = burst(reed_solomon.encode(&payload).unwrap().collect());
+        let decoded: Vec<_> = reed_solomon.decode(&encoded[..]).unwrap().collect();
+        assert_eq!(decoded, payload);
+
+        let encoded = burst(ldpc.encode(&payload).unwrap().collect());
+        let decoded: Option<Vec<_>> = ldpc
+            .decode(&encoded[..])
+            .ok()
+            .map(|decoded| decoded.collect());
+        assert_ne!(decoded.as_deref(), Some(&payload[..]));
+    }
+}
@@ -15,8 +15,11 @@ extern crate std;
 
 use defmt::Format;
 
-pub mod chain; // TODO, don't know what to do with this
+pub mod chain;
+pub mod convolutional;
+pub mod dynamic;
 pub mod four_to_six;
+pub mod ldpc;
 pub mod lzss;
 pub mod reed_solomon;
 
@@ -26,6 +29,47 @@ pub enum CodecError {
     DecodeError,
 }
 
+/// How many erasure positions can one decoder pass to the next one
+pub const MAX_ERASURES: usize = 16;
+
+/// What the decoder had to fix to decode the payload
+#[derive(Format, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeReport {
+    /// Symbols corrected by the decoder
+    pub corrected: usize,
+    erasures: [u8; MAX_ERASURES],
+    erasure_count: usize,
+}
+
+impl DecodeReport {
+    pub fn corrected(corrected: usize) -> Self {
+        Self {
+            corrected,
+            ..Self::default()
+        }
+    }
+
+    /// Positions in the decoded data which are not reliable,
+    /// the next decoder in the chain can use them as erasures
+    pub fn erasures(&self) -> &[u8] {
+        &self.erasures[..self.erasure_count]
+    }
+
+    /// Returns `false` when there is no space for another erasure
+    pub fn add_erasure(&mut self, position: u8) -> bool {
+        if self.erasures().contains(&position) {
+            return true;
+        }
+        if self.erasure_count >= MAX_ERASURES {
+            return false;
+        }
+
+        self.erasures[self.erasure_count] = position;
+        self.erasure_count += 1;
+        true
+    }
+}
+
 #[const_trait]
 pub trait CodecSize {
     // TODO this method should return number bigger or equal to runtime size version of this function
@@ -33,15 +77,85 @@ pub trait CodecSize {
 }
 
 pub trait Codec: CodecSize {
-    type Encoded<'a>: Iterator<Item = u8> + 'a;
-    type Decoded<'a>: Iterator<Item = u8> + 'a;
+    type Encoded<'a>: Iterator<Item = u8> + 'a
+    where
+        Self: 'a;
+    type Decoded<'a>: Iterator<Item = u8> + 'a
+    where
+        Self: 'a;
 
     fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError>;
     fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError>;
 
+    /// Decode and report what had to be corrected. `erasures` are positions of payload
+    /// bytes known to be broken, e.g. reported by the previous decoder in the chain.
+    /// Codecs without error correction ignore them and report nothing.
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let _ = erasures;
+        Ok((self.decode(payload)?, DecodeReport::default()))
+    }
+
+    /// Encode into the caller buffer and return the written length, too small buffer is an error.
+    /// Codecs of this crate write directly into it, without buffers of their own.
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        write_into(self.encode(payload)?, output).ok_or(CodecError::EncodeError)
+    }
+
+    /// Decode into the caller buffer and return the written length
+    fn decode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        let (size, _) = self.decode_into_with_report(payload, &[], output)?;
+        Ok(size)
+    }
+
+    /// `decode_with_report` into the caller buffer
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let (decoded, report) = self.decode_with_report(payload, erasures)?;
+        let size = write_into(decoded, output).ok_or(CodecError::DecodeError)?;
+        Ok((size, report))
+    }
+
     fn get_encode_size(payload_size: usize) -> usize;
 }
 
+/// Write data into the buffer, `None` when it doesn't fit instead of a panic
+fn write_into<I: Iterator<Item = u8>>(data: I, output: &mut [u8]) -> Option<usize> {
+    let mut size = 0usize;
+    for byte in data {
+        *output.get_mut(size)? = byte;
+        size += 1;
+    }
+    Some(size)
+}
+
+/// Copy data into the beginning of the buffer, `None` when it doesn't fit
+pub(crate) fn copy_into(data: &[u8], output: &mut [u8]) -> Option<usize> {
+    output.get_mut(..data.len())?.copy_from_slice(data);
+    Some(data.len())
+}
+
+/// Iterator API on top of the buffer one, `write` gets the whole buffer
+/// and returns the written length
+pub fn write_vec<T, const SIZE: usize>(
+    write: impl FnOnce(&mut [u8]) -> Result<(usize, T), CodecError>,
+) -> Result<(heapless::Vec<u8, SIZE>, T), CodecError> {
+    let mut buffer = heapless::Vec::new();
+    buffer
+        .resize_default(SIZE)
+        .expect("Buffer has exactly SIZE bytes");
+    let (size, value) = write(&mut buffer[..])?;
+    buffer.truncate(size);
+    Ok((buffer, value))
+}
+
 #[derive(Default)]
 pub struct Identity {}
 
@@ -57,6 +171,21 @@ impl Codec for Identity {
         Ok(payload.iter().copied())
     }
 
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        copy_into(payload, output).ok_or(CodecError::EncodeError)
+    }
+
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let _ = erasures;
+        let size = copy_into(payload, output).ok_or(CodecError::DecodeError)?;
+        Ok((size, DecodeReport::default()))
+    }
+
     fn get_encode_size(payload_size: usize) -> usize {
         payload_size
     }
@@ -1,12 +1,10 @@
-use crate::{Codec, CodecError, CodecSize};
+use crate::{write_vec, Codec, CodecError, CodecSize, DecodeReport};
 
 type BaseCompression<const EI: usize, const EJ: usize> =
     lzss::Lzss<EI, EJ, 0x20, { 1 << EI }, { 2 << EI }>;
 
 const EI: usize = 6;
-#[allow(dead_code)] // FIXME once the fixme with const is resolved remove this
 const COMPRESSION_BUFFER_SIZE: usize = 2 << EI;
-#[allow(dead_code)] // FIXME once the fixme with const is resolved remove this
 const DECOMPRESSION_BUFFER_SIZE: usize = 1 << EI;
 type Compression = BaseCompression<EI, 3>;
 
@@ -18,45 +16,51 @@ impl Codec for LzssCompression {
     type Decoded<'a> = impl Iterator<Item = u8> + 'a;
 
     fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
-        let mut compressed = [0u8; { COMPRESSION_BUFFER_SIZE }];
-        let compression_result = Compression::compress_stack(
-            lzss::SliceReader::new(payload),
-            lzss::SliceWriter::new(&mut compressed[..]),
-        )
-        .map_err(|_| CodecError::EncodeError)?;
+        let (compressed, _) = write_vec::<_, COMPRESSION_BUFFER_SIZE>(|output| {
+            Ok((self.encode_into(payload, output)?, ()))
+        })?;
+        Ok(compressed.into_iter())
+    }
 
-        // FIXME dont know why that const generic is not resolved.
-        // let output: heapless::Vec<_, COMPRESSION_BUFFER_SIZE> =
-        let output: heapless::Vec<_, 128> =
-            heapless::Vec::from_iter(compressed[..compression_result].iter().copied());
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decompressed, _) = write_vec::<_, DECOMPRESSION_BUFFER_SIZE>(|output| {
+            Ok((self.decode_into(payload, output)?, ()))
+        })?;
+        Ok(decompressed.into_iter())
+    }
 
-        Ok(output.into_iter())
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        Compression::compress_stack(
+            lzss::SliceReader::new(payload),
+            lzss::SliceWriter::new(output),
+        )
+        .map_err(|_| CodecError::EncodeError)
     }
 
-    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
-        let mut decompressed = [0u8; { DECOMPRESSION_BUFFER_SIZE }];
-        let decompression_result = Compression::decompress_stack(
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let _ = erasures;
+        let size = Compression::decompress_stack(
             lzss::SliceReader::new(payload),
-            lzss::SliceWriter::new(&mut decompressed[..]),
+            lzss::SliceWriter::new(output),
         )
         .map_err(|_| CodecError::DecodeError)?;
-
-        // FIXME dont know why that const generic is not resolved.
-        // let mut decompressed: heapless::Vec<_, { DECOMPRESSION_BUFFER_SIZE }> = heapless::Vec::new();
-        let output: heapless::Vec<_, 64> =
-            heapless::Vec::from_iter(decompressed[..decompression_result].iter().copied());
-
-        Ok(output.into_iter())
+        Ok((size, DecodeReport::default()))
     }
 
     fn get_encode_size(payload_size: usize) -> usize {
-        payload_size
+        Self::get_encode_const_size(payload_size)
     }
 }
 
 impl const CodecSize for LzssCompression {
+    /// Data which can't be compressed grows by a flag bit for every byte
     fn get_encode_const_size(payload_size: usize) -> usize {
-        payload_size
+        payload_size + payload_size.div_ceil(8)
     }
 }
 
@@ -125,4 +129,25 @@ mod test {
             .collect();
         assert_eq!(payload, decoded);
     }
+
+    #[test]
+    fn test_encode_decode_into() {
+        let codec = LzssCompression::default();
+        let payload = [1u8, 1, 1, 1, 1, 1, 7, 8, 9, 10];
+        let mut compressed = [0u8; 16];
+        let size = codec.encode_into(&payload, &mut compressed).unwrap();
+        assert!(size < payload.len());
+
+        let mut decompressed = [0u8; 10];
+        assert_eq!(
+            codec
+                .decode_into(&compressed[..size], &mut decompressed)
+                .unwrap(),
+            10
+        );
+        assert_eq!(decompressed, payload);
+        assert!(codec
+            .decode_into(&compressed[..size], &mut decompressed[..9])
+            .is_err());
+    }
 }
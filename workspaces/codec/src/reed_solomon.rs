@@ -1,4 +1,4 @@
-use crate::{Codec, CodecError, CodecSize};
+use crate::{copy_into, write_vec, Codec, CodecError, CodecSize, DecodeReport};
 
 use reed_solomon::{Decoder, Encoder};
 
@@ -13,6 +13,26 @@ impl<const ECC_LEN: usize, const ENCODE_BUFFER_SIZE: usize>
     const DECODE_BUFFER_SIZE: usize = ENCODE_BUFFER_SIZE + ECC_LEN;
 }
 
+impl<const ECC_LEN: usize, const ENCODE_BUFFER_SIZE: usize> ReedSolomon<ECC_LEN, ENCODE_BUFFER_SIZE>
+where
+    [(); Self::DECODE_BUFFER_SIZE]: Sized,
+    [(); Self::get_encode_const_size(ENCODE_BUFFER_SIZE)]: Sized,
+{
+    fn decode_vec(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+    ) -> Result<
+        (
+            heapless::Vec<u8, { Self::DECODE_BUFFER_SIZE }>,
+            DecodeReport,
+        ),
+        CodecError,
+    > {
+        write_vec(|output| self.decode_into_with_report(payload, erasures, output))
+    }
+}
+
 impl<const ECC_LEN: usize, const ENCODE_BUFFER_SIZE: usize> Default
     for ReedSolomon<ECC_LEN, ENCODE_BUFFER_SIZE>
 {
@@ -34,23 +54,61 @@ where
     type Decoded<'a> = impl Iterator<Item = u8> + 'a;
 
     fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
-        let encoded: heapless::Vec<_, { Self::get_encode_const_size(ENCODE_BUFFER_SIZE) }> =
-            self.encoder.encode(payload).iter().copied().collect();
-
+        let (encoded, _) =
+            write_vec::<_, { Self::get_encode_const_size(ENCODE_BUFFER_SIZE) }>(|output| {
+                Ok((self.encode_into(payload, output)?, ()))
+            })?;
         Ok(encoded.into_iter())
     }
 
     fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
-        let decode_buffer = self
+        let (decoded, _) = self.decode_vec(payload, &[])?;
+        Ok(decoded.into_iter())
+    }
+
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = self.decode_vec(payload, erasures)?;
+        Ok((decoded.into_iter(), report))
+    }
+
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > ENCODE_BUFFER_SIZE {
+            return Err(CodecError::EncodeError);
+        }
+
+        let encoded = self.encoder.encode(payload);
+        copy_into(&encoded[..], output).ok_or(CodecError::EncodeError)
+    }
+
+    /// Returns corrected data without the ecc and how many symbols were corrected
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        // Shorter data can't hold the ecc, longer does not fit into the buffer
+        if payload.len() < ECC_LEN || payload.len() > Self::DECODE_BUFFER_SIZE {
+            return Err(CodecError::DecodeError);
+        }
+
+        let erasures = if erasures.is_empty() {
+            None
+        } else {
+            Some(erasures)
+        };
+        let (corrected_buffer, corrected) = self
             .decoder
-            .correct(payload, None)
+            .correct_err_count(payload, erasures)
             .map_err(|_| CodecError::DecodeError)?;
-        let mut decoded: heapless::Vec<_, { Self::DECODE_BUFFER_SIZE }> =
-            decode_buffer.iter().copied().collect();
-        decoded
-            .resize(decoded.len() - ECC_LEN, 0)
-            .expect("This should not fail");
-        Ok(decoded.into_iter())
+
+        let data = &corrected_buffer[..corrected_buffer.len() - ECC_LEN];
+        let size = copy_into(data, output).ok_or(CodecError::DecodeError)?;
+        Ok((size, DecodeReport::corrected(corrected)))
     }
 
     fn get_encode_size(payload_size: usize) -> usize {
@@ -109,4 +167,52 @@ mod test {
             .collect();
         assert_eq!(payload, decoded);
     }
+
+    #[test]
+    fn test_report_corrected() {
+        let codec = ReedSolomon::<4, 4>::default();
+        let payload = vec![1u8, 2, 3, 4];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+
+        let (_, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(report.corrected, 0);
+
+        encoded[0] = 3;
+        encoded[3] = 3;
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(payload, decoded.collect::<Vec<_>>());
+        assert_eq!(report.corrected, 2);
+
+        // Known positions of broken bytes let the decoder fix more of them
+        encoded[1] = 0xff;
+        encoded[2] = 0xff;
+        assert!(codec.decode_with_report(&encoded[..], &[]).is_err());
+        let (decoded, report) = codec
+            .decode_with_report(&encoded[..], &[0, 1, 2, 3])
+            .unwrap();
+        assert_eq!(payload, decoded.collect::<Vec<_>>());
+        assert!(report.corrected > 0);
+    }
+
+    #[test]
+    fn test_encode_decode_into() {
+        let codec = ReedSolomon::<4, 4>::default();
+        let payload = [1u8, 2, 3, 4];
+        let mut encoded = [0u8; 8];
+        assert_eq!(codec.encode_into(&payload, &mut encoded).unwrap(), 8);
+        assert_eq!(
+            &encoded[..],
+            &codec.encode(&payload).unwrap().collect::<Vec<_>>()[..]
+        );
+        assert!(codec.encode_into(&payload, &mut [0u8; 7]).is_err());
+
+        encoded[1] = 0;
+        let mut decoded = [0u8; 4];
+        let (size, report) = codec
+            .decode_into_with_report(&encoded, &[], &mut decoded)
+            .unwrap();
+        assert_eq!(&decoded[..size], &payload);
+        assert_eq!(report.corrected, 1);
+        assert!(codec.decode_into(&encoded, &mut [0u8; 3]).is_err());
+    }
 }
@@ -129,6 +129,11 @@ where
         self.len
     }
 
+    /// Drop all the elements, allocated memory is kept
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
     pub fn capacity(&self) -> usize {
         self.data.size
     }
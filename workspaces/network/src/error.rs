@@ -11,10 +11,42 @@ pub enum NetworkError {
 
     DataConstructingError(DataConstructionError),
     CodecError(CodecError),
+
+    /// The other side did not confirm the message within the retry budget
+    AcknowledgeTimeout,
+    /// Reassembled message does not match its checksum trailer
+    MessageChecksumMismatch,
+
+    BulkTransferError(BulkTransferError),
+
+    /// No free slot in the transmit queue
+    TransmitQueueFull,
+    /// Message does not fit into a slot of the transmit queue
+    MessageTooLarge,
 }
 
 #[derive(Debug, Format)]
 pub enum DataConstructionError {
     FullWindow,
     WrongStreamId,
+    /// Stream was not completed before the reassembly deadline and was dropped
+    StreamTimeout,
+    /// Packet format has no way to mark control packets
+    UnsupportedControlPacket,
+}
+
+#[derive(Debug, Format)]
+pub enum BulkTransferError {
+    /// Message is too short to be a chunk
+    InvalidChunk,
+    /// Chunk belongs to different transfer than the one being received
+    UnexpectedTransfer,
+    /// Some chunks were lost, sender has to resume the transfer from this offset
+    MissingChunk { expected: u32 },
+    /// Message can't hold any data next to the chunk header
+    ChunkTooSmall,
+    /// Data is longer than the chunk header can describe
+    TooLarge,
+    /// Sink could not store the chunk
+    SinkError,
 }
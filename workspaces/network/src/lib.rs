@@ -3,6 +3,7 @@
 #![feature(
     async_fn_in_trait,
     type_alias_impl_trait,
+    impl_trait_in_assoc_type,
     const_trait_impl,
     generic_const_exprs
 )]
@@ -15,30 +16,48 @@ extern crate std;
 #[cfg(test)]
 pub mod tests;
 
-mod packet;
 mod packet_builder;
 
 pub mod error;
+pub mod packet;
 pub mod simple;
 pub mod transport;
 
-#[cfg(not(any(feature = "packet-32", feature = "packet-64")))]
-compile_error!("You need to enable one of the features `packet-32` or `packet-64`");
+use crate::packet::PacketFormat;
 
-#[cfg(all(feature = "packet-32", feature = "packet-64"))]
-compile_error!("You need to enable exactly one of the features `packet-32` or `packet-64`");
+/// How many group addresses can a node listen to
+pub const MAX_GROUP_ADDRESSES: usize = 4;
 
 #[derive(Clone)]
 pub struct Address {
     pub local_address: u8,
     pub destination_address: u8,
+    /// Additional addresses we accept packets for, e.g. all actuators in a room
+    pub group_addresses: heapless::Vec<u8, MAX_GROUP_ADDRESSES>,
 }
 
 impl Address {
+    /// Use `PacketFormat::BROADCAST_ADDRESS` as `destination_address` to reach every node
     pub fn new(local_address: u8, destination_address: u8) -> Self {
         Self {
             local_address,
             destination_address,
+            group_addresses: heapless::Vec::new(),
         }
     }
+
+    /// Returns the group back when there is no space for it
+    pub fn add_group(&mut self, group_address: u8) -> Result<(), u8> {
+        if self.group_addresses.contains(&group_address) {
+            return Ok(());
+        }
+        self.group_addresses.push(group_address)
+    }
+
+    /// Should we receive packet of format `F` with given destination address
+    pub fn accepts<F: PacketFormat>(&self, destination_address: u8) -> bool {
+        destination_address == self.local_address
+            || destination_address == F::BROADCAST_ADDRESS
+            || self.group_addresses.contains(&destination_address)
+    }
 }
@@ -1,22 +1,93 @@
 use bitfield_struct::bitfield;
+use core::fmt::Debug;
+use core::ops::{Deref, DerefMut};
 use defmt::trace;
 
-use sequence_number::SequenceNumber;
-
-#[cfg(feature = "packet-32")]
-pub type PacketType = Packet32;
-#[cfg(feature = "packet-64")]
-pub type PacketType = Packet64;
+use sequence_number::{Sequence, SequenceNumber};
+
+/// Largest size of all the packet formats, use it for buffers in the code generic over the format
+pub const MAX_PACKET_SIZE: usize = 16;
+/// Largest payload of all the packet formats
+pub const MAX_PAYLOAD_SIZE: usize = 11;
+/// Everything the transport layer needs to know about a packet format.
+///
+/// Every format is a bitfield with different field sizes, so the sequence number
+/// and stream id types differ as well.
+pub trait PacketFormat: Copy + PartialEq + Debug + defmt::Format {
+    type SequenceNumber: Sequence;
+    type StreamId: Sequence;
+    /// Packets of one stream, `heapless::Vec` with the capacity of `WINDOW_SIZE`
+    type Packets: PacketBuffer<Self>;
+
+    /// Size of the whole packet in bytes
+    const SIZE: usize;
+    const PAYLOAD_SIZE: usize;
+    /// Every node accepts packets sent to this address
+    const BROADCAST_ADDRESS: u8;
+    /// `payload_used_index` marking control packets (ACK, NACK, ...).
+    /// `None` when the format has no spare value for it.
+    const CONTROL_PAYLOAD_INDEX: Option<u8>;
+    /// Maximum number of packets in one stream
+    const WINDOW_SIZE: usize = <Self::SequenceNumber as Sequence>::MODULO as usize;
+    /// Most bytes one stream can carry. Compression and the message checksum
+    /// are counted in, so the usable size of the message can be smaller.
+    const MAX_MESSAGE_SIZE: usize = Self::PAYLOAD_SIZE * Self::WINDOW_SIZE;
+
+    fn new() -> Self;
+
+    fn kind(&self) -> PacketKind;
+    fn with_kind(self, kind: PacketKind) -> Self;
+
+    fn sequence_number(&self) -> Self::SequenceNumber;
+    fn with_sequence_number(self, sequence_number: Self::SequenceNumber) -> Self;
+
+    fn stream_id(&self) -> Self::StreamId;
+    fn with_stream_id(self, stream_id: Self::StreamId) -> Self;
+
+    fn source_address(&self) -> u8;
+    fn with_source_address(self, address: u8) -> Self;
+
+    fn destination_address(&self) -> u8;
+    fn with_destination_address(self, address: u8) -> Self;
+
+    fn payload_used_index(&self) -> u8;
+    fn with_payload_used_index(self, index: u8) -> Self;
+
+    /// Bytes which don't fit into the payload are ignored
+    fn with_payload_bytes(self, bytes: &[u8]) -> Self;
+    /// Copy the whole payload into the buffer, returns `PAYLOAD_SIZE`
+    fn write_payload(&self, buffer: &mut [u8]) -> usize;
+
+    fn with_updated_crc(self) -> Self;
+    fn validate(&self) -> bool;
+
+    /// Write the packet as little endian bytes, returns `SIZE`
+    fn write_le_bytes(&self, buffer: &mut [u8]) -> usize;
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    fn is_control(&self) -> bool {
+        Self::CONTROL_PAYLOAD_INDEX == Some(self.payload_used_index())
+    }
+}
 
-#[cfg(feature = "packet-32")]
-pub const PACKET_TYPE_SN_SIZE: u8 = 8;
-#[cfg(feature = "packet-32")]
-pub const PACKET_TYPE_STREAM_ID_SIZE: u8 = 4;
+/// CRC-8 (polynomial 0x07), detects every single bit error and bursts up to 8 bits
+fn crc8(bytes: &[u8]) -> u8 {
+    const CRC8_POLYNOMIAL: u8 = 0x07;
+
+    let mut crc = 0u8;
+    for byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ CRC8_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+    }
 
-#[cfg(feature = "packet-64")]
-pub const PACKET_TYPE_SN_SIZE: u8 = 16;
-#[cfg(feature = "packet-64")]
-pub const PACKET_TYPE_STREAM_ID_SIZE: u8 = 8;
+    crc
+}
 
 #[derive(Debug, Eq, PartialEq, Clone, defmt::Format)]
 #[repr(u8)]
@@ -40,6 +111,18 @@ impl From<PacketKind> for u64 {
     }
 }
 
+impl From<u128> for PacketKind {
+    fn from(value: u128) -> Self {
+        (value as u64).into()
+    }
+}
+
+impl From<PacketKind> for u128 {
+    fn from(value: PacketKind) -> Self {
+        value as u128
+    }
+}
+
 impl From<u32> for PacketKind {
     fn from(value: u32) -> Self {
         match value {
@@ -71,39 +154,79 @@ pub struct Packet32 {
     pub kind: PacketKind,
     #[bits(3)] // Up to 8 packets
     pub sequence_number: SequenceNumber<8>,
-    #[bits(2)] // Up to 4 streams
-    pub stream_id: SequenceNumber<4>,
+    #[bits(3)] // Up to 8 streams
+    pub stream_id: SequenceNumber<8>,
 
     #[bits(4)] // Up to 16 devices
     pub source_address: u8,
     #[bits(4)]
     pub destination_address: u8,
 
-    #[bits(16)]
-    pub payload: u16,
+    #[bits(8)] // Single byte, the rest is taken by the CRC
+    pub payload: u8,
 
-    #[bits(1)]
-    pub payload_used_index: u8,
+    #[bits(8)]
+    pub crc8: u8,
 }
 
 impl Packet32 {
+    pub const SIZE: usize = 4;
+    pub const PAYLOAD_SIZE: usize = 1;
+
     pub fn to_le_bytes(self) -> [u8; 4] {
         Into::<u32>::into(self).to_le_bytes()
     }
 
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; Self::SIZE];
+        buffer.copy_from_slice(&bytes[..Self::SIZE]);
+        u32::from_le_bytes(buffer).into()
+    }
+
+    /// Bytes which don't fit into the payload are ignored
+    pub fn with_payload_bytes(self, bytes: &[u8]) -> Self {
+        self.with_payload(bytes.first().copied().unwrap_or(0))
+    }
+
+    pub fn payload_bytes(&self) -> [u8; Self::PAYLOAD_SIZE] {
+        [self.payload()]
+    }
+
+    /// The single payload byte is always used, so the index is not sent
+    pub fn payload_used_index(&self) -> u8 {
+        0
+    }
+
+    pub fn with_payload_used_index(self, _index: u8) -> Self {
+        self
+    }
+
     pub fn to_be_bytes(self) -> [u8; 4] {
         Into::<u32>::into(self).to_be_bytes()
     }
 
-    pub fn update_crc(&mut self) {}
+    /// CRC-8 over everything except the CRC itself
+    pub fn compute_crc8(&self) -> u8 {
+        let value: u32 = self.with_crc8(0x0).into();
+        // CRC is stored in the last byte
+        crc8(&value.to_le_bytes()[..Self::SIZE - 1])
+    }
+
+    pub fn update_crc(&mut self) {
+        self.set_crc8(self.compute_crc8());
+    }
+
+    pub fn with_updated_crc(&self) -> Self {
+        self.with_crc8(self.compute_crc8())
+    }
 
     pub fn validate(&self) -> bool {
-        true
+        self.compute_crc8() == self.crc8()
     }
 
     #[inline]
     pub const fn size() -> usize {
-        4
+        Self::SIZE
     }
 }
 
@@ -115,18 +238,16 @@ impl From<u64> for Packet32 {
 
 impl defmt::Format for Packet32 {
     fn format(&self, fmt: defmt::Formatter) {
-        let payload = self.payload().to_le_bytes();
         defmt::write!(
             fmt,
-            "Packet32 {{ kind: {:?}, sequence_number: {}, stream_id: {}, source_address: {:#04x}, destination_address: {:#04x}, payload: [{:#04x}, {:#04x}], payload_used_index: {} }}",
+            "Packet32 {{ kind: {:?}, sequence_number: {}, stream_id: {}, source_address: {:#04x}, destination_address: {:#04x}, payload: [{:#04x}], crc8: {:#04x} }}",
             self.kind(),
             self.sequence_number(),
             self.stream_id(),
             self.source_address(),
             self.destination_address(),
-            payload[0],
-            payload[1],
-            self.payload_used_index(),
+            self.payload(),
+            self.crc8(),
         )
     }
 }
@@ -156,7 +277,34 @@ pub struct Packet64 {
     pub crc4: u8,
 }
 
+/// Data packets use `payload_used_index` only in range `0..=4`.
+/// The highest value marks control packets (ACK, NACK, ...).
+pub const PACKET64_CONTROL_PAYLOAD_INDEX: u8 = 0x7;
+
 impl Packet64 {
+    pub const SIZE: usize = 8;
+    pub const PAYLOAD_SIZE: usize = 5;
+
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; Self::SIZE];
+        buffer.copy_from_slice(&bytes[..Self::SIZE]);
+        u64::from_le_bytes(buffer).into()
+    }
+
+    /// Bytes which don't fit into the payload are ignored
+    pub fn with_payload_bytes(self, bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; 8];
+        let length = bytes.len().min(Self::PAYLOAD_SIZE);
+        buffer[..length].copy_from_slice(&bytes[..length]);
+        self.with_payload(u64::from_le_bytes(buffer))
+    }
+
+    pub fn payload_bytes(&self) -> [u8; Self::PAYLOAD_SIZE] {
+        let mut buffer = [0u8; Self::PAYLOAD_SIZE];
+        buffer.copy_from_slice(&self.payload().to_le_bytes()[..Self::PAYLOAD_SIZE]);
+        buffer
+    }
+
     pub fn compute_crc4(&self) -> u8 {
         const CRC4_TABLE: [u8; 16] = [
             0x0, 0x7, 0xe, 0x9, 0xb, 0xc, 0x5, 0x2, 0x1, 0x6, 0xf, 0x8, 0xa, 0xd, 0x4, 0x3,
@@ -202,7 +350,7 @@ impl Packet64 {
 
     #[inline]
     pub const fn size() -> usize {
-        8
+        Self::SIZE
     }
 }
 
@@ -228,6 +376,238 @@ impl defmt::Format for Packet64 {
     }
 }
 
+#[bitfield(u128)]
+#[derive(PartialEq)]
+pub struct Packet128 {
+    #[bits(2)]
+    pub kind: PacketKind,
+    #[bits(5)] // Up to 32 packets
+    pub sequence_number: SequenceNumber<32>,
+    #[bits(5)] // Stream identification
+    pub stream_id: SequenceNumber<32>,
+
+    #[bits(8)] // Up to 256 devices
+    pub source_address: u8,
+    #[bits(8)]
+    pub destination_address: u8,
+
+    #[bits(88)] // 11bytes
+    pub payload: u128,
+
+    #[bits(4)]
+    pub payload_used_index: u8,
+
+    #[bits(8)]
+    pub crc8: u8,
+}
+
+/// Data packets use `payload_used_index` only in range `0..=10`.
+/// The highest value marks control packets (ACK, NACK, ...).
+pub const PACKET128_CONTROL_PAYLOAD_INDEX: u8 = 0xf;
+
+impl Packet128 {
+    pub const SIZE: usize = 16;
+    pub const PAYLOAD_SIZE: usize = 11;
+
+    pub fn from_le_bytes(bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; Self::SIZE];
+        buffer.copy_from_slice(&bytes[..Self::SIZE]);
+        u128::from_le_bytes(buffer).into()
+    }
+
+    /// Bytes which don't fit into the payload are ignored
+    pub fn with_payload_bytes(self, bytes: &[u8]) -> Self {
+        let mut buffer = [0u8; 16];
+        let length = bytes.len().min(Self::PAYLOAD_SIZE);
+        buffer[..length].copy_from_slice(&bytes[..length]);
+        self.with_payload(u128::from_le_bytes(buffer))
+    }
+
+    pub fn payload_bytes(&self) -> [u8; Self::PAYLOAD_SIZE] {
+        let mut buffer = [0u8; Self::PAYLOAD_SIZE];
+        buffer.copy_from_slice(&self.payload().to_le_bytes()[..Self::PAYLOAD_SIZE]);
+        buffer
+    }
+
+    /// CRC-8 over everything except the CRC itself
+    pub fn compute_crc8(&self) -> u8 {
+        let value: u128 = self.with_crc8(0x0).into();
+        // CRC is stored in the last byte
+        crc8(&value.to_le_bytes()[..Self::SIZE - 1])
+    }
+
+    pub fn with_updated_crc(&self) -> Self {
+        self.with_crc8(self.compute_crc8())
+    }
+
+    pub fn update_crc(&mut self) {
+        self.set_crc8(self.compute_crc8());
+    }
+
+    pub fn validate(&self) -> bool {
+        self.compute_crc8() == self.crc8()
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 16] {
+        Into::<u128>::into(self).to_le_bytes()
+    }
+
+    #[inline]
+    pub const fn size() -> usize {
+        Self::SIZE
+    }
+}
+
+impl defmt::Format for Packet128 {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "Packet128 {{ kind: {:?}, sequence_number: {}, stream_id: {}, source_address: {:#04x}, destination_address: {:#04x}, payload: {=[u8]:#04x}, payload_used_index: {}, crc8: {:#04x} }}",
+            self.kind(),
+            self.sequence_number(),
+            self.stream_id(),
+            self.source_address(),
+            self.destination_address(),
+            &self.payload_bytes()[..],
+            self.payload_used_index(),
+            self.crc8(),
+        )
+    }
+}
+
+/// Storage for the packets of one stream, every format sizes it by its own window,
+/// so small formats don't take the RAM the largest one needs
+pub trait PacketBuffer<F>: Default + Deref<Target = [F]> + DerefMut {
+    fn push(&mut self, packet: F) -> Result<(), F>;
+    fn insert(&mut self, index: usize, packet: F) -> Result<(), F>;
+    fn clear(&mut self);
+}
+
+impl<F, const SIZE: usize> PacketBuffer<F> for heapless::Vec<F, SIZE> {
+    fn push(&mut self, packet: F) -> Result<(), F> {
+        heapless::Vec::push(self, packet)
+    }
+
+    fn insert(&mut self, index: usize, packet: F) -> Result<(), F> {
+        heapless::Vec::insert(self, index, packet)
+    }
+
+    fn clear(&mut self) {
+        heapless::Vec::clear(self)
+    }
+}
+
+/// Implement `PacketFormat` by calling the methods generated by the bitfield
+macro_rules! impl_packet_format {
+    ($packet:ty, $sequence_number:ty, $stream_id:ty, $broadcast_address:expr, $control_payload_index:expr) => {
+        impl PacketFormat for $packet {
+            type SequenceNumber = $sequence_number;
+            type StreamId = $stream_id;
+            type Packets = heapless::Vec<Self, { <$sequence_number as Sequence>::MODULO as usize }>;
+
+            const SIZE: usize = <$packet>::SIZE;
+            const PAYLOAD_SIZE: usize = <$packet>::PAYLOAD_SIZE;
+            const BROADCAST_ADDRESS: u8 = $broadcast_address;
+            const CONTROL_PAYLOAD_INDEX: Option<u8> = $control_payload_index;
+
+            fn new() -> Self {
+                <$packet>::new()
+            }
+
+            fn kind(&self) -> PacketKind {
+                <$packet>::kind(self)
+            }
+
+            fn with_kind(self, kind: PacketKind) -> Self {
+                <$packet>::with_kind(self, kind)
+            }
+
+            fn sequence_number(&self) -> Self::SequenceNumber {
+                <$packet>::sequence_number(self)
+            }
+
+            fn with_sequence_number(self, sequence_number: Self::SequenceNumber) -> Self {
+                <$packet>::with_sequence_number(self, sequence_number)
+            }
+
+            fn stream_id(&self) -> Self::StreamId {
+                <$packet>::stream_id(self)
+            }
+
+            fn with_stream_id(self, stream_id: Self::StreamId) -> Self {
+                <$packet>::with_stream_id(self, stream_id)
+            }
+
+            fn source_address(&self) -> u8 {
+                <$packet>::source_address(self)
+            }
+
+            fn with_source_address(self, address: u8) -> Self {
+                <$packet>::with_source_address(self, address)
+            }
+
+            fn destination_address(&self) -> u8 {
+                <$packet>::destination_address(self)
+            }
+
+            fn with_destination_address(self, address: u8) -> Self {
+                <$packet>::with_destination_address(self, address)
+            }
+
+            fn payload_used_index(&self) -> u8 {
+                <$packet>::payload_used_index(self)
+            }
+
+            fn with_payload_used_index(self, index: u8) -> Self {
+                <$packet>::with_payload_used_index(self, index)
+            }
+
+            fn with_payload_bytes(self, bytes: &[u8]) -> Self {
+                <$packet>::with_payload_bytes(self, bytes)
+            }
+
+            fn write_payload(&self, buffer: &mut [u8]) -> usize {
+                buffer[..<$packet>::PAYLOAD_SIZE].copy_from_slice(&self.payload_bytes());
+                <$packet>::PAYLOAD_SIZE
+            }
+
+            fn with_updated_crc(self) -> Self {
+                <$packet>::with_updated_crc(&self)
+            }
+
+            fn validate(&self) -> bool {
+                <$packet>::validate(self)
+            }
+
+            fn write_le_bytes(&self, buffer: &mut [u8]) -> usize {
+                buffer[..<$packet>::SIZE].copy_from_slice(&self.to_le_bytes());
+                <$packet>::SIZE
+            }
+
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                <$packet>::from_le_bytes(bytes)
+            }
+        }
+    };
+}
+
+// Single byte payload of Packet32 can't hold the NACK bitmap
+impl_packet_format!(Packet32, SequenceNumber<8>, SequenceNumber<8>, 0x0f, None);
+impl_packet_format!(
+    Packet64,
+    SequenceNumber<16>,
+    SequenceNumber<8>,
+    0x0f,
+    Some(PACKET64_CONTROL_PAYLOAD_INDEX)
+);
+impl_packet_format!(
+    Packet128,
+    SequenceNumber<32>,
+    SequenceNumber<32>,
+    0xff,
+    Some(PACKET128_CONTROL_PAYLOAD_INDEX)
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -246,6 +626,66 @@ mod tests {
         assert_eq!(crc, packet64.crc4());
     }
 
+    #[test]
+    fn test_crc_packet32() {
+        let mut packet32 = Packet32::new()
+            .with_kind(PacketKind::End)
+            .with_sequence_number(SequenceNumber::new(5))
+            .with_source_address(0x05)
+            .with_destination_address(0x0f)
+            .with_payload_bytes(&[0xa5]);
+        assert!(!packet32.validate());
+
+        packet32.update_crc();
+        assert!(packet32.validate());
+
+        // Every single bit error has to be detected
+        let data: u32 = packet32.into();
+        for bit in 0..32 {
+            assert!(!Packet32::from(data ^ (1u32 << bit)).validate());
+        }
+    }
+
+    #[test]
+    fn test_crc_packet128() {
+        let mut packet128 = Packet128::new()
+            .with_kind(PacketKind::Start)
+            .with_source_address(0xa5)
+            .with_destination_address(0xff)
+            .with_payload_bytes(&[0x12, 0x34, 0x56]);
+        packet128.update_crc();
+        assert!(packet128.validate());
+
+        // Every single bit error has to be detected
+        let data: u128 = packet128.into();
+        for bit in 0..128 {
+            assert!(!Packet128::from(data ^ (1u128 << bit)).validate());
+        }
+    }
+
+    #[test]
+    fn test_packet128_payload_bytes() {
+        let payload = [
+            0x01u8, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,
+        ];
+        let packet = Packet128::new()
+            .with_sequence_number(SequenceNumber::new(31))
+            .with_payload_bytes(&payload)
+            .with_payload_used_index(10)
+            .with_updated_crc();
+
+        assert_eq!(packet.payload_bytes(), payload);
+        assert_eq!(Packet128::from_le_bytes(&packet.to_le_bytes()), packet);
+        assert_eq!(packet.sequence_number(), SequenceNumber::new(31));
+    }
+
+    #[test]
+    fn test_max_message_size() {
+        assert_eq!(<Packet32 as PacketFormat>::MAX_MESSAGE_SIZE, 8);
+        assert_eq!(<Packet64 as PacketFormat>::MAX_MESSAGE_SIZE, 80);
+        assert_eq!(<Packet128 as PacketFormat>::MAX_MESSAGE_SIZE, 352);
+    }
+
     #[test]
     fn test_packet32_from_u64() {
         let original_packet = Packet32::new()
@@ -258,4 +698,17 @@ mod tests {
         let received_packet = Packet32::from(packet_data_64);
         assert_eq!(received_packet, original_packet);
     }
+
+    #[test]
+    fn test_packet32_stream_id() {
+        let packet = Packet32::new()
+            .with_stream_id(SequenceNumber::new(7))
+            .with_payload_bytes(&[0xff])
+            .with_updated_crc();
+
+        let received_packet = Packet32::from_le_bytes(&packet.to_le_bytes());
+        assert_eq!(received_packet.stream_id(), SequenceNumber::new(7));
+        assert_eq!(received_packet.payload(), 0xff);
+        assert!(received_packet.validate());
+    }
 }
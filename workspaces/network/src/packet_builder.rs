@@ -1,9 +1,212 @@
-#[cfg(feature = "packet-32")]
-pub mod packet32_builder;
-#[cfg(feature = "packet-64")]
-pub mod packet64_builder;
-
-#[cfg(feature = "packet-32")]
-pub use packet32_builder::PacketBuilder;
-#[cfg(feature = "packet-64")]
-pub use packet64_builder::PacketBuilder;
+use core::borrow::Borrow;
+
+use sequence_number::Sequence;
+
+use crate::packet::{PacketFormat, PacketKind, MAX_PAYLOAD_SIZE};
+use crate::Address;
+
+/// Split payload into packets of one stream.
+/// Works the same for every packet format, only the payload size differs.
+pub struct PacketBuilder<'a, F, P, I>
+where
+    F: PacketFormat,
+    P: Iterator<Item = I>,
+    I: Borrow<u8>,
+{
+    address: &'a Address,
+
+    sequence_number: &'a mut F::SequenceNumber,
+    stream_id: F::StreamId,
+    payload: P,
+
+    last_byte: Option<u8>,
+}
+
+impl<'a, F, P, I> PacketBuilder<'a, F, P, I>
+where
+    F: PacketFormat,
+    P: Iterator<Item = I>,
+    I: Borrow<u8>,
+{
+    pub fn new(
+        address: &'a Address,
+        start_sequence_number: &'a mut F::SequenceNumber,
+        stream_id: F::StreamId,
+        payload: P,
+    ) -> Self {
+        Self {
+            address,
+            sequence_number: start_sequence_number,
+            stream_id,
+            payload,
+
+            last_byte: None,
+        }
+    }
+}
+
+impl<'a, F, P, I> Iterator for PacketBuilder<'a, F, P, I>
+where
+    F: PacketFormat,
+    P: Iterator<Item = I>,
+    I: Borrow<u8>,
+{
+    type Item = F;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let was_previous_byte_set = self.last_byte.is_some();
+        let first_payload_byte = self
+            .last_byte
+            .or_else(|| self.payload.next().map(|v| *v.borrow()))?;
+        self.last_byte = None;
+
+        let packet = F::new()
+            .with_sequence_number(self.sequence_number.advance())
+            .with_stream_id(self.stream_id.clone())
+            .with_source_address(self.address.local_address)
+            .with_destination_address(self.address.destination_address);
+
+        let mut payload_buffer = [0x0u8; MAX_PAYLOAD_SIZE];
+        payload_buffer[0] = first_payload_byte;
+
+        for index in 1usize..F::PAYLOAD_SIZE {
+            if let Some(byte) = self.payload.next() {
+                payload_buffer[index] = *byte.borrow();
+            } else {
+                return Some(
+                    packet
+                        .with_kind(if was_previous_byte_set {
+                            PacketKind::End
+                        } else {
+                            PacketKind::SelfContained
+                        })
+                        .with_payload_bytes(&payload_buffer[..F::PAYLOAD_SIZE])
+                        .with_payload_used_index(index as u8 - 1)
+                        .with_updated_crc(),
+                );
+            }
+        }
+
+        // Peek if there is anything left to decide the packet kind
+        let probably_last_byte = self.payload.next().map(|v| *v.borrow());
+        self.last_byte = probably_last_byte;
+
+        let kind = match (was_previous_byte_set, probably_last_byte.is_some()) {
+            (false, false) => PacketKind::SelfContained,
+            (false, true) => PacketKind::Start,
+            (true, true) => PacketKind::Continue,
+            (true, false) => PacketKind::End,
+        };
+
+        Some(
+            packet
+                .with_kind(kind)
+                .with_payload_bytes(&payload_buffer[..F::PAYLOAD_SIZE])
+                .with_payload_used_index(F::PAYLOAD_SIZE as u8 - 1)
+                .with_updated_crc(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet128, Packet32, Packet64};
+
+    use std::vec::Vec;
+
+    fn build<F: PacketFormat>(payload: &[u8]) -> Vec<F> {
+        let mut sequence_number = F::SequenceNumber::new(1);
+        let address = Address::new(0x01, 0x02);
+        let builder = PacketBuilder::<F, _, _>::new(
+            &address,
+            &mut sequence_number,
+            F::StreamId::new(0),
+            payload.iter(),
+        );
+
+        builder.collect()
+    }
+
+    fn payload<F: PacketFormat>(packet: &F) -> Vec<u8> {
+        let mut buffer = [0u8; MAX_PAYLOAD_SIZE];
+        let size = packet.write_payload(&mut buffer);
+        Vec::from(&buffer[..size])
+    }
+
+    fn base_packet_builder<F: PacketFormat>() {
+        // Packet32 has only a single byte of payload
+        let paylaod = vec![0x01u8, 0x02][..F::PAYLOAD_SIZE.min(2)].to_vec();
+        let mut sequence_number = F::SequenceNumber::new(1);
+        let address = Address::new(0x01, 0x02);
+        let builder = PacketBuilder::<F, _, _>::new(
+            &address,
+            &mut sequence_number,
+            F::StreamId::new(0),
+            paylaod.clone().into_iter(),
+        );
+
+        let mut result: Vec<F> = builder.collect();
+        assert_eq!(result.len(), 1);
+        assert_eq!(sequence_number, F::SequenceNumber::new(2));
+
+        let packet = result.pop().unwrap();
+        println!("Packet = {:?}", packet);
+
+        assert!(packet.validate());
+        assert_eq!(packet.sequence_number(), F::SequenceNumber::new(1));
+        assert_eq!(packet.kind(), PacketKind::SelfContained);
+        assert_eq!(&payload(&packet)[..paylaod.len()], &paylaod[..]);
+        assert_eq!(packet.payload_used_index() as usize, paylaod.len() - 1);
+    }
+
+    fn complex_packet_builder<F: PacketFormat>() {
+        // Payload for 3 packets, the last one is not complete
+        let paylaod: Vec<u8> = (0u8..(F::PAYLOAD_SIZE as u8 * 2 + 1)).collect();
+        let result = build::<F>(&paylaod);
+        println!("{:?}", result);
+        assert_eq!(result.len(), 3);
+
+        assert_eq!(result[0].kind(), PacketKind::Start);
+        assert_eq!(result[1].kind(), PacketKind::Continue);
+        assert_eq!(result[2].kind(), PacketKind::End);
+
+        let used_bytes: Vec<usize> = result
+            .iter()
+            .map(|packet| packet.payload_used_index() as usize + 1)
+            .collect();
+        assert_eq!(used_bytes, vec![F::PAYLOAD_SIZE, F::PAYLOAD_SIZE, 1]);
+        assert_eq!(payload(&result[2])[0], F::PAYLOAD_SIZE as u8 * 2);
+    }
+
+    fn full_packets_packet_builder<F: PacketFormat>() {
+        let paylaod: Vec<u8> = (0u8..(F::PAYLOAD_SIZE as u8 * 2)).collect();
+        let result = build::<F>(&paylaod);
+        assert_eq!(result.len(), 2);
+
+        assert_eq!(result[0].kind(), PacketKind::Start);
+        assert_eq!(result[1].kind(), PacketKind::End);
+        assert_eq!(result[1].payload_used_index() as usize, F::PAYLOAD_SIZE - 1);
+    }
+
+    #[test]
+    fn test_base_packet_builder() {
+        base_packet_builder::<Packet32>();
+        base_packet_builder::<Packet64>();
+        base_packet_builder::<Packet128>();
+    }
+
+    #[test]
+    fn test_complex_packet_builder() {
+        complex_packet_builder::<Packet32>();
+        complex_packet_builder::<Packet64>();
+        complex_packet_builder::<Packet128>();
+    }
+
+    #[test]
+    fn test_full_packets_packet_builder() {
+        full_packets_packet_builder::<Packet32>();
+        full_packets_packet_builder::<Packet64>();
+        full_packets_packet_builder::<Packet128>();
+    }
+}
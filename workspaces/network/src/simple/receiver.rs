@@ -1,5 +1,8 @@
+use core::marker::PhantomData;
+
 use crate::transport::reader::TransportReader;
 
+use crate::packet::PacketFormat;
 use crate::simple::codec::{
     create_codec, create_compression, CodecFactoryType, CompressionFactoryType,
 };
@@ -7,15 +10,18 @@ use crate::Address;
 use codec::Codec;
 use physical_layer::BaseReader;
 
-pub struct SimpleReceiver<R, C, P> {
+/// Owns everything the transport needs, packets are received in the format `F`
+pub struct SimpleReceiver<F, R, C, P> {
     address: Address,
     reader: R,
     codec: C,
     compression: P,
+    _format: PhantomData<F>,
 }
 
-impl<R, C, P> SimpleReceiver<R, C, P>
+impl<F, R, C, P> SimpleReceiver<F, R, C, P>
 where
+    F: PacketFormat,
     R: BaseReader,
     C: Codec,
     P: Codec,
@@ -26,10 +32,11 @@ where
             reader,
             codec,
             compression,
+            _format: PhantomData,
         }
     }
 
-    pub fn create_transport(&mut self) -> TransportReader<R, C, P> {
+    pub fn create_transport(&mut self) -> TransportReader<F, R, C, P> {
         TransportReader::new(
             self.address.clone(),
             &self.codec,
@@ -39,8 +46,9 @@ where
     }
 }
 
-impl<R> SimpleReceiver<R, CodecFactoryType, CompressionFactoryType>
+impl<F, R> SimpleReceiver<F, R, CodecFactoryType, CompressionFactoryType>
 where
+    F: PacketFormat,
     R: BaseReader,
 {
     pub fn new_simple(address: Address, reader: R) -> Self {
@@ -49,6 +57,7 @@ where
             reader,
             codec: create_codec(),
             compression: create_compression(),
+            _format: PhantomData,
         }
     }
 }
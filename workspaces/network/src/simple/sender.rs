@@ -1,5 +1,8 @@
+use core::marker::PhantomData;
+
 use crate::transport::writer::TransportWriter;
 
+use crate::packet::PacketFormat;
 use crate::simple::codec::{
     create_codec, create_compression, CodecFactoryType, CompressionFactoryType,
 };
@@ -7,15 +10,18 @@ use crate::Address;
 use codec::Codec;
 use physical_layer::BaseWriter;
 
-pub struct SimpleSender<W, C, P> {
+/// Owns everything the transport needs, packets are sent in the format `F`
+pub struct SimpleSender<F, W, C, P> {
     address: Address,
     writer: W,
     codec: C,
     compression: P,
+    _format: PhantomData<F>,
 }
 
-impl<W, C, P> SimpleSender<W, C, P>
+impl<F, W, C, P> SimpleSender<F, W, C, P>
 where
+    F: PacketFormat,
     W: BaseWriter,
     C: Codec,
     P: Codec,
@@ -26,10 +32,11 @@ where
             writer,
             codec,
             compression,
+            _format: PhantomData,
         }
     }
 
-    pub fn create_transport(&mut self) -> TransportWriter<W, C, P> {
+    pub fn create_transport(&mut self) -> TransportWriter<F, W, C, P> {
         TransportWriter::new(
             self.address.clone(),
             3,
@@ -40,8 +47,9 @@ where
     }
 }
 
-impl<W> SimpleSender<W, CodecFactoryType, CompressionFactoryType>
+impl<F, W> SimpleSender<F, W, CodecFactoryType, CompressionFactoryType>
 where
+    F: PacketFormat,
     W: BaseWriter,
 {
     pub fn new_simple(address: Address, writer: W) -> Self {
@@ -50,6 +58,7 @@ where
             writer,
             codec: create_codec(),
             compression: create_compression(),
+            _format: PhantomData,
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::packet::{Packet128, Packet32, Packet64};
 use crate::transport::reader::TransportReader;
 use crate::transport::writer::TransportWriter;
 use crate::transport::{TransportReceiver, TransportSender};
@@ -13,8 +14,10 @@ use codec::reed_solomon::ReedSolomon;
 use std::future::Future;
 use std::vec::Vec;
 
+pub mod address;
 pub mod io;
 pub mod network;
+pub mod simulated;
 
 // use embedded_hal::serial::Write;
 // use std::io::{self, Write as _};
@@ -61,13 +64,21 @@ where
 }
 
 macro_rules! test_configuration {
-    ($codec:ty, $compression: ty) => {
+    ($format:ty, $codec:ty, $compression: ty) => {
+        test_configuration!(
+            $format,
+            $codec,
+            $compression,
+            vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa]
+        )
+    };
+    ($format:ty, $codec:ty, $compression: ty, $payload:expr) => {
         test_network(
             // FIXME how to use `Cod` here?
             |mut reader_factory, mut writer_factory: WriterFactory<$codec, $compression>| async move {
-                let mut reader = reader_factory.create_reader();
-                let mut writer = writer_factory.create_writer();
-                let payload = vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa];
+                let mut reader = reader_factory.create_reader::<$format>();
+                let mut writer = writer_factory.create_writer::<$format>();
+                let payload: Vec<u8> = $payload;
 
                 let wrote_bytes = writer
                     .send_bytes(&payload[..])
@@ -92,27 +103,48 @@ macro_rules! test_configuration {
 
 #[test]
 fn test_full_receive_transmit_identity() {
-    test_configuration!(Identity, Identity);
+    test_configuration!(Packet64, Identity, Identity);
+}
+
+#[test]
+fn test_full_receive_transmit_packet32() {
+    // Packet32 carries a single byte per packet, so up to 8 bytes in one stream
+    test_configuration!(
+        Packet32,
+        Identity,
+        Identity,
+        vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc]
+    );
+}
+
+#[test]
+fn test_full_receive_transmit_packet128() {
+    test_configuration!(Packet128, ReedSolomon<4, 16>, Identity);
 }
 
 #[test]
 fn test_full_receive_transmit_codec_reed_solomon() {
-    test_configuration!(ReedSolomon<4, 8>, Identity);
+    test_configuration!(Packet64, ReedSolomon<4, 8>, Identity);
 }
 
 #[test]
 fn test_full_receive_transmit_codec_chain() {
-    test_configuration!(Chain<ReedSolomon<4, 8>, FourToSixBits<20>, 8>, Identity);
+    test_configuration!(
+        Packet64,
+        Chain<ReedSolomon<4, 8>, FourToSixBits<20>, 8>,
+        Identity
+    );
 }
 
 #[test]
 fn test_full_receive_transmit_lzss_compression() {
-    test_configuration!(Identity, LzssCompression);
+    test_configuration!(Packet64, Identity, LzssCompression);
 }
 
 #[test]
 fn test_full_receive_transmit_codec_complex_compression() {
     test_configuration!(
+        Packet64,
         Chain<ReedSolomon<4, 8>, FourToSixBits<20>, 8>,
         LzssCompression
     );
@@ -0,0 +1,31 @@
+use crate::packet::{Packet128, Packet64, PacketFormat};
+use crate::{Address, MAX_GROUP_ADDRESSES};
+
+#[test]
+fn test_accepts_local_and_broadcast() {
+    let address = Address::new(0x03, 0x08);
+
+    assert!(address.accepts::<Packet64>(0x03));
+    assert!(address.accepts::<Packet64>(Packet64::BROADCAST_ADDRESS));
+    assert!(!address.accepts::<Packet64>(0x08));
+
+    // Broadcast address depends on the address size of the format
+    assert!(address.accepts::<Packet128>(0xff));
+    assert!(!address.accepts::<Packet128>(0x0f));
+}
+
+#[test]
+fn test_accepts_groups() {
+    let mut address = Address::new(0x03, 0x08);
+    address.add_group(0x0a).unwrap();
+    address.add_group(0x0a).unwrap();
+
+    assert!(address.accepts::<Packet64>(0x0a));
+    assert!(!address.accepts::<Packet64>(0x0b));
+    assert_eq!(address.group_addresses.len(), 1);
+
+    for group in 0..MAX_GROUP_ADDRESSES as u8 - 1 {
+        address.add_group(group).unwrap();
+    }
+    assert_eq!(address.add_group(0x0c), Err(0x0c));
+}
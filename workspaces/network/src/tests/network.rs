@@ -1,4 +1,5 @@
 use super::io::*;
+use crate::packet::PacketFormat;
 use crate::transport::reader::TransportReader;
 use crate::transport::writer::TransportWriter;
 use crate::transport::{TransportReceiver, TransportSender};
@@ -24,7 +25,10 @@ where
         }
     }
 
-    pub fn create_reader(&mut self) -> TransportReader<'_, DummyManchesterReader, Cod, Com> {
+    pub fn create_reader<F>(&mut self) -> TransportReader<'_, F, DummyManchesterReader, Cod, Com>
+    where
+        F: PacketFormat,
+    {
         TransportReader::new(
             Address::new(0x03, 0x08),
             &self.codec,
@@ -53,7 +57,10 @@ where
         }
     }
 
-    pub fn create_writer(&mut self) -> TransportWriter<'_, DummyManchesterWriter, Cod, Com> {
+    pub fn create_writer<F>(&mut self) -> TransportWriter<'_, F, DummyManchesterWriter, Cod, Com>
+    where
+        F: PacketFormat,
+    {
         TransportWriter::new(
             Address::new(0x08, 0x03),
             3,
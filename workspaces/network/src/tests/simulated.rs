@@ -0,0 +1,445 @@
+use crate::packet::{Packet128, Packet32, Packet64, PacketFormat};
+use crate::transport::checksum::MessageChecksum;
+use crate::transport::reader::TransportReader;
+use crate::transport::statistics::LinkStatisticsTable;
+use crate::transport::writer::TransportWriter;
+use crate::transport::{TransportReceiver, TransportSender};
+use crate::Address;
+
+use async_std::task::block_on;
+use codec::chain::Chain;
+use codec::convolutional::Convolutional;
+use codec::dynamic::{CodecKind, DynamicCodec};
+use codec::four_to_six::FourToSixBits;
+use codec::lzss::LzssCompression;
+use codec::reed_solomon::ReedSolomon;
+use codec::{Codec, CodecSize, Identity};
+use simulated_channel::{ChannelConfig, NoiseConfig, SimulatedChannel, XorShiftRng};
+use std::vec::Vec;
+
+use super::init_logging_stdout;
+
+/// Send payload over the simulated channel and try to receive it back.
+fn transfer<F, Cod>(channel: SimulatedChannel, payload: &[u8]) -> Option<Vec<u8>>
+where
+    F: PacketFormat,
+    Cod: Codec + ~const CodecSize + Default,
+    [(); Cod::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    transfer_addressed::<F, Cod>(
+        channel,
+        Address::new(0x08, 0x03),
+        Address::new(0x03, 0x08),
+        payload,
+    )
+}
+
+fn transfer_addressed<F, Cod>(
+    channel: SimulatedChannel,
+    sender_address: Address,
+    receiver_address: Address,
+    payload: &[u8],
+) -> Option<Vec<u8>>
+where
+    F: PacketFormat,
+    Cod: Codec + ~const CodecSize + Default,
+    [(); Cod::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    let codec = Cod::default();
+    let compression = Identity::default();
+    transfer_with::<F, _, _>(
+        channel,
+        Link::new(&codec, &compression).with_addresses(sender_address, receiver_address),
+        payload,
+        |_| {},
+    )
+}
+
+/// Configuration of both ends of the simulated link
+struct Link<'a, Cod, Com> {
+    sender_address: Address,
+    receiver_address: Address,
+    codec: &'a Cod,
+    compression: &'a Com,
+    checksum: MessageChecksum,
+}
+
+impl<'a, Cod, Com> Link<'a, Cod, Com> {
+    /// Same addresses as `transfer` uses, no message checksum
+    fn new(codec: &'a Cod, compression: &'a Com) -> Self {
+        Self {
+            sender_address: Address::new(0x08, 0x03),
+            receiver_address: Address::new(0x03, 0x08),
+            codec,
+            compression,
+            checksum: MessageChecksum::None,
+        }
+    }
+
+    fn with_addresses(mut self, sender_address: Address, receiver_address: Address) -> Self {
+        self.sender_address = sender_address;
+        self.receiver_address = receiver_address;
+        self
+    }
+
+    fn with_message_checksum(mut self, checksum: MessageChecksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+}
+
+/// Send payload over the simulated channel and try to receive it back.
+/// Statistics of the receiver are handed to the callback once the transfer ends.
+fn transfer_with<F, Cod, Com>(
+    channel: SimulatedChannel,
+    link: Link<Cod, Com>,
+    payload: &[u8],
+    statistics: impl FnOnce(&LinkStatisticsTable),
+) -> Option<Vec<u8>>
+where
+    F: PacketFormat,
+    Cod: Codec + ~const CodecSize,
+    Com: Codec + ~const CodecSize,
+    [(); Cod::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    init_logging_stdout();
+
+    let mut channel_writer = channel.writer();
+    let mut channel_reader = channel.reader();
+
+    let mut writer = TransportWriter::<F, _, _, _>::new(
+        link.sender_address,
+        3,
+        link.codec,
+        link.compression,
+        &mut channel_writer,
+    )
+    .with_message_checksum(link.checksum);
+    let mut reader = TransportReader::<F, _, _, _>::new(
+        link.receiver_address,
+        link.codec,
+        link.compression,
+        &mut channel_reader,
+    )
+    .with_message_checksum(link.checksum);
+
+    let received = block_on(async {
+        writer.send_bytes(payload).await.expect("Can't send data");
+
+        let mut read_buffer = [0x00u8; F::MAX_MESSAGE_SIZE];
+        let read_bytes = reader.receive_bytes(&mut read_buffer).await.ok()?;
+        Some(Vec::from(&read_buffer[..read_bytes]))
+    });
+
+    statistics(reader.statistics());
+    received
+}
+
+fn payload() -> Vec<u8> {
+    vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa]
+}
+
+#[test]
+fn test_simulated_lossless_channel() {
+    let channel = SimulatedChannel::lossless();
+    assert_eq!(
+        transfer::<Packet64, Identity>(channel, &payload()),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_lossless_every_format() {
+    assert_eq!(
+        transfer::<Packet32, Identity>(SimulatedChannel::lossless(), &payload()[..8]),
+        Some(Vec::from(&payload()[..8]))
+    );
+    assert_eq!(
+        transfer::<Packet128, Identity>(SimulatedChannel::lossless(), &payload()),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_bit_flips_reed_solomon() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig::with_bit_flips(0.002),
+        0xdead_beef,
+    ));
+    assert_eq!(
+        transfer::<Packet64, ReedSolomon<4, 8>>(channel.clone(), &payload()),
+        Some(payload())
+    );
+    assert!(channel.statistics().noise.bits_flipped > 0);
+}
+
+#[test]
+fn test_simulated_bit_flips_chain() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig::with_bit_flips(0.002),
+        0x1234_5678,
+    ));
+    assert_eq!(
+        transfer::<Packet64, Chain<ReedSolomon<4, 8>, FourToSixBits<20>, 8>>(channel, &payload()),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_bit_flips_convolutional() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig::with_bit_flips(0.01),
+        0x0bad_cafe,
+    ));
+    assert_eq!(
+        transfer::<Packet64, Convolutional<8>>(channel.clone(), &payload()),
+        Some(payload())
+    );
+    assert!(channel.statistics().noise.bits_flipped > 0);
+}
+
+#[test]
+fn test_simulated_bit_flips_dynamic_chain() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig::with_bit_flips(0.002),
+        0x1234_5678,
+    ));
+    // Codec picked at runtime, e.g. from the configuration
+    let codec =
+        DynamicCodec::from_ids(&[CodecKind::ReedSolomon4.id(), CodecKind::FourToSix.id()]).unwrap();
+    let compression = Identity::default();
+    assert_eq!(
+        transfer_with::<Packet64, _, _>(
+            channel,
+            Link::new(&codec, &compression),
+            &payload(),
+            |_| {}
+        ),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_packet_loss_with_resend() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig {
+            packet_loss_probability: 0.1,
+            ..NoiseConfig::default()
+        },
+        42,
+    ));
+    assert_eq!(
+        transfer::<Packet64, Identity>(channel, &payload()),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_all_packets_lost() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig {
+            packet_loss_probability: 1.0,
+            ..NoiseConfig::default()
+        },
+        42,
+    ));
+    assert_eq!(transfer::<Packet64, Identity>(channel, &payload()), None);
+}
+
+#[test]
+fn test_simulated_broadcast() {
+    let channel = SimulatedChannel::lossless();
+    assert_eq!(
+        transfer_addressed::<Packet64, Identity>(
+            channel,
+            Address::new(0x08, Packet64::BROADCAST_ADDRESS),
+            Address::new(0x03, 0x08),
+            &payload()
+        ),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_group() {
+    let mut receiver_address = Address::new(0x03, 0x08);
+    receiver_address.add_group(0x0a).unwrap();
+
+    assert_eq!(
+        transfer_addressed::<Packet64, Identity>(
+            SimulatedChannel::lossless(),
+            Address::new(0x08, 0x0a),
+            receiver_address,
+            &payload()
+        ),
+        Some(payload())
+    );
+}
+
+#[test]
+fn test_simulated_other_address_ignored() {
+    let channel = SimulatedChannel::lossless();
+    assert_eq!(
+        transfer_addressed::<Packet64, Identity>(
+            channel,
+            Address::new(0x08, 0x04),
+            Address::new(0x03, 0x08),
+            &payload()
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_simulated_more_messages_than_stream_ids() {
+    init_logging_stdout();
+
+    let channel = SimulatedChannel::lossless();
+    let (codec, compression) = (Identity::default(), Identity::default());
+    let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+    let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+        Address::new(0x08, 0x03),
+        3,
+        &codec,
+        &compression,
+        &mut channel_writer,
+    );
+    let mut reader = TransportReader::<Packet64, _, _, _>::new(
+        Address::new(0x03, 0x08),
+        &codec,
+        &compression,
+        &mut channel_reader,
+    );
+
+    // Stream ids of Packet64 come around after 8 messages, well within the duplicate window
+    let messages: Vec<[u8; 2]> = (0..12u8).map(|index| [index, !index]).collect();
+    let received = block_on(async {
+        for message in messages.iter() {
+            writer.send_bytes(message).await.expect("Can't send data");
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..messages.len() {
+            let mut read_buffer = [0x00u8; 2];
+            let read_bytes = reader.receive_bytes(&mut read_buffer).await.ok()?;
+            received.push(read_buffer);
+            assert_eq!(read_bytes, 2);
+        }
+        Some(received)
+    });
+
+    assert_eq!(received, Some(messages));
+}
+
+#[test]
+fn test_simulated_message_checksum() {
+    let codec = Identity::default();
+    let compression = Identity::default();
+    let received = transfer_with::<Packet64, _, _>(
+        SimulatedChannel::lossless(),
+        Link::new(&codec, &compression).with_message_checksum(MessageChecksum::Crc32),
+        &payload(),
+        |_| {},
+    );
+
+    // Trailer is removed before the message is handed over
+    assert_eq!(received, Some(payload()));
+}
+
+#[test]
+fn test_simulated_struct_bigger_than_16_bytes() {
+    init_logging_stdout();
+
+    let channel = SimulatedChannel::lossless();
+    let (codec, compression) = (Identity::default(), Identity::default());
+    let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+    let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+        Address::new(0x08, 0x03),
+        1,
+        &codec,
+        &compression,
+        &mut channel_writer,
+    );
+    let mut reader = TransportReader::<Packet64, _, _, _>::new(
+        Address::new(0x03, 0x08),
+        &codec,
+        &compression,
+        &mut channel_reader,
+    );
+
+    // Serialized into 37 bytes, more than the old 16 byte buffers of `send_struct`
+    let data = ([0xa5u8; 32], 0x1234_5678u32);
+    let received: ([u8; 32], u32) = block_on(async {
+        writer.send_struct(&data).await.expect("Can't send data");
+        reader.receive_struct().await.expect("Can't receive data")
+    });
+    assert_eq!(received, data);
+}
+
+#[test]
+fn test_simulated_max_message_size() {
+    init_logging_stdout();
+
+    let channel = SimulatedChannel::lossless();
+    let (codec, compression) = (Identity::default(), LzssCompression::default());
+    let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+    let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+        Address::new(0x08, 0x03),
+        1,
+        &codec,
+        &compression,
+        &mut channel_writer,
+    )
+    .with_message_checksum(MessageChecksum::Crc32);
+    let mut reader = TransportReader::<Packet64, _, _, _>::new(
+        Address::new(0x03, 0x08),
+        &codec,
+        &compression,
+        &mut channel_reader,
+    )
+    .with_message_checksum(MessageChecksum::Crc32);
+
+    // Room for the checksum and the worst case of the compression is taken out of the window
+    fn max_message_size<S: TransportSender>(_: &S) -> usize {
+        S::MAX_MESSAGE_SIZE
+    }
+    let size = max_message_size(&writer);
+    assert!(size < Packet64::MAX_MESSAGE_SIZE);
+
+    // Random data can't be compressed
+    let mut random = XorShiftRng::new(0x512e);
+    let payload: Vec<u8> = (0..size).map(|_| random.next_u32() as u8).collect();
+    let received = block_on(async {
+        writer.send_bytes(&payload).await.expect("Can't send data");
+
+        let mut read_buffer = [0x00u8; Packet64::MAX_MESSAGE_SIZE];
+        let read_bytes = reader.receive_bytes(&mut read_buffer).await.ok()?;
+        Some(Vec::from(&read_buffer[..read_bytes]))
+    });
+    assert_eq!(received, Some(payload));
+}
+
+#[test]
+fn test_simulated_corrections_in_statistics() {
+    let channel = SimulatedChannel::new(ChannelConfig::new(
+        NoiseConfig::with_bit_flips(0.02),
+        0xdead_beef,
+    ));
+    let codec = ReedSolomon::<4, 8>::default();
+    let compression = Identity::default();
+    let received = transfer_with::<Packet64, _, _>(
+        channel,
+        Link::new(&codec, &compression),
+        &payload(),
+        |statistics| {
+            let statistics = statistics.get(0x08).unwrap();
+            assert!(statistics.codec_corrections > 0);
+            assert!(statistics.corrected_symbols >= statistics.codec_corrections);
+            assert_eq!(statistics.messages_completed, 1);
+        },
+    );
+    assert_eq!(received, Some(payload()));
+}
@@ -0,0 +1,353 @@
+#[cfg(not(test))]
+use defmt::{error, trace};
+
+#[cfg(test)]
+use log::{error, trace};
+
+use crate::error::{BulkTransferError, NetworkError};
+use crate::transport::{TransportReceiver, TransportSender};
+
+use external_memory::vec_type::ColdVec;
+
+/// Every chunk starts with offset of its data and total length of the transfer (both u32 LE)
+pub const BULK_HEADER_SIZE: usize = 8;
+
+/// Storage the received transfer is written into, chunks are appended in order
+pub trait BulkSink {
+    fn append(&mut self, data: &[u8]) -> Result<(), BulkTransferError>;
+
+    /// New transfer is starting, drop everything received so far
+    fn clear(&mut self);
+}
+
+impl<const N: usize> BulkSink for heapless::Vec<u8, N> {
+    fn append(&mut self, data: &[u8]) -> Result<(), BulkTransferError> {
+        self.extend_from_slice(data)
+            .map_err(|_| BulkTransferError::SinkError)
+    }
+
+    fn clear(&mut self) {
+        heapless::Vec::clear(self);
+    }
+}
+
+impl<'a> BulkSink for ColdVec<'a, u8> {
+    fn append(&mut self, data: &[u8]) -> Result<(), BulkTransferError> {
+        for byte in data {
+            self.push(*byte).map_err(|_| BulkTransferError::SinkError)?;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        ColdVec::clear(self);
+    }
+}
+
+struct ChunkHeader {
+    offset: u32,
+    total: u32,
+}
+
+impl ChunkHeader {
+    fn write(&self, buffer: &mut [u8]) {
+        buffer[..4].copy_from_slice(&self.offset.to_le_bytes());
+        buffer[4..BULK_HEADER_SIZE].copy_from_slice(&self.total.to_le_bytes());
+    }
+
+    fn read(chunk: &[u8]) -> Option<Self> {
+        if chunk.len() < BULK_HEADER_SIZE {
+            return None;
+        }
+
+        let mut offset = [0u8; 4];
+        let mut total = [0u8; 4];
+        offset.copy_from_slice(&chunk[..4]);
+        total.copy_from_slice(&chunk[4..BULK_HEADER_SIZE]);
+        Some(Self {
+            offset: u32::from_le_bytes(offset),
+            total: u32::from_le_bytes(total),
+        })
+    }
+}
+
+/// Sends data bigger than one message as a sequence of chunks, each in its own message
+pub struct BulkSender<'a, S> {
+    sender: &'a mut S,
+    chunk_size: usize,
+}
+
+impl<'a, S> BulkSender<'a, S>
+where
+    S: TransportSender,
+    [(); S::MAX_MESSAGE_SIZE]: Sized,
+{
+    pub fn new(sender: &'a mut S) -> Self {
+        Self {
+            sender,
+            chunk_size: S::MAX_MESSAGE_SIZE.saturating_sub(BULK_HEADER_SIZE),
+        }
+    }
+
+    /// Data bytes in one chunk, at most `MAX_MESSAGE_SIZE` of the sender without the header
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.min(S::MAX_MESSAGE_SIZE.saturating_sub(BULK_HEADER_SIZE));
+        self
+    }
+
+    pub async fn send(&mut self, data: &[u8]) -> Result<usize, NetworkError> {
+        self.send_from(data, 0).await
+    }
+
+    /// Continue interrupted transfer from the offset the receiver got to,
+    /// see `BulkReceiver::progress`
+    pub async fn send_from(&mut self, data: &[u8], offset: usize) -> Result<usize, NetworkError> {
+        let mut sent_bytes = 0usize;
+        let mut offset = offset.min(data.len());
+
+        loop {
+            let (size, next_offset) = self.send_chunk(data, offset).await?;
+            sent_bytes += size;
+            offset = next_offset;
+
+            if offset >= data.len() {
+                return Ok(sent_bytes);
+            }
+        }
+    }
+
+    /// Send single chunk of the data starting at the offset.
+    /// Returns sent bytes and offset of the next chunk.
+    pub async fn send_chunk(
+        &mut self,
+        data: &[u8],
+        offset: usize,
+    ) -> Result<(usize, usize), NetworkError> {
+        if self.chunk_size == 0 {
+            return Err(NetworkError::BulkTransferError(
+                BulkTransferError::ChunkTooSmall,
+            ));
+        }
+        let total = u32::try_from(data.len())
+            .map_err(|_| NetworkError::BulkTransferError(BulkTransferError::TooLarge))?;
+
+        let end = (offset + self.chunk_size).min(data.len());
+        let chunk_size = BULK_HEADER_SIZE + end - offset;
+
+        let mut buffer = [0u8; S::MAX_MESSAGE_SIZE];
+        ChunkHeader {
+            offset: offset as u32,
+            total,
+        }
+        .write(&mut buffer);
+        buffer[BULK_HEADER_SIZE..chunk_size].copy_from_slice(&data[offset..end]);
+
+        trace!("Sending chunk at offset = {}, total = {}", offset, total);
+        let sent_bytes = self.sender.send_bytes(&buffer[..chunk_size]).await?;
+        Ok((sent_bytes, end))
+    }
+}
+
+/// How far is the unfinished transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct BulkProgress {
+    pub received: usize,
+    pub total: usize,
+}
+
+/// Receives chunks sent by `BulkSender` and writes them into a sink.
+///
+/// Chunks are accepted only in order. Chunk with offset `0` always starts a new transfer.
+/// When some chunk is lost, the progress is kept and the transfer can be resumed.
+pub struct BulkReceiver<'a, R> {
+    receiver: &'a mut R,
+    progress: Option<BulkProgress>,
+}
+
+impl<'a, R> BulkReceiver<'a, R>
+where
+    R: TransportReceiver,
+    [(); R::MAX_MESSAGE_SIZE]: Sized,
+{
+    pub fn new(receiver: &'a mut R) -> Self {
+        Self {
+            receiver,
+            progress: None,
+        }
+    }
+
+    /// Progress of the unfinished transfer, the sender should resume from `received`
+    pub fn progress(&self) -> Option<BulkProgress> {
+        self.progress
+    }
+
+    /// Receive chunks until the transfer is complete, returns its size.
+    /// On error the progress is kept, call it again to continue with the resumed transfer.
+    pub async fn receive<K>(&mut self, sink: &mut K) -> Result<usize, NetworkError>
+    where
+        K: BulkSink,
+    {
+        let mut buffer = [0u8; R::MAX_MESSAGE_SIZE];
+
+        loop {
+            let size = self.receiver.receive_bytes(&mut buffer).await?;
+            let header = ChunkHeader::read(&buffer[..size]).ok_or(
+                NetworkError::BulkTransferError(BulkTransferError::InvalidChunk),
+            )?;
+            let (offset, total) = (header.offset as usize, header.total as usize);
+
+            if offset == 0 {
+                sink.clear();
+                self.progress = Some(BulkProgress { received: 0, total });
+            }
+
+            let progress = match self.progress.as_mut() {
+                Some(progress) if progress.total == total => progress,
+                Some(_) => {
+                    return Err(NetworkError::BulkTransferError(
+                        BulkTransferError::UnexpectedTransfer,
+                    ))
+                }
+                None => {
+                    error!("Beginning of the transfer was lost");
+                    return Err(NetworkError::BulkTransferError(
+                        BulkTransferError::MissingChunk { expected: 0 },
+                    ));
+                }
+            };
+
+            if offset < progress.received {
+                trace!("Dropping already received chunk at offset = {}", offset);
+                continue;
+            }
+            if offset > progress.received {
+                error!("Missing chunk at offset = {}", progress.received);
+                return Err(NetworkError::BulkTransferError(
+                    BulkTransferError::MissingChunk {
+                        expected: progress.received as u32,
+                    },
+                ));
+            }
+
+            let data = &buffer[BULK_HEADER_SIZE..size];
+            sink.append(data).map_err(NetworkError::BulkTransferError)?;
+            progress.received += data.len();
+
+            if progress.received >= progress.total {
+                self.progress = None;
+                return Ok(total);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet64;
+    use crate::tests::init_logging_stdout;
+    use crate::transport::reader::TransportReader;
+    use crate::transport::writer::TransportWriter;
+    use crate::Address;
+
+    use async_std::task::block_on;
+    use codec::Identity;
+    use external_memory::allocator::DummyAllocator;
+    use external_memory::memory::DummyMemory;
+    use simulated_channel::SimulatedChannel;
+    use std::vec::Vec;
+
+    fn calibration_table() -> Vec<u8> {
+        (0..300u16).map(|v| (v * 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_bulk_transfer() {
+        init_logging_stdout();
+
+        let channel = SimulatedChannel::lossless();
+        let (codec, compression) = (Identity::default(), Identity::default());
+        let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+        let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+            Address::new(0x08, 0x03),
+            1,
+            &codec,
+            &compression,
+            &mut channel_writer,
+        );
+        let mut reader = TransportReader::<Packet64, _, _, _>::new(
+            Address::new(0x03, 0x08),
+            &codec,
+            &compression,
+            &mut channel_reader,
+        );
+
+        // More chunks than there are stream ids
+        let data = calibration_table();
+        let mut sink = heapless::Vec::<u8, 512>::new();
+        let received = block_on(async {
+            BulkSender::new(&mut writer)
+                .with_chunk_size(32)
+                .send(&data)
+                .await
+                .expect("Can't send data");
+            BulkReceiver::new(&mut reader).receive(&mut sink).await
+        });
+
+        assert_eq!(received.unwrap(), data.len());
+        assert_eq!(&sink[..], &data[..]);
+    }
+
+    #[test]
+    fn test_bulk_resume_after_loss() {
+        init_logging_stdout();
+
+        let channel = SimulatedChannel::lossless();
+        let (codec, compression) = (Identity::default(), Identity::default());
+        let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+        let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+            Address::new(0x08, 0x03),
+            1,
+            &codec,
+            &compression,
+            &mut channel_writer,
+        );
+        let mut reader = TransportReader::<Packet64, _, _, _>::new(
+            Address::new(0x03, 0x08),
+            &codec,
+            &compression,
+            &mut channel_reader,
+        );
+
+        let memory = DummyMemory::new([0u8; 512]);
+        let allocator = DummyAllocator::new(memory);
+        let mut sink = ColdVec::<u8>::with_capacity(512, &allocator).unwrap();
+
+        let data = calibration_table();
+        let mut sender = BulkSender::new(&mut writer);
+        let mut receiver = BulkReceiver::new(&mut reader);
+        block_on(async {
+            // Second chunk is lost
+            let (_, second) = sender.send_chunk(&data, 0).await.unwrap();
+            sender.send_chunk(&data, second * 2).await.unwrap();
+
+            let result = receiver.receive(&mut sink).await;
+            assert!(matches!(
+                result,
+                Err(NetworkError::BulkTransferError(
+                    BulkTransferError::MissingChunk { expected }
+                )) if expected as usize == second
+            ));
+            let progress = receiver.progress().unwrap();
+            assert_eq!(progress.received, second);
+            assert_eq!(progress.total, data.len());
+
+            sender.send_from(&data, progress.received).await.unwrap();
+            assert_eq!(receiver.receive(&mut sink).await.unwrap(), data.len());
+        });
+
+        assert_eq!(receiver.progress(), None);
+        let received: Vec<u8> = sink.iter().collect();
+        assert_eq!(received, data);
+    }
+}
@@ -0,0 +1,194 @@
+/// Optional checksum appended after the whole (compressed) message.
+///
+/// Packet CRCs are short and protect each packet on its own, the trailer
+/// checks the reassembled message as a whole. Trailer is stored little endian.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+pub enum MessageChecksum {
+    #[default]
+    None,
+    /// CRC-16/CCITT-FALSE
+    Crc16,
+    /// CRC-32 (IEEE), the one used by ethernet or zip
+    Crc32,
+}
+
+impl MessageChecksum {
+    /// Size of the biggest trailer in bytes
+    pub const MAX_SIZE: usize = 4;
+
+    /// Size of the trailer in bytes
+    pub const fn size(&self) -> usize {
+        match self {
+            MessageChecksum::None => 0,
+            MessageChecksum::Crc16 => 2,
+            MessageChecksum::Crc32 => 4,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        let mut crc = Crc::new(*self);
+        for byte in data {
+            crc.update(*byte);
+        }
+        crc.finish()
+    }
+
+    /// Check the trailer at the end of the data.
+    /// Returns size of the message without the trailer, `None` when it does not match.
+    pub fn verify(&self, data: &[u8]) -> Option<usize> {
+        let size = data.len().checked_sub(self.size())?;
+
+        let mut trailer = [0u8; Self::MAX_SIZE];
+        trailer[..self.size()].copy_from_slice(&data[size..]);
+        if self.compute(&data[..size]) != u32::from_le_bytes(trailer) {
+            return None;
+        }
+
+        Some(size)
+    }
+
+    /// Pass the data through and append the trailer after the last byte
+    pub fn append<I>(&self, data: I) -> WithChecksum<I>
+    where
+        I: Iterator<Item = u8>,
+    {
+        WithChecksum {
+            data,
+            crc: Crc::new(*self),
+            trailer: None,
+        }
+    }
+}
+
+/// Checksum computed byte by byte
+struct Crc {
+    checksum: MessageChecksum,
+    value: u32,
+}
+
+impl Crc {
+    const CRC16_POLYNOMIAL: u16 = 0x1021;
+    /// Reversed 0x04c11db7
+    const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+
+    fn new(checksum: MessageChecksum) -> Self {
+        let value = match checksum {
+            MessageChecksum::None => 0,
+            MessageChecksum::Crc16 => 0xffff,
+            MessageChecksum::Crc32 => 0xffff_ffff,
+        };
+        Self { checksum, value }
+    }
+
+    fn update(&mut self, byte: u8) {
+        match self.checksum {
+            MessageChecksum::None => {}
+            MessageChecksum::Crc16 => {
+                let mut crc = self.value as u16 ^ ((byte as u16) << 8);
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 {
+                        (crc << 1) ^ Self::CRC16_POLYNOMIAL
+                    } else {
+                        crc << 1
+                    };
+                }
+                self.value = crc as u32;
+            }
+            MessageChecksum::Crc32 => {
+                let mut crc = self.value ^ byte as u32;
+                for _ in 0..8 {
+                    crc = if crc & 0x1 != 0 {
+                        (crc >> 1) ^ Self::CRC32_POLYNOMIAL
+                    } else {
+                        crc >> 1
+                    };
+                }
+                self.value = crc;
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        match self.checksum {
+            MessageChecksum::Crc32 => !self.value,
+            _ => self.value,
+        }
+    }
+}
+
+/// Iterator returned by `MessageChecksum::append`
+pub struct WithChecksum<I> {
+    data: I,
+    crc: Crc,
+    /// Trailer bytes and how many of them were already returned
+    trailer: Option<([u8; 4], usize)>,
+}
+
+impl<I> Iterator for WithChecksum<I>
+where
+    I: Iterator<Item = u8>,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.trailer.is_none() {
+            if let Some(byte) = self.data.next() {
+                self.crc.update(byte);
+                return Some(byte);
+            }
+            self.trailer = Some((self.crc.finish().to_le_bytes(), 0));
+        }
+
+        let (bytes, index) = self.trailer.as_mut().expect("Trailer is set above");
+        if *index >= self.crc.checksum.size() {
+            return None;
+        }
+        *index += 1;
+        Some(bytes[*index - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const DATA: &[u8] = b"123456789";
+
+    #[test]
+    fn test_check_values() {
+        // Standard check values of the algorithms
+        assert_eq!(MessageChecksum::Crc16.compute(DATA), 0x29b1);
+        assert_eq!(MessageChecksum::Crc32.compute(DATA), 0xcbf4_3926);
+        assert_eq!(MessageChecksum::None.compute(DATA), 0);
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        for checksum in [
+            MessageChecksum::None,
+            MessageChecksum::Crc16,
+            MessageChecksum::Crc32,
+        ] {
+            let message: Vec<u8> = checksum.append(DATA.iter().copied()).collect();
+            assert_eq!(message.len(), DATA.len() + checksum.size());
+            assert_eq!(&message[..DATA.len()], DATA);
+            assert_eq!(checksum.verify(&message), Some(DATA.len()));
+        }
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        for checksum in [MessageChecksum::Crc16, MessageChecksum::Crc32] {
+            let message: Vec<u8> = checksum.append(DATA.iter().copied()).collect();
+            for bit in 0..message.len() * 8 {
+                let mut corrupted = message.clone();
+                corrupted[bit / 8] ^= 1 << (bit % 8);
+                assert_eq!(checksum.verify(&corrupted), None);
+            }
+
+            // Message shorter than the trailer itself
+            assert_eq!(checksum.verify(&[0x01]), None);
+        }
+    }
+}
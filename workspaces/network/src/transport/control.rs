@@ -0,0 +1,217 @@
+use sequence_number::SequenceNumberBitmap;
+
+use crate::packet::{PacketFormat, PacketKind, MAX_PAYLOAD_SIZE};
+use crate::transport::profile::CodecProfile;
+use crate::Address;
+
+const CONTROL_TYPE_ACK: u8 = 0x0;
+const CONTROL_TYPE_NACK: u8 = 0x1;
+const CONTROL_TYPE_PROFILE_REQUEST: u8 = 0x2;
+const CONTROL_TYPE_PROFILE_ACCEPT: u8 = 0x3;
+
+/// Control packets used by the acknowledged delivery mode and the codec profile negotiation.
+///
+/// Control packet is always a single `SelfContained` packet marked by
+/// `PacketFormat::CONTROL_PAYLOAD_INDEX`. The stream id of the packet is the id
+/// of the stream the control packet is talking about, profile packets use it
+/// to pair the answer with the request.
+/// First payload byte holds the control type, NACK stores the bitmap
+/// of missing sequence numbers right after it, profile packets the profile id.
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub enum ControlPacket<F: PacketFormat> {
+    /// Whole stream was received
+    Ack { stream_id: F::StreamId },
+    /// Stream is not complete, only the packets in `missing` should be sent again.
+    /// Full bitmap is used when the receiver can't tell which packets are missing.
+    Nack {
+        stream_id: F::StreamId,
+        missing: SequenceNumberBitmap<F::SequenceNumber>,
+    },
+    /// Sender wants both nodes to use the codec profile
+    ProfileRequest {
+        stream_id: F::StreamId,
+        profile: CodecProfile,
+    },
+    /// Receiver switched to the profile, it can be stronger than the requested one
+    ProfileAccept {
+        stream_id: F::StreamId,
+        profile: CodecProfile,
+    },
+}
+
+impl<F: PacketFormat> ControlPacket<F> {
+    pub fn stream_id(&self) -> F::StreamId {
+        match self {
+            ControlPacket::Ack { stream_id } => stream_id.clone(),
+            ControlPacket::Nack { stream_id, .. } => stream_id.clone(),
+            ControlPacket::ProfileRequest { stream_id, .. } => stream_id.clone(),
+            ControlPacket::ProfileAccept { stream_id, .. } => stream_id.clone(),
+        }
+    }
+
+    /// Returns `None` when the packet format can't carry control packets
+    pub fn to_packet(&self, address: &Address) -> Option<F> {
+        let control_payload_index = F::CONTROL_PAYLOAD_INDEX?;
+        let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+
+        match self {
+            ControlPacket::Ack { .. } => {
+                payload[0] = CONTROL_TYPE_ACK;
+            }
+            ControlPacket::Nack { missing, .. } => {
+                payload[0] = CONTROL_TYPE_NACK;
+                missing.write_bytes(&mut payload[1..]);
+            }
+            ControlPacket::ProfileRequest { profile, .. } => {
+                payload[0] = CONTROL_TYPE_PROFILE_REQUEST;
+                payload[1] = profile.id();
+            }
+            ControlPacket::ProfileAccept { profile, .. } => {
+                payload[0] = CONTROL_TYPE_PROFILE_ACCEPT;
+                payload[1] = profile.id();
+            }
+        }
+
+        Some(
+            F::new()
+                .with_kind(PacketKind::SelfContained)
+                .with_stream_id(self.stream_id())
+                .with_source_address(address.local_address)
+                .with_destination_address(address.destination_address)
+                .with_payload_bytes(&payload[..F::PAYLOAD_SIZE])
+                .with_payload_used_index(control_payload_index)
+                .with_updated_crc(),
+        )
+    }
+
+    pub fn from_packet(packet: &F) -> Option<Self> {
+        if !packet.is_control() {
+            return None;
+        }
+
+        let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+        packet.write_payload(&mut payload);
+        let stream_id = packet.stream_id();
+
+        match payload[0] {
+            CONTROL_TYPE_ACK => Some(ControlPacket::Ack { stream_id }),
+            CONTROL_TYPE_NACK => Some(ControlPacket::Nack {
+                stream_id,
+                missing: SequenceNumberBitmap::from_bytes(&payload[1..]),
+            }),
+            CONTROL_TYPE_PROFILE_REQUEST => Some(ControlPacket::ProfileRequest {
+                stream_id,
+                profile: CodecProfile::from_id(payload[1])?,
+            }),
+            CONTROL_TYPE_PROFILE_ACCEPT => Some(ControlPacket::ProfileAccept {
+                stream_id,
+                profile: CodecProfile::from_id(payload[1])?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet128, Packet32, Packet64};
+    use sequence_number::Sequence;
+
+    fn ack_roundtrip<F: PacketFormat>() {
+        let address = Address::new(0x01, 0x02);
+        let control = ControlPacket::<F>::Ack {
+            stream_id: F::StreamId::new(3),
+        };
+
+        let packet = control.to_packet(&address).unwrap();
+        assert!(packet.validate());
+        assert!(packet.is_control());
+        assert_eq!(packet.source_address(), 0x01);
+        assert_eq!(packet.destination_address(), 0x02);
+        assert_eq!(ControlPacket::from_packet(&packet), Some(control));
+    }
+
+    fn nack_roundtrip<F: PacketFormat>() {
+        let address = Address::new(0x01, 0x02);
+        let control = ControlPacket::<F>::Nack {
+            stream_id: F::StreamId::new(5),
+            missing: [1u8, 2, 7, 15, 0]
+                .into_iter()
+                .map(F::SequenceNumber::new)
+                .collect(),
+        };
+
+        let packet = control.to_packet(&address).unwrap();
+        assert_eq!(ControlPacket::from_packet(&packet), Some(control));
+    }
+
+    fn nack_full_roundtrip<F: PacketFormat>() {
+        let address = Address::new(0x01, 0x02);
+        let control = ControlPacket::<F>::Nack {
+            stream_id: F::StreamId::new(5),
+            missing: SequenceNumberBitmap::full(),
+        };
+
+        let packet = control.to_packet(&address).unwrap();
+        assert_eq!(ControlPacket::from_packet(&packet), Some(control));
+    }
+
+    fn profile_roundtrip<F: PacketFormat>() {
+        let address = Address::new(0x01, 0x02);
+        let request = ControlPacket::<F>::ProfileRequest {
+            stream_id: F::StreamId::new(2),
+            profile: CodecProfile::ReedSolomon8,
+        };
+        let accept = ControlPacket::<F>::ProfileAccept {
+            stream_id: F::StreamId::new(2),
+            profile: CodecProfile::ReedSolomonFourToSix,
+        };
+
+        let packet = request.to_packet(&address).unwrap();
+        assert_eq!(ControlPacket::from_packet(&packet), Some(request));
+        let packet = accept.to_packet(&address).unwrap();
+        assert_eq!(ControlPacket::from_packet(&packet), Some(accept));
+    }
+
+    #[test]
+    fn test_ack_roundtrip() {
+        ack_roundtrip::<Packet64>();
+        ack_roundtrip::<Packet128>();
+    }
+
+    #[test]
+    fn test_nack_roundtrip() {
+        nack_roundtrip::<Packet64>();
+        nack_roundtrip::<Packet128>();
+    }
+
+    #[test]
+    fn test_nack_full_roundtrip() {
+        nack_full_roundtrip::<Packet64>();
+        nack_full_roundtrip::<Packet128>();
+    }
+
+    #[test]
+    fn test_profile_roundtrip() {
+        profile_roundtrip::<Packet64>();
+        profile_roundtrip::<Packet128>();
+    }
+
+    #[test]
+    fn test_packet32_has_no_control() {
+        let control = ControlPacket::<Packet32>::Ack {
+            stream_id: <Packet32 as PacketFormat>::StreamId::new(1),
+        };
+        assert_eq!(control.to_packet(&Address::new(0x01, 0x02)), None);
+    }
+
+    #[test]
+    fn test_data_packet_is_not_control() {
+        let packet = Packet64::new()
+            .with_kind(PacketKind::SelfContained)
+            .with_payload_used_index(4)
+            .with_updated_crc();
+        assert_eq!(ControlPacket::from_packet(&packet), None);
+    }
+}
@@ -0,0 +1,197 @@
+use embassy_time::{Duration, Instant};
+
+/// How many delivered streams we remember
+pub const DUPLICATE_FILTER_SIZE: usize = 8;
+
+struct Delivered {
+    source_address: u8,
+    /// Value of the stream id, so the filter does not depend on the packet format
+    stream_id: u8,
+    delivered_at: Instant,
+}
+
+/// Remembers recently delivered streams so resent copies of them are not delivered again.
+///
+/// Stream ids wrap around quickly, so a stream is considered
+/// a duplicate only within the `window` after it was delivered. By default the streams
+/// of a source are also forgotten once it starts another one, otherwise a source sending
+/// more messages than there are stream ids within the `window` would lose them.
+pub struct DuplicateFilter<const SIZE: usize> {
+    delivered: heapless::Vec<Delivered, SIZE>,
+    next_index: usize,
+    window: Duration,
+    /// Forget the streams of a source once it starts another one
+    forget_older_streams: bool,
+    dropped: u32,
+}
+
+impl<const SIZE: usize> DuplicateFilter<SIZE> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            delivered: heapless::Vec::new(),
+            next_index: 0,
+            window,
+            forget_older_streams: true,
+            dropped: 0,
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Copies are usually sent right after each other, so once the source sends another
+    /// stream the older ones won't come again. Forgetting them lets their stream ids
+    /// be reused right away, late copies of them are not dropped then. Enabled by default.
+    pub fn set_forget_older_streams(&mut self, forget: bool) {
+        self.forget_older_streams = forget;
+    }
+
+    /// How many packets of already delivered streams were dropped
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub fn insert(&mut self, source_address: u8, stream_id: u8, now: Instant) {
+        let delivered = Delivered {
+            source_address,
+            stream_id,
+            delivered_at: now,
+        };
+
+        // Overwrite the oldest record when full
+        if self.delivered.is_full() {
+            self.delivered[self.next_index] = delivered;
+            self.next_index = (self.next_index + 1) % SIZE;
+        } else {
+            let _ = self.delivered.push(delivered);
+        }
+    }
+
+    /// Forget the stream, so its copies are not dropped any more
+    pub fn remove(&mut self, source_address: u8, stream_id: u8) {
+        self.retain(|delivered| {
+            delivered.source_address != source_address || delivered.stream_id != stream_id
+        });
+    }
+
+    pub fn contains(&self, source_address: u8, stream_id: u8, now: Instant) -> bool {
+        self.delivered.iter().any(|delivered| {
+            delivered.source_address == source_address
+                && delivered.stream_id == stream_id
+                && now < delivered.delivered_at + self.window
+        })
+    }
+
+    /// Returns `true` and counts the packet as dropped when it belongs to already delivered stream
+    pub fn filter(&mut self, source_address: u8, stream_id: u8, now: Instant) -> bool {
+        let duplicate = self.contains(source_address, stream_id, now);
+        if duplicate {
+            self.dropped = self.dropped.saturating_add(1);
+        } else if self.forget_older_streams {
+            self.forget_source(source_address, stream_id);
+        }
+        duplicate
+    }
+
+    /// Forget other streams of the source
+    fn forget_source(&mut self, source_address: u8, stream_id: u8) {
+        self.retain(|delivered| {
+            delivered.source_address != source_address || delivered.stream_id == stream_id
+        });
+    }
+
+    fn retain(&mut self, keep: impl FnMut(&Delivered) -> bool) {
+        // Keep the oldest record first, so it is overwritten first once full again
+        self.delivered.rotate_left(self.next_index);
+        self.next_index = 0;
+        self.delivered.retain(keep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_within_window() {
+        let mut filter = DuplicateFilter::<2>::new(Duration::from_secs(5));
+        let now = Instant::from_secs(100);
+
+        filter.insert(0x01, 3, now);
+        assert!(filter.filter(0x01, 3, now));
+        assert!(!filter.filter(0x02, 3, now));
+        assert!(!filter.filter(0x01, 4, now));
+        assert_eq!(filter.dropped(), 1);
+
+        // Stream id can be reused after the window
+        assert!(!filter.filter(0x01, 3, now + Duration::from_secs(5)));
+        assert_eq!(filter.dropped(), 1);
+    }
+
+    #[test]
+    fn test_oldest_is_forgotten() {
+        let mut filter = DuplicateFilter::<2>::new(Duration::from_secs(5));
+        let now = Instant::from_secs(100);
+
+        filter.insert(0x01, 1, now);
+        filter.insert(0x01, 2, now);
+        filter.insert(0x01, 3, now);
+
+        assert!(!filter.contains(0x01, 1, now));
+        assert!(filter.contains(0x01, 2, now));
+        assert!(filter.contains(0x01, 3, now));
+    }
+
+    #[test]
+    fn test_late_copy_after_interleaved_streams() {
+        let mut filter = DuplicateFilter::<4>::new(Duration::from_secs(5));
+        filter.set_forget_older_streams(false);
+        let now = Instant::from_secs(100);
+
+        filter.insert(0x01, 3, now);
+        // Other streams of the same source come before the late copy
+        assert!(!filter.filter(0x01, 4, now));
+        filter.insert(0x01, 4, now);
+        assert!(!filter.filter(0x01, 5, now));
+
+        assert!(filter.filter(0x01, 3, now + Duration::from_secs(1)));
+        assert!(filter.filter(0x01, 4, now + Duration::from_secs(1)));
+        assert_eq!(filter.dropped(), 2);
+    }
+
+    #[test]
+    fn test_forget_when_source_moves_on() {
+        let mut filter = DuplicateFilter::<4>::new(Duration::from_secs(5));
+        let now = Instant::from_secs(100);
+
+        filter.insert(0x01, 3, now);
+        filter.insert(0x02, 3, now);
+        assert!(filter.filter(0x01, 3, now));
+
+        // Source 0x01 sends another stream, so its stream id 3 can be reused right away
+        assert!(!filter.filter(0x01, 4, now));
+        assert!(!filter.filter(0x01, 3, now));
+        assert!(filter.contains(0x02, 3, now));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut filter = DuplicateFilter::<2>::new(Duration::from_secs(5));
+        let now = Instant::from_secs(100);
+
+        filter.insert(0x01, 1, now);
+        filter.insert(0x01, 2, now);
+        filter.insert(0x01, 3, now);
+        filter.remove(0x01, 3);
+        assert!(!filter.contains(0x01, 3, now));
+        assert!(filter.contains(0x01, 2, now));
+
+        // Oldest record is still overwritten first
+        filter.insert(0x01, 4, now);
+        filter.insert(0x01, 5, now);
+        assert!(!filter.contains(0x01, 2, now));
+        assert!(filter.contains(0x01, 4, now));
+        assert!(filter.contains(0x01, 5, now));
+    }
+}
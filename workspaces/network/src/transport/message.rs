@@ -0,0 +1,32 @@
+use embassy_time::Instant;
+
+use crate::error::NetworkError;
+use crate::packet::PacketFormat;
+
+/// Information about received message which is not part of the payload
+#[derive(Debug, Clone, PartialEq, defmt::Format)]
+pub struct ReceivedMessage {
+    pub source_address: u8,
+    pub destination_address: u8,
+    /// Value of the stream id, its modulo depends on the packet format
+    pub stream_id: u8,
+    /// How many packets the message consisted of
+    pub packet_count: usize,
+    /// How many packets had errors corrected by the codec
+    pub recovered_packets: usize,
+    /// When the last packet of the message was received
+    pub received_at: Instant,
+}
+
+/// Everything we know about one packet heard in promiscuous mode
+#[derive(Debug, defmt::Format)]
+pub struct SniffedPacket<F: PacketFormat> {
+    pub packet: F,
+    pub crc_valid: bool,
+    /// Codec had to correct some errors in the frame
+    pub recovered: bool,
+    /// Size and metadata of the message completed by this packet
+    pub message: Option<(usize, ReceivedMessage)>,
+    /// Stream of the packet couldn't be received, e.g. `WrongStreamId` or a checksum mismatch
+    pub error: Option<NetworkError>,
+}
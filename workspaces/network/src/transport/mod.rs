@@ -1,32 +1,77 @@
 use crate::error::NetworkError;
+use crate::transport::message::ReceivedMessage;
 
+mod duplicate;
+mod reassembly;
 mod window;
 
+pub mod bulk;
+pub mod checksum;
+pub mod control;
+pub mod message;
+pub mod profile;
+pub mod queue;
 pub mod reader;
+pub mod reliable;
+pub mod statistics;
 pub mod writer;
 
 pub trait TransportReceiver {
-    async fn receive_bytes(&mut self, buffer: &mut [u8]) -> Result<usize, NetworkError>;
+    /// Biggest message which can be received, buffers of `receive_struct` have this size
+    const MAX_MESSAGE_SIZE: usize;
+
+    /// Receive message and information about who sent it
+    async fn receive_bytes_with_metadata(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, ReceivedMessage), NetworkError>;
+
+    async fn receive_bytes(&mut self, buffer: &mut [u8]) -> Result<usize, NetworkError> {
+        self.receive_bytes_with_metadata(buffer)
+            .await
+            .map(|(size, _)| size)
+    }
 
     async fn receive_struct<P>(&mut self) -> Result<P, NetworkError>
     where
         P: for<'a> serde::Deserialize<'a>,
+        [(); Self::MAX_MESSAGE_SIZE]: Sized,
     {
-        let mut buffer = [0u8; 16]; // Packet32 can hold only up to 16bytes
+        let mut buffer = [0u8; Self::MAX_MESSAGE_SIZE];
         let read_bytes = self.receive_bytes(&mut buffer).await?;
 
         postcard::from_bytes(&buffer[..read_bytes]).map_err(NetworkError::ReceiverEncodingError)
     }
+
+    async fn receive_struct_with_metadata<P>(
+        &mut self,
+    ) -> Result<(P, ReceivedMessage), NetworkError>
+    where
+        P: for<'a> serde::Deserialize<'a>,
+        [(); Self::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let mut buffer = [0u8; Self::MAX_MESSAGE_SIZE];
+        let (read_bytes, message) = self.receive_bytes_with_metadata(&mut buffer).await?;
+
+        postcard::from_bytes(&buffer[..read_bytes])
+            .map(|payload| (payload, message))
+            .map_err(NetworkError::ReceiverEncodingError)
+    }
 }
 
 pub trait TransportSender {
+    /// Biggest message which can always be sent, buffer of `send_struct` has this size.
+    /// Room for the message checksum and the compression overhead is already taken out.
+    const MAX_MESSAGE_SIZE: usize;
+
     async fn send_bytes(&mut self, payload: &[u8]) -> Result<usize, NetworkError>;
 
     async fn send_struct<P>(&mut self, payload: &P) -> Result<usize, NetworkError>
     where
         P: serde::Serialize,
+        [(); Self::MAX_MESSAGE_SIZE]: Sized,
     {
-        let mut buffer = [0u8; 16]; // With Packet32 we can encode only up to 16bytes
+        let mut buffer = [0u8; Self::MAX_MESSAGE_SIZE];
 
         let data_slice =
             postcard::to_slice(payload, &mut buffer).map_err(NetworkError::SenderEncodingError)?;
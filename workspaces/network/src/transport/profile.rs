@@ -0,0 +1,741 @@
+use core::cell::Cell;
+
+#[cfg(not(test))]
+use defmt::{error, trace};
+
+#[cfg(test)]
+use log::{error, trace};
+
+use embassy_time::with_timeout;
+
+use crate::error::{DataConstructionError, NetworkError};
+use crate::packet::{PacketFormat, MAX_PACKET_SIZE};
+use crate::transport::control::ControlPacket;
+use crate::transport::reader::TransportReader;
+use crate::transport::reliable::AcknowledgeConfig;
+use crate::transport::statistics::LinkStatistics;
+use crate::transport::writer::TransportWriter;
+use crate::Address;
+
+use codec::chain::Chain;
+use codec::four_to_six::FourToSixBits;
+use codec::reed_solomon::ReedSolomon;
+use codec::{write_vec, Codec, CodecError, CodecSize, DecodeReport, Identity, MAX_ERASURES};
+use physical_layer::error::ReadError;
+use physical_layer::{BaseReader, BaseWriter};
+use sequence_number::Sequence;
+
+/// Link has to be heard for this many frames before the profile is changed
+pub const MIN_PROFILE_FRAMES: u32 = 16;
+
+/// Above this frame error rate the next stronger profile is recommended
+pub const STRONGER_PROFILE_ERROR_RATE: f32 = 0.1;
+
+/// Profile id and its complement in front of every frame
+const PROFILE_HEADER_SIZE: usize = 1;
+
+type ReedSolomon4 = ReedSolomon<4, MAX_PACKET_SIZE>;
+type ReedSolomon8 = ReedSolomon<8, MAX_PACKET_SIZE>;
+type ReedSolomonFourToSix =
+    Chain<ReedSolomon<8, MAX_PACKET_SIZE>, FourToSixBits<{ MAX_PACKET_SIZE + 8 }>, MAX_PACKET_SIZE>;
+
+const MAX_FRAME_SIZE: usize = ProfileCodec::get_encode_const_size(MAX_PACKET_SIZE);
+
+/// Codecs both nodes know about, ordered from the least to the most robust one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum CodecProfile {
+    Identity,
+    ReedSolomon4,
+    ReedSolomon8,
+    /// Reed-Solomon with 8 ecc bytes and 4b6b symbols reporting erasures
+    ReedSolomonFourToSix,
+}
+
+impl CodecProfile {
+    pub const ALL: [CodecProfile; 4] = [
+        CodecProfile::Identity,
+        CodecProfile::ReedSolomon4,
+        CodecProfile::ReedSolomon8,
+        CodecProfile::ReedSolomonFourToSix,
+    ];
+
+    pub const MOST_ROBUST: CodecProfile = CodecProfile::ReedSolomonFourToSix;
+
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get(id as usize).copied()
+    }
+
+    pub fn stronger(self) -> Self {
+        Self::from_id(self.id() + 1).unwrap_or(self)
+    }
+
+    pub fn weaker(self) -> Self {
+        self.id()
+            .checked_sub(1)
+            .and_then(Self::from_id)
+            .unwrap_or(self)
+    }
+
+    /// Profile the other node should use when we receive its frames with these statistics.
+    /// It moves only one step at a time, so a single bad moment doesn't switch to the strongest.
+    ///
+    /// Frames with wrong CRC can't be attributed to any source, so all `crc_failures`
+    /// of the reader count against this link. With more nodes in range it overestimates
+    /// the errors, which can only make the profile stronger.
+    pub fn recommended(self, statistics: &LinkStatistics, crc_failures: u32) -> Self {
+        let frames = statistics.frames_received.saturating_add(crc_failures);
+        if frames < MIN_PROFILE_FRAMES {
+            return self;
+        }
+
+        let errors = statistics.codec_corrections.saturating_add(crc_failures);
+        let error_rate = errors.min(frames) as f32 / frames as f32;
+        if error_rate > STRONGER_PROFILE_ERROR_RATE {
+            self.stronger()
+        } else if error_rate == 0.0 {
+            self.weaker()
+        } else {
+            self
+        }
+    }
+
+    /// Id in the lower nibble and its complement in the upper one,
+    /// a single bit flip can't turn it into another profile
+    fn header(self) -> u8 {
+        let id = self.id() & 0x0f;
+        ((!id & 0x0f) << 4) | id
+    }
+
+    fn from_header(header: u8) -> Option<Self> {
+        let id = header & 0x0f;
+        if header >> 4 != !id & 0x0f {
+            return None;
+        }
+        Self::from_id(id)
+    }
+}
+
+/// Codec which encodes with the selected profile and decodes any of them.
+///
+/// Every frame starts with the header of its profile, so the receiver doesn't
+/// have to know which profile the sender uses. Frames have different sizes,
+/// the reader has to return a frame shorter than the buffer once the transmission ends.
+/// Profile can be changed through a shared reference while the writer uses the codec.
+pub struct ProfileCodec {
+    profile: Cell<CodecProfile>,
+
+    identity: Identity,
+    reed_solomon_4: ReedSolomon4,
+    reed_solomon_8: ReedSolomon8,
+    reed_solomon_four_to_six: ReedSolomonFourToSix,
+}
+
+impl ProfileCodec {
+    pub fn new(profile: CodecProfile) -> Self {
+        Self {
+            profile: Cell::new(profile),
+            identity: Identity::default(),
+            reed_solomon_4: ReedSolomon4::default(),
+            reed_solomon_8: ReedSolomon8::default(),
+            reed_solomon_four_to_six: ReedSolomonFourToSix::default(),
+        }
+    }
+
+    /// Profile used to encode frames
+    pub fn profile(&self) -> CodecProfile {
+        self.profile.get()
+    }
+
+    pub fn set_profile(&self, profile: CodecProfile) {
+        self.profile.set(profile);
+    }
+
+    fn decode_vec(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+    ) -> Result<(heapless::Vec<u8, MAX_PACKET_SIZE>, DecodeReport), CodecError> {
+        write_vec(|output| self.decode_into_with_report(payload, erasures, output))
+    }
+}
+
+impl Default for ProfileCodec {
+    fn default() -> Self {
+        Self::new(CodecProfile::ReedSolomon4)
+    }
+}
+
+fn decode_profile<C: Codec>(
+    codec: &C,
+    frame: &[u8],
+    erasures: &[u8],
+    output: &mut [u8],
+) -> Result<(usize, DecodeReport), CodecError> {
+    // Broken header can point to a profile the frame was not encoded with,
+    // codecs are not ready for frames of unexpected size
+    if frame.len() <= C::get_encode_size(0) || frame.len() > C::get_encode_size(MAX_PACKET_SIZE) {
+        return Err(CodecError::DecodeError);
+    }
+
+    codec.decode_into_with_report(frame, erasures, output)
+}
+
+impl Codec for ProfileCodec {
+    type Encoded<'a> = impl Iterator<Item = u8> + 'a;
+    type Decoded<'a> = impl Iterator<Item = u8> + 'a;
+
+    fn encode<'a>(&self, payload: &'a [u8]) -> Result<Self::Encoded<'a>, CodecError> {
+        let (frame, _) =
+            write_vec::<_, MAX_FRAME_SIZE>(|output| Ok((self.encode_into(payload, output)?, ())))?;
+        Ok(frame.into_iter())
+    }
+
+    fn decode<'a>(&self, payload: &'a [u8]) -> Result<Self::Decoded<'a>, CodecError> {
+        let (decoded, _) = self.decode_vec(payload, &[])?;
+        Ok(decoded.into_iter())
+    }
+
+    fn decode_with_report<'a>(
+        &self,
+        payload: &'a [u8],
+        erasures: &[u8],
+    ) -> Result<(Self::Decoded<'a>, DecodeReport), CodecError> {
+        let (decoded, report) = self.decode_vec(payload, erasures)?;
+        Ok((decoded.into_iter(), report))
+    }
+
+    fn encode_into(&self, payload: &[u8], output: &mut [u8]) -> Result<usize, CodecError> {
+        if payload.len() > MAX_PACKET_SIZE {
+            return Err(CodecError::EncodeError);
+        }
+
+        let profile = self.profile();
+        let (header, frame) = output.split_first_mut().ok_or(CodecError::EncodeError)?;
+        *header = profile.header();
+
+        let size = match profile {
+            CodecProfile::Identity => self.identity.encode_into(payload, frame)?,
+            CodecProfile::ReedSolomon4 => self.reed_solomon_4.encode_into(payload, frame)?,
+            CodecProfile::ReedSolomon8 => self.reed_solomon_8.encode_into(payload, frame)?,
+            CodecProfile::ReedSolomonFourToSix => {
+                self.reed_solomon_four_to_six.encode_into(payload, frame)?
+            }
+        };
+        Ok(PROFILE_HEADER_SIZE + size)
+    }
+
+    fn decode_into_with_report(
+        &self,
+        payload: &[u8],
+        erasures: &[u8],
+        output: &mut [u8],
+    ) -> Result<(usize, DecodeReport), CodecError> {
+        let (header, frame) = payload.split_first().ok_or(CodecError::DecodeError)?;
+        let profile = CodecProfile::from_header(*header).ok_or(CodecError::DecodeError)?;
+
+        // Erasures are positions in the whole frame
+        let mut frame_erasures = [0u8; MAX_ERASURES];
+        let mut erasure_count = 0usize;
+        for position in erasures.iter().filter(|position| **position > 0) {
+            if erasure_count >= MAX_ERASURES {
+                break;
+            }
+            frame_erasures[erasure_count] = position - 1;
+            erasure_count += 1;
+        }
+        let erasures = &frame_erasures[..erasure_count];
+
+        let output_size = output.len().min(MAX_PACKET_SIZE);
+        let output = &mut output[..output_size];
+        match profile {
+            CodecProfile::Identity => decode_profile(&self.identity, frame, erasures, output),
+            CodecProfile::ReedSolomon4 => {
+                decode_profile(&self.reed_solomon_4, frame, erasures, output)
+            }
+            CodecProfile::ReedSolomon8 => {
+                decode_profile(&self.reed_solomon_8, frame, erasures, output)
+            }
+            CodecProfile::ReedSolomonFourToSix => {
+                decode_profile(&self.reed_solomon_four_to_six, frame, erasures, output)
+            }
+        }
+    }
+
+    /// Size of the biggest profile, frames of the others are shorter
+    fn get_encode_size(payload_size: usize) -> usize {
+        Self::get_encode_const_size(payload_size)
+    }
+}
+
+impl const CodecSize for ProfileCodec {
+    fn get_encode_const_size(payload_size: usize) -> usize {
+        let mut size = Identity::get_encode_const_size(payload_size);
+        let reed_solomon_4 = ReedSolomon4::get_encode_const_size(payload_size);
+        if reed_solomon_4 > size {
+            size = reed_solomon_4;
+        }
+        let reed_solomon_8 = ReedSolomon8::get_encode_const_size(payload_size);
+        if reed_solomon_8 > size {
+            size = reed_solomon_8;
+        }
+        let reed_solomon_four_to_six = ReedSolomonFourToSix::get_encode_const_size(payload_size);
+        if reed_solomon_four_to_six > size {
+            size = reed_solomon_four_to_six;
+        }
+
+        PROFILE_HEADER_SIZE + size
+    }
+}
+
+/// Agrees on the codec profile with another node using control packets.
+///
+/// Initiator sends `ProfileRequest` and switches once it gets `ProfileAccept`,
+/// the other node switches before it answers. The answer can contain a stronger profile
+/// when the other node hears us badly. Receivers decode every profile, so a lost
+/// answer only means the two directions use different profiles until the next request.
+/// Negotiation packets are always sent with the most robust profile.
+pub struct ProfileNegotiator<F: PacketFormat> {
+    config: AcknowledgeConfig,
+    request_id: F::StreamId,
+}
+
+impl<F: PacketFormat> ProfileNegotiator<F> {
+    pub fn new(config: AcknowledgeConfig) -> Self {
+        Self {
+            config,
+            request_id: F::StreamId::new(0),
+        }
+    }
+
+    /// Ask the destination to use the profile, returns the profile both nodes use now
+    pub async fn propose<W, R, P, const SLOTS: usize>(
+        &mut self,
+        writer: &mut TransportWriter<'_, F, W, ProfileCodec, P>,
+        reader: &mut TransportReader<'_, F, R, ProfileCodec, P, SLOTS>,
+        destination: u8,
+        profile: CodecProfile,
+    ) -> Result<CodecProfile, NetworkError>
+    where
+        W: BaseWriter,
+        R: BaseReader,
+        P: Codec + ~const CodecSize,
+        [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let stream_id = self.request_id.advance();
+        let request = ControlPacket::ProfileRequest {
+            stream_id: stream_id.clone(),
+            profile,
+        };
+
+        for _ in 0..=self.config.retries {
+            send_control(writer, &request, destination).await?;
+
+            match with_timeout(
+                self.config.timeout,
+                wait_for_accept(reader, destination, &stream_id),
+            )
+            .await
+            {
+                Ok(Ok(accepted)) => {
+                    writer.codec().set_profile(accepted);
+                    reader.reset_link_statistics(destination);
+                    return Ok(accepted);
+                }
+                Ok(Err(NetworkError::ReceiverReaderError(ReadError::TimeoutError))) | Err(_) => {
+                    trace!("Profile request was not answered, sending it again");
+                }
+                Ok(Err(e)) => return Err(e),
+            }
+        }
+
+        error!("Codec profile was not accepted");
+        Err(NetworkError::AcknowledgeTimeout)
+    }
+
+    /// Propose the profile recommended by the statistics of frames received from the destination.
+    /// Nothing is sent when the current profile is fine.
+    pub async fn adapt<W, R, P, const SLOTS: usize>(
+        &mut self,
+        writer: &mut TransportWriter<'_, F, W, ProfileCodec, P>,
+        reader: &mut TransportReader<'_, F, R, ProfileCodec, P, SLOTS>,
+        destination: u8,
+    ) -> Result<CodecProfile, NetworkError>
+    where
+        W: BaseWriter,
+        R: BaseReader,
+        P: Codec + ~const CodecSize,
+        [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let current = writer.codec().profile();
+        let profile = match reader.statistics().get(destination) {
+            Some(statistics) => current.recommended(statistics, reader.statistics().crc_failures()),
+            None => current,
+        };
+        if profile == current {
+            return Ok(current);
+        }
+
+        self.propose(writer, reader, destination, profile).await
+    }
+
+    /// Answer the packet when it is a profile request, returns the profile we switched to.
+    /// Use it with packets from `TransportReader::sniff` to negotiate while receiving messages.
+    pub async fn handle_packet<W, R, P, const SLOTS: usize>(
+        &mut self,
+        writer: &mut TransportWriter<'_, F, W, ProfileCodec, P>,
+        reader: &mut TransportReader<'_, F, R, ProfileCodec, P, SLOTS>,
+        packet: &F,
+    ) -> Result<Option<CodecProfile>, NetworkError>
+    where
+        W: BaseWriter,
+        R: BaseReader,
+        P: Codec + ~const CodecSize,
+        [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let (stream_id, requested) = match ControlPacket::<F>::from_packet(packet) {
+            Some(ControlPacket::ProfileRequest { stream_id, profile }) => (stream_id, profile),
+            _ => return Ok(None),
+        };
+        let source_address = packet.source_address();
+
+        // We hear the other node badly, so it should not use weaker profile than we recommend
+        let profile = match reader.statistics().get(source_address) {
+            Some(statistics) => requested.max(
+                writer
+                    .codec()
+                    .profile()
+                    .recommended(statistics, reader.statistics().crc_failures()),
+            ),
+            None => requested,
+        };
+        trace!(
+            "Switching to codec profile = {:?}, requested = {:?}",
+            profile,
+            requested
+        );
+
+        writer.codec().set_profile(profile);
+        reader.reset_link_statistics(source_address);
+
+        let accept = ControlPacket::ProfileAccept { stream_id, profile };
+        send_control(writer, &accept, source_address).await?;
+        Ok(Some(profile))
+    }
+
+    /// Wait for a profile request and answer it.
+    /// Other packets received meanwhile are dropped.
+    pub async fn respond<W, R, P, const SLOTS: usize>(
+        &mut self,
+        writer: &mut TransportWriter<'_, F, W, ProfileCodec, P>,
+        reader: &mut TransportReader<'_, F, R, ProfileCodec, P, SLOTS>,
+    ) -> Result<CodecProfile, NetworkError>
+    where
+        W: BaseWriter,
+        R: BaseReader,
+        P: Codec + ~const CodecSize,
+        [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        loop {
+            let packet = reader.receive_packet().await?;
+            if let Some(profile) = self.handle_packet(writer, reader, &packet).await? {
+                return Ok(profile);
+            }
+        }
+    }
+}
+
+async fn send_control<F, W, P>(
+    writer: &mut TransportWriter<'_, F, W, ProfileCodec, P>,
+    control: &ControlPacket<F>,
+    destination: u8,
+) -> Result<(), NetworkError>
+where
+    F: PacketFormat,
+    W: BaseWriter,
+    P: Codec,
+    [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    let address = Address::new(writer.address().local_address, destination);
+    let packet = control
+        .to_packet(&address)
+        .ok_or(NetworkError::DataConstructingError(
+            DataConstructionError::UnsupportedControlPacket,
+        ))?;
+
+    let codec = writer.codec();
+    let profile = codec.profile();
+    codec.set_profile(CodecProfile::MOST_ROBUST);
+    let result = writer.send_packet(&packet).await;
+    codec.set_profile(profile);
+
+    result.map(|_| ())
+}
+
+async fn wait_for_accept<F, R, P, const SLOTS: usize>(
+    reader: &mut TransportReader<'_, F, R, ProfileCodec, P, SLOTS>,
+    source_address: u8,
+    stream_id: &F::StreamId,
+) -> Result<CodecProfile, NetworkError>
+where
+    F: PacketFormat,
+    R: BaseReader,
+    P: Codec + ~const CodecSize,
+    [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    loop {
+        let packet = reader.receive_packet().await?;
+        if packet.source_address() != source_address {
+            continue;
+        }
+
+        match ControlPacket::from_packet(&packet) {
+            Some(ControlPacket::ProfileAccept {
+                stream_id: accepted_id,
+                profile,
+            }) if accepted_id == *stream_id => return Ok(profile),
+            _ => trace!("Skipping packet while waiting for profile accept"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet128, Packet64, PacketKind};
+    use crate::tests::init_logging_stdout;
+    use crate::transport::{TransportReceiver, TransportSender};
+
+    use async_std::task::block_on;
+    use embassy_time::Duration;
+    use simulated_channel::{ChannelConfig, NoiseConfig, SimulatedChannel};
+    use std::vec::Vec;
+
+    #[test]
+    fn test_every_profile_roundtrip() {
+        let codec = ProfileCodec::default();
+        let payload: Vec<u8> = (0..MAX_PACKET_SIZE as u8).collect();
+
+        for profile in CodecProfile::ALL {
+            codec.set_profile(profile);
+            let encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+            assert!(encoded.len() <= ProfileCodec::get_encode_size(MAX_PACKET_SIZE));
+
+            // Receiver does not need to know the profile
+            let receiver = ProfileCodec::new(CodecProfile::Identity);
+            let decoded: Vec<_> = receiver.decode(&encoded[..]).unwrap().collect();
+            assert_eq!(decoded, payload, "{:?}", profile);
+        }
+    }
+
+    #[test]
+    fn test_broken_header() {
+        let codec = ProfileCodec::new(CodecProfile::ReedSolomon8);
+        let mut encoded: Vec<_> = codec.encode(&[1u8, 2, 3, 4]).unwrap().collect();
+
+        // Single bit flip changes the id but not its complement
+        encoded[0] ^= 0x01;
+        assert!(codec.decode(&encoded[..]).is_err());
+        assert!(codec.decode(&[]).is_err());
+    }
+
+    #[test]
+    fn test_report_corrections() {
+        let codec = ProfileCodec::new(CodecProfile::ReedSolomon4);
+        let payload = [1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut encoded: Vec<_> = codec.encode(&payload[..]).unwrap().collect();
+        encoded[3] ^= 0xff;
+
+        let (decoded, report) = codec.decode_with_report(&encoded[..], &[]).unwrap();
+        assert_eq!(decoded.collect::<Vec<_>>(), payload);
+        assert_eq!(report.corrected, 1);
+    }
+
+    #[test]
+    fn test_recommended_profile() {
+        let mut statistics = LinkStatistics {
+            frames_received: MIN_PROFILE_FRAMES - 1,
+            codec_corrections: MIN_PROFILE_FRAMES - 1,
+            ..LinkStatistics::default()
+        };
+        // Not enough frames yet
+        assert_eq!(
+            CodecProfile::ReedSolomon4.recommended(&statistics, 0),
+            CodecProfile::ReedSolomon4
+        );
+
+        statistics.frames_received = 20;
+        statistics.codec_corrections = 4;
+        assert_eq!(
+            CodecProfile::ReedSolomon4.recommended(&statistics, 0),
+            CodecProfile::ReedSolomon8
+        );
+        assert_eq!(
+            CodecProfile::MOST_ROBUST.recommended(&statistics, 0),
+            CodecProfile::MOST_ROBUST
+        );
+
+        statistics.codec_corrections = 1;
+        assert_eq!(
+            CodecProfile::ReedSolomon4.recommended(&statistics, 0),
+            CodecProfile::ReedSolomon4
+        );
+
+        statistics.codec_corrections = 0;
+        assert_eq!(
+            CodecProfile::ReedSolomon4.recommended(&statistics, 0),
+            CodecProfile::Identity
+        );
+        assert_eq!(
+            CodecProfile::Identity.recommended(&statistics, 0),
+            CodecProfile::Identity
+        );
+
+        // Broken frames of unknown source count against the link
+        assert_eq!(
+            CodecProfile::Identity.recommended(&statistics, 4),
+            CodecProfile::ReedSolomon4
+        );
+        let statistics = LinkStatistics {
+            frames_received: 1,
+            ..LinkStatistics::default()
+        };
+        assert_eq!(
+            CodecProfile::Identity.recommended(&statistics, MIN_PROFILE_FRAMES),
+            CodecProfile::ReedSolomon4
+        );
+    }
+
+    fn fast_channel(seed: u64) -> SimulatedChannel {
+        SimulatedChannel::new(ChannelConfig {
+            bit_time: std::time::Duration::from_micros(100),
+            read_timeout: std::time::Duration::from_millis(200),
+            ..ChannelConfig::new(NoiseConfig::lossless(), seed)
+        })
+    }
+
+    fn negotiate<F>(requested: CodecProfile, responder_statistics: bool) -> Vec<CodecProfile>
+    where
+        F: PacketFormat,
+        [(); ProfileCodec::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        init_logging_stdout();
+
+        let (forward, backward) = (fast_channel(1), fast_channel(2));
+        let initiator_codec = ProfileCodec::new(CodecProfile::Identity);
+        let responder_codec = ProfileCodec::new(CodecProfile::Identity);
+        let compression = Identity::default();
+        let config = AcknowledgeConfig::new(Duration::from_millis(500), 3);
+
+        let (mut initiator_channel_writer, mut initiator_channel_reader) =
+            (forward.writer(), backward.reader());
+        let (mut responder_channel_reader, mut responder_channel_writer) =
+            (forward.reader(), backward.writer());
+
+        let mut initiator_writer = TransportWriter::<F, _, _, _>::new(
+            Address::new(0x08, 0x03),
+            1,
+            &initiator_codec,
+            &compression,
+            &mut initiator_channel_writer,
+        );
+        let mut initiator_reader = TransportReader::<F, _, _, _>::new(
+            Address::new(0x08, 0x03),
+            &initiator_codec,
+            &compression,
+            &mut initiator_channel_reader,
+        );
+        let mut responder_writer = TransportWriter::<F, _, _, _>::new(
+            Address::new(0x03, 0x08),
+            1,
+            &responder_codec,
+            &compression,
+            &mut responder_channel_writer,
+        );
+        let mut responder_reader = TransportReader::<F, _, _, _>::new(
+            Address::new(0x03, 0x08),
+            &responder_codec,
+            &compression,
+            &mut responder_channel_reader,
+        );
+
+        let mut initiator = ProfileNegotiator::<F>::new(config.clone());
+        let mut responder = ProfileNegotiator::<F>::new(config);
+
+        let payload = vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa];
+        block_on(async {
+            let (proposed, responded) = futures::join!(
+                async {
+                    if responder_statistics {
+                        // Responder hears only broken frames from the initiator
+                        let broken = F::new()
+                            .with_kind(PacketKind::SelfContained)
+                            .with_source_address(0x08)
+                            .with_destination_address(0x03)
+                            .with_payload_used_index(1);
+                        for _ in 0..MIN_PROFILE_FRAMES {
+                            initiator_writer.send_packet(&broken).await.unwrap();
+                        }
+                    }
+                    initiator
+                        .propose(
+                            &mut initiator_writer,
+                            &mut initiator_reader,
+                            0x03,
+                            requested,
+                        )
+                        .await
+                },
+                responder.respond(&mut responder_writer, &mut responder_reader)
+            );
+            let (proposed, responded) = (proposed.unwrap(), responded.unwrap());
+            assert_eq!(initiator_codec.profile(), proposed);
+            assert_eq!(responder_codec.profile(), responded);
+
+            // Both nodes use the agreed profile for the data
+            let mut read_buffer = [0x00u8; 32];
+            let (sent, received) = futures::join!(
+                responder_writer.send_bytes(&payload[..]),
+                initiator_reader.receive_bytes(&mut read_buffer)
+            );
+            sent.unwrap();
+            assert_eq!(&read_buffer[..received.unwrap()], &payload[..]);
+
+            vec![proposed, responded]
+        })
+    }
+
+    #[test]
+    fn test_negotiate_profile() {
+        assert_eq!(
+            negotiate::<Packet64>(CodecProfile::ReedSolomon8, false),
+            vec![CodecProfile::ReedSolomon8, CodecProfile::ReedSolomon8]
+        );
+        assert_eq!(
+            negotiate::<Packet128>(CodecProfile::ReedSolomonFourToSix, false),
+            vec![
+                CodecProfile::ReedSolomonFourToSix,
+                CodecProfile::ReedSolomonFourToSix
+            ]
+        );
+    }
+
+    #[test]
+    fn test_responder_keeps_stronger_profile() {
+        // Responder recommends one step stronger than its current Identity
+        assert_eq!(
+            negotiate::<Packet64>(CodecProfile::Identity, true),
+            vec![CodecProfile::ReedSolomon4, CodecProfile::ReedSolomon4]
+        );
+    }
+}
@@ -0,0 +1,422 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+
+#[cfg(not(test))]
+use defmt::trace;
+
+#[cfg(test)]
+use log::trace;
+
+use crate::error::NetworkError;
+use crate::packet::PacketFormat;
+use crate::transport::writer::TransportWriter;
+
+use codec::{Codec, CodecSize};
+use physical_layer::BaseWriter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+const PRIORITY_LEVELS: usize = 3;
+
+enum SlotState {
+    Empty,
+    Queued {
+        priority: Priority,
+        order: u32,
+    },
+    /// Taken by the runner
+    Sending,
+    /// Result is signalled, the sender frees the slot once it takes it
+    Completed,
+    /// Sender stopped waiting while the message was being sent,
+    /// the runner frees the slot once it is done with it
+    Abandoned,
+}
+
+struct Slot<const SIZE: usize> {
+    state: SlotState,
+    payload: heapless::Vec<u8, SIZE>,
+}
+
+struct QueueState<const SLOTS: usize, const SIZE: usize> {
+    slots: [Slot<SIZE>; SLOTS],
+    /// Messages with the same priority are sent in the order they were queued
+    next_order: u32,
+}
+
+/// Message which is being sent
+struct Transmission<F: PacketFormat> {
+    slot: usize,
+    priority: Priority,
+    packets: F::Packets,
+    next_packet: usize,
+    sent_bytes: usize,
+}
+
+/// Bounded queue of messages waiting for `TransportWriter`.
+///
+/// Messages are sent by `run` in its own task. Message with higher priority
+/// preempts the one being sent at the packet boundary, the receiver reassembles
+/// both streams at the same time. Up to `SLOTS` messages of up to `SIZE` bytes
+/// can be queued.
+pub struct TransmitQueue<M, const SLOTS: usize, const SIZE: usize>
+where
+    M: RawMutex,
+{
+    state: Mutex<M, RefCell<QueueState<SLOTS, SIZE>>>,
+    queued: Signal<M, ()>,
+    completed: [Signal<M, Result<usize, NetworkError>>; SLOTS],
+}
+
+impl<M, const SLOTS: usize, const SIZE: usize> Default for TransmitQueue<M, SLOTS, SIZE>
+where
+    M: RawMutex,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, const SLOTS: usize, const SIZE: usize> TransmitQueue<M, SLOTS, SIZE>
+where
+    M: RawMutex,
+{
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(QueueState {
+                slots: core::array::from_fn(|_| Slot {
+                    state: SlotState::Empty,
+                    payload: heapless::Vec::new(),
+                }),
+                next_order: 0,
+            })),
+            queued: Signal::new(),
+            completed: core::array::from_fn(|_| Signal::new()),
+        }
+    }
+
+    /// Queue the message and wait until it is sent, returns sent bytes.
+    /// When the future is dropped, the message is removed from the queue
+    /// unless it is already being sent.
+    pub async fn send(&self, priority: Priority, payload: &[u8]) -> Result<usize, NetworkError> {
+        let slot = self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let index = state
+                .slots
+                .iter()
+                .position(|slot| matches!(slot.state, SlotState::Empty))
+                .ok_or(NetworkError::TransmitQueueFull)?;
+
+            let order = state.next_order;
+            state.next_order = order.wrapping_add(1);
+
+            let slot = &mut state.slots[index];
+            slot.payload.clear();
+            slot.payload
+                .extend_from_slice(payload)
+                .map_err(|_| NetworkError::MessageTooLarge)?;
+            slot.state = SlotState::Queued { priority, order };
+
+            Ok(index)
+        })?;
+        let _guard = SlotGuard { queue: self, slot };
+        self.queued.signal(());
+
+        self.completed[slot].wait().await
+    }
+
+    /// Send queued messages forever, run it in its own task
+    pub async fn run<F, W, C, P>(&self, writer: &mut TransportWriter<'_, F, W, C, P>)
+    where
+        F: PacketFormat,
+        W: BaseWriter,
+        C: Codec + ~const CodecSize,
+        P: Codec,
+        [(); C::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let mut current: Option<Transmission<F>> = None;
+        // Preempted transmissions, the priority is increasing
+        let mut preempted: heapless::Vec<Transmission<F>, PRIORITY_LEVELS> = heapless::Vec::new();
+
+        loop {
+            let running = current
+                .as_ref()
+                .or(preempted.last())
+                .map(|transmission| transmission.priority);
+            if let Some((slot, priority)) = self.take_next(running) {
+                let packets = self
+                    .state
+                    .lock(|state| writer.create_packets(&state.borrow().slots[slot].payload));
+
+                match packets {
+                    Ok(packets) => {
+                        trace!("Starting message with priority = {:?}", priority);
+                        let transmission = Transmission {
+                            slot,
+                            priority,
+                            packets,
+                            next_packet: 0,
+                            sent_bytes: 0,
+                        };
+                        if let Some(previous) = current.replace(transmission) {
+                            trace!("Preempting message with priority = {:?}", previous.priority);
+                            preempted
+                                .push(previous)
+                                .ok()
+                                .expect("Preempted transmissions have increasing priority");
+                        }
+                    }
+                    Err(e) => self.complete(slot, Err(e)),
+                }
+                continue;
+            }
+
+            let transmission = match current.as_mut() {
+                Some(transmission) => transmission,
+                None => {
+                    match preempted.pop() {
+                        Some(transmission) => current = Some(transmission),
+                        None => self.queued.wait().await,
+                    }
+                    continue;
+                }
+            };
+
+            let packet = transmission.packets[transmission.next_packet];
+            match writer.send_packet(&packet).await {
+                Ok(size) => {
+                    transmission.sent_bytes += size;
+                    transmission.next_packet += 1;
+                    if transmission.next_packet >= transmission.packets.len() {
+                        let (slot, sent_bytes) = (transmission.slot, transmission.sent_bytes);
+                        current = None;
+                        self.complete(slot, Ok(sent_bytes));
+                    }
+                }
+                Err(e) => {
+                    let slot = transmission.slot;
+                    current = None;
+                    self.complete(slot, Err(e));
+                }
+            }
+        }
+    }
+
+    /// Take the oldest message with the highest priority, when it is above the running one
+    fn take_next(&self, running: Option<Priority>) -> Option<(usize, Priority)> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            let (index, priority) = state
+                .slots
+                .iter()
+                .enumerate()
+                .filter_map(|(index, slot)| match slot.state {
+                    SlotState::Queued { priority, order } => Some((index, priority, order)),
+                    _ => None,
+                })
+                .filter(|(_, priority, _)| running.map_or(true, |running| *priority > running))
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)))
+                .map(|(index, priority, _)| (index, priority))?;
+
+            state.slots[index].state = SlotState::Sending;
+            Some((index, priority))
+        })
+    }
+
+    fn complete(&self, slot: usize, result: Result<usize, NetworkError>) {
+        let abandoned = self.state.lock(|state| {
+            let state = &mut state.borrow_mut().slots[slot].state;
+            let abandoned = matches!(state, SlotState::Abandoned);
+            *state = if abandoned {
+                SlotState::Empty
+            } else {
+                SlotState::Completed
+            };
+            abandoned
+        });
+        if !abandoned {
+            self.completed[slot].signal(result);
+        }
+    }
+}
+
+/// Frees the slot when `send` returns or its future is dropped
+struct SlotGuard<'a, M, const SLOTS: usize, const SIZE: usize>
+where
+    M: RawMutex,
+{
+    queue: &'a TransmitQueue<M, SLOTS, SIZE>,
+    slot: usize,
+}
+
+impl<'a, M, const SLOTS: usize, const SIZE: usize> Drop for SlotGuard<'a, M, SLOTS, SIZE>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        self.queue.state.lock(|state| {
+            let state = &mut state.borrow_mut().slots[self.slot].state;
+            *state = match state {
+                SlotState::Sending => SlotState::Abandoned,
+                _ => SlotState::Empty,
+            };
+        });
+        // Result of this message must not be taken by the next one in the slot
+        self.queue.completed[self.slot].reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::Packet64;
+    use crate::tests::init_logging_stdout;
+    use crate::transport::reader::TransportReader;
+    use crate::transport::TransportReceiver;
+    use crate::Address;
+
+    use async_std::task::{block_on, sleep};
+    use codec::Identity;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use futures::future::{select, Either};
+    use futures::pin_mut;
+    use simulated_channel::SimulatedChannel;
+    use std::time::Duration;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_high_priority_preempts() {
+        init_logging_stdout();
+
+        let channel = SimulatedChannel::lossless();
+        let (codec, compression) = (Identity::default(), Identity::default());
+        let (mut channel_writer, mut channel_reader) = (channel.writer(), channel.reader());
+        let mut writer = TransportWriter::<Packet64, _, _, _>::new(
+            Address::new(0x08, 0x03),
+            1,
+            &codec,
+            &compression,
+            &mut channel_writer,
+        );
+        let mut reader = TransportReader::<Packet64, _, _, _>::new(
+            Address::new(0x03, 0x08),
+            &codec,
+            &compression,
+            &mut channel_reader,
+        );
+
+        let queue = TransmitQueue::<NoopRawMutex, 2, 64>::new();
+        let report: Vec<u8> = (0u8..60).collect();
+        let alarm = vec![0xaau8, 0x55];
+
+        block_on(async {
+            let runner = queue.run(&mut writer);
+            let senders = async {
+                let low = queue.send(Priority::Low, &report);
+                let high = async {
+                    // Wait until the report is being sent
+                    while !channel.is_busy() {
+                        sleep(Duration::from_micros(100)).await;
+                    }
+                    queue.send(Priority::High, &alarm).await
+                };
+                futures::join!(low, high)
+            };
+            pin_mut!(runner);
+            pin_mut!(senders);
+
+            match select(runner, senders).await {
+                Either::Right(((low, high), _)) => {
+                    assert!(low.is_ok());
+                    assert!(high.is_ok());
+                }
+                Either::Left(_) => unreachable!("Runner never stops"),
+            }
+        });
+
+        // Alarm is delivered before the report even though it was queued later
+        let mut buffer = [0u8; 64];
+        let size = block_on(reader.receive_bytes(&mut buffer)).unwrap();
+        assert_eq!(&buffer[..size], &alarm[..]);
+        let size = block_on(reader.receive_bytes(&mut buffer)).unwrap();
+        assert_eq!(&buffer[..size], &report[..]);
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let queue = TransmitQueue::<NoopRawMutex, 1, 8>::new();
+
+        block_on(async {
+            let first = queue.send(Priority::Normal, &[0x01]);
+            pin_mut!(first);
+            // Nobody runs the queue, so the first message stays in it
+            assert!(futures::poll!(first.as_mut()).is_pending());
+
+            let second = queue.send(Priority::High, &[0x02]).await;
+            assert!(matches!(second, Err(NetworkError::TransmitQueueFull)));
+
+            let too_big = TransmitQueue::<NoopRawMutex, 1, 8>::new()
+                .send(Priority::Low, &[0u8; 9])
+                .await;
+            assert!(matches!(too_big, Err(NetworkError::MessageTooLarge)));
+        });
+    }
+
+    #[test]
+    fn test_dropped_send_frees_slot() {
+        let queue = TransmitQueue::<NoopRawMutex, 1, 8>::new();
+
+        block_on(async {
+            {
+                let first = queue.send(Priority::Normal, &[0x01]);
+                pin_mut!(first);
+                assert!(futures::poll!(first.as_mut()).is_pending());
+            }
+
+            // Slot of the dropped message can be used again
+            let second = queue.send(Priority::Normal, &[0x02]);
+            pin_mut!(second);
+            assert!(futures::poll!(second.as_mut()).is_pending());
+            assert_eq!(queue.take_next(None), Some((0, Priority::Normal)));
+        });
+        assert_eq!(
+            queue
+                .state
+                .lock(|state| state.borrow().slots[0].payload.clone()),
+            [0x02]
+        );
+    }
+
+    #[test]
+    fn test_dropped_while_sending() {
+        let queue = TransmitQueue::<NoopRawMutex, 1, 8>::new();
+
+        block_on(async {
+            {
+                let first = queue.send(Priority::Normal, &[0x01]);
+                pin_mut!(first);
+                assert!(futures::poll!(first.as_mut()).is_pending());
+                assert_eq!(queue.take_next(None), Some((0, Priority::Normal)));
+            }
+
+            // Runner still holds the slot of the abandoned message
+            let second = queue.send(Priority::Normal, &[0x02]).await;
+            assert!(matches!(second, Err(NetworkError::TransmitQueueFull)));
+
+            queue.complete(0, Ok(1));
+            let third = queue.send(Priority::Normal, &[0x03]);
+            pin_mut!(third);
+            // Result of the abandoned message is not handed to the new one
+            assert!(futures::poll!(third.as_mut()).is_pending());
+        });
+    }
+}
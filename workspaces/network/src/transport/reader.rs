@@ -1,11 +1,18 @@
-use crate::error::NetworkError;
-use crate::packet::{PacketType, PACKET_TYPE_SN_SIZE};
+use crate::error::{DataConstructionError, NetworkError};
+use crate::packet::{PacketFormat, MAX_PACKET_SIZE};
+use crate::transport::checksum::MessageChecksum;
+use crate::transport::duplicate::{DuplicateFilter, DUPLICATE_FILTER_SIZE};
+use crate::transport::message::{ReceivedMessage, SniffedPacket};
+use crate::transport::reassembly::{CompletedStream, ReassemblyTable};
+use crate::transport::statistics::{count, LinkStatisticsTable};
 use crate::transport::window::Window;
 use crate::transport::TransportReceiver;
 use crate::Address;
 
 use codec::{Codec, CodecSize};
+use embassy_time::{with_timeout, Duration, Instant};
 use physical_layer::BaseReader;
+use sequence_number::Sequence;
 
 #[cfg(not(test))]
 use defmt::{error, trace};
@@ -13,17 +20,45 @@ use defmt::{error, trace};
 #[cfg(test)]
 use log::{error, trace};
 
-pub struct TransportReader<'a, R, C, P> {
+/// How long we wait for the rest of the stream after its first packet
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Packet together with information how it was received
+pub(crate) struct ReceivedPacket<F> {
+    pub packet: F,
+    /// Codec had to correct some errors in the frame
+    pub recovered: bool,
+    /// Symbols corrected by the codec, see `codec::DecodeReport`
+    pub corrected_symbols: usize,
+}
+
+/// How long after delivery are resent copies of the message dropped
+pub const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// How many streams can be received at the same time by default
+pub const DEFAULT_REASSEMBLY_SLOTS: usize = 4;
+
+pub struct TransportReader<'a, F, R, C, P, const SLOTS: usize = DEFAULT_REASSEMBLY_SLOTS>
+where
+    F: PacketFormat,
+{
     address: Address,
-    window: Window<{ PACKET_TYPE_SN_SIZE as usize }>,
+    streams: ReassemblyTable<F, SLOTS>,
+    reassembly_timeout: Duration,
+    duplicates: DuplicateFilter<DUPLICATE_FILTER_SIZE>,
+    /// Receive packets for all the addresses
+    promiscuous: bool,
+    checksum: MessageChecksum,
+    statistics: LinkStatisticsTable,
 
     codec: &'a C,
     compression: &'a P,
     reader: &'a mut R,
 }
 
-impl<'a, R, C, P> TransportReader<'a, R, C, P>
+impl<'a, F, R, C, P, const SLOTS: usize> TransportReader<'a, F, R, C, P, SLOTS>
 where
+    F: PacketFormat,
     R: BaseReader,
     C: Codec,
     P: Codec,
@@ -31,102 +66,407 @@ where
     pub fn new(address: Address, codec: &'a C, compression: &'a P, reader: &'a mut R) -> Self {
         Self {
             address,
-            window: Window::new(),
+            streams: ReassemblyTable::new(),
+            reassembly_timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+            duplicates: DuplicateFilter::new(DEFAULT_DUPLICATE_WINDOW),
+            promiscuous: false,
+            checksum: MessageChecksum::None,
+            statistics: LinkStatisticsTable::new(),
 
             codec,
             compression,
             reader,
         }
     }
+
+    /// Incomplete stream is dropped when it is not received within the timeout
+    pub fn with_reassembly_timeout(mut self, timeout: Duration) -> Self {
+        self.reassembly_timeout = timeout;
+        self
+    }
+
+    /// Copies of already delivered message are dropped for this long after the delivery
+    pub fn with_duplicate_window(mut self, window: Duration) -> Self {
+        self.duplicates.set_window(window);
+        self
+    }
+
+    /// Forget delivered streams of a source once it starts another one, so stream ids
+    /// can be reused right away when many messages are sent in a row (e.g. by `BulkSender`).
+    /// Late copies of the older streams are delivered again then. Enabled by default,
+    /// turn it off only when copies of different messages come interleaved and the source
+    /// sends fewer messages than there are stream ids within the duplicate window.
+    pub fn with_forget_older_streams(mut self, forget: bool) -> Self {
+        self.duplicates.set_forget_older_streams(forget);
+        self
+    }
+
+    /// Skip the destination address filter and receive everything on the channel,
+    /// useful for debugging with `sniff`
+    pub fn with_promiscuous_mode(mut self, promiscuous: bool) -> Self {
+        self.promiscuous = promiscuous;
+        self
+    }
+
+    /// Verify checksum of the whole message, the writer has to use the same one
+    pub fn with_message_checksum(mut self, checksum: MessageChecksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// How many packets of already delivered messages were dropped
+    pub fn duplicates_dropped(&self) -> u32 {
+        self.duplicates.dropped()
+    }
+
+    /// Link quality of every source we heard
+    pub fn statistics(&self) -> &LinkStatisticsTable {
+        &self.statistics
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.statistics.clear();
+    }
+
+    /// Forget statistics of one source, e.g. when it starts to use another codec profile.
+    /// Frames with wrong CRC could come from it as well, so they are forgotten too.
+    pub fn reset_link_statistics(&mut self, source_address: u8) {
+        self.statistics.remove(source_address);
+        self.statistics.reset_crc_failures();
+    }
 }
 
-impl<'a, R, C, P> TransportReceiver for TransportReader<'a, R, C, P>
+impl<'a, F, R, C, P, const SLOTS: usize> TransportReader<'a, F, R, C, P, SLOTS>
 where
+    F: PacketFormat,
     R: BaseReader,
     C: Codec + ~const CodecSize,
     P: Codec + ~const CodecSize,
-    [(); C::get_encode_const_size(8)]: Sized,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
 {
-    async fn receive_bytes(&mut self, buffer: &mut [u8]) -> Result<usize, NetworkError> {
-        self.window.clear();
+    pub(crate) fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Window of the stream which is still being received
+    pub(crate) fn stream_window(
+        &self,
+        source_address: u8,
+        stream_id: &F::StreamId,
+    ) -> Option<&Window<F>> {
+        self.streams.get(source_address, stream_id)
+    }
+
+    pub(crate) fn remove_stream(&mut self, source_address: u8, stream_id: &F::StreamId) {
+        self.streams.remove(source_address, stream_id);
+    }
+
+    /// Read frames until there is a valid packet addressed to us.
+    /// Broken frames and packets for other nodes are skipped.
+    pub(crate) async fn receive_packet(&mut self) -> Result<F, NetworkError> {
+        self.receive_packet_with_report()
+            .await
+            .map(|received| received.packet)
+    }
+
+    pub(crate) async fn receive_packet_with_report(
+        &mut self,
+    ) -> Result<ReceivedPacket<F>, NetworkError> {
+        loop {
+            let received = self.receive_frame().await?;
+            let packet = &received.packet;
+
+            if !packet.validate() {
+                error!("Received packet checksum does not match");
+                self.statistics.crc_failure();
+                continue;
+            }
+
+            if !self.is_listening_to(packet.destination_address()) {
+                trace!(
+                    "Received packet for different address = {}. Expected = {}",
+                    packet.destination_address(),
+                    self.address.local_address
+                );
+                continue;
+            }
+
+            self.count_frame(&received);
+            return Ok(received);
+        }
+    }
+
+    /// Read report about every packet heard on the channel.
+    /// When the packet completes some message, the message is copied into the buffer.
+    /// Without promiscuous mode only packets addressed to us are reported.
+    /// Errors of the stream are in the report, only reader errors are returned.
+    pub async fn sniff(&mut self, buffer: &mut [u8]) -> Result<SniffedPacket<F>, NetworkError> {
+        loop {
+            let received = self.receive_frame().await?;
+            let ReceivedPacket {
+                packet, recovered, ..
+            } = received;
+
+            let crc_valid = packet.validate();
+            if !crc_valid {
+                self.statistics.crc_failure();
+            }
+            if !self.is_listening_to(packet.destination_address()) {
+                continue;
+            }
+
+            if crc_valid {
+                self.count_frame(&received);
+            }
+            let (mut message, mut error) = (None, None);
+            if crc_valid && !packet.is_control() {
+                // Broken streams are normal traffic for the analyzer, they are only reported
+                match self.push_packet(packet, recovered) {
+                    Ok(Some(stream)) => match self.write_message(&stream, buffer) {
+                        Ok(size) => message = Some((size, self.create_metadata(&stream))),
+                        Err(err) => error = Some(err),
+                    },
+                    Ok(None) => {}
+                    Err(err) => error = Some(err),
+                }
+            }
+
+            return Ok(SniffedPacket {
+                packet,
+                crc_valid,
+                recovered,
+                message,
+                error,
+            });
+        }
+    }
+
+    fn is_listening_to(&self, destination_address: u8) -> bool {
+        self.promiscuous || self.address.accepts::<F>(destination_address)
+    }
 
+    /// Count the frame to its link, only for valid packets addressed to us
+    fn count_frame(&mut self, received: &ReceivedPacket<F>) {
+        let corrected_symbols = received.corrected_symbols as u32;
+        self.statistics
+            .update(received.packet.source_address(), Instant::now(), |link| {
+                count(&mut link.frames_received);
+                if received.recovered {
+                    count(&mut link.codec_corrections);
+                }
+                link.corrected_symbols = link.corrected_symbols.saturating_add(corrected_symbols);
+            });
+    }
+
+    /// Read and decode one frame, frames which can't be decoded are skipped
+    async fn receive_frame(&mut self) -> Result<ReceivedPacket<F>, NetworkError> {
         loop {
-            // FIXME Beware encoded can be longer than 4bytes
-            // Maximum received size should be up to 4bytes per packet and up to 8packets
-            // so maximum is 32bytes.
-            // But in sender we encode and send each packet one by one
-            // This way we should be receiving only 4 bytes before encoding.
-            // So the number of received bytes is basically how much bytes is needed to encode 4 bytes
-            // Update: Abowe is correct for Packet32 but not Packet64 - changing to 8
-            let mut reader_buffer = [0u8; C::get_encode_const_size(8)];
-
-            let read_size = C::get_encode_size(PacketType::size());
+            // Writer sends every packet as its own frame, so one frame is at most
+            // one packet of the format encoded by the codec
+            let mut reader_buffer = [0u8; C::get_encode_const_size(F::SIZE)];
+
+            let read_size = C::get_encode_size(F::SIZE);
             let received_size = self
                 .reader
                 .read_bytes_buffer(&mut reader_buffer[..read_size])
                 .await
                 .map_err(NetworkError::ReceiverReaderError)?;
 
-            // This should be then data worth of one packet only (4 bytes)
-            let decoded_data_result = self.codec.decode(&reader_buffer[..received_size]);
+            let mut packet_buffer = [0u8; MAX_PACKET_SIZE];
+            let decoded_result = self.codec.decode_into_with_report(
+                &reader_buffer[..received_size],
+                &[],
+                &mut packet_buffer,
+            );
             // FIXME what about decode errors?
             //   We can receive packet multiple times even when the first
             //   transmission is broken
-            if decoded_data_result.is_err() {
-                error!("Decoding data error = {:?}", decoded_data_result.err());
-                continue;
-            }
-            let decoded_data = decoded_data_result.expect("This cant be error after the if");
-            let mut packet_buffer = [0u8; 8]; // One packet is 32bit = 4bytes// Update: Packet64 -> 8
-            for (index, byte) in decoded_data.enumerate() {
-                // FIXME WTF how is the negation possible?
-                // FIXME this is moved to manchester physical layer writer
-                // packet_buffer[index] = !byte;
-                packet_buffer[index] = byte;
-            }
+            let report = match decoded_result {
+                // Reader returns shorter frame when the transmission ends early,
+                // the rest of the packet would be made up by the zero padding
+                Ok((size, _)) if size != F::SIZE => {
+                    error!("Decoded frame has {} bytes, expected = {}", size, F::SIZE);
+                    self.statistics.decode_error();
+                    continue;
+                }
+                Ok((_, report)) => report,
+                Err(err) => {
+                    error!("Decoding data error = {:?}", err);
+                    self.statistics.decode_error();
+                    continue;
+                }
+            };
             // trace!("Received packet buffer = {:#04x?}", packet_buffer);
 
             // And here is our packet (comment for readability)
-            let packet: PacketType = u64::from_le_bytes(packet_buffer).into();
+            let packet = F::from_le_bytes(&packet_buffer);
             trace!("Received packet = {:?}", packet);
-            if packet.destination_address() != self.address.local_address {
-                trace!(
-                    "Received packet for different address = {}. Expected = {}",
-                    packet.destination_address(),
-                    self.address.local_address
-                );
-                continue;
-            }
 
-            if !packet.validate() {
-                error!("Received packet checksum does not match");
-                continue;
+            // Re-encoding the packet can't tell us this, codec with profiles
+            // could have encoded the frame differently than it would now
+            let recovered = report.corrected > 0;
+
+            return Ok(ReceivedPacket {
+                packet,
+                recovered,
+                corrected_symbols: report.corrected,
+            });
+        }
+    }
+
+    /// Put data packet into the window of its stream.
+    /// Returns the stream once it is completely received.
+    pub(crate) fn push_packet(
+        &mut self,
+        packet: F,
+        recovered: bool,
+    ) -> Result<Option<CompletedStream<F>>, NetworkError> {
+        let now = Instant::now();
+        if self.remove_expired_streams(now) > 0 {
+            error!("Dropping stale incomplete stream");
+        }
+
+        let (source_address, stream_id) = (packet.source_address(), packet.stream_id());
+        if self
+            .duplicates
+            .filter(source_address, stream_id.value(), now)
+        {
+            trace!("Dropping packet of already delivered stream");
+            self.statistics
+                .update(source_address, now, |link| count(&mut link.duplicates));
+            return Ok(None);
+        }
+
+        // FIXME maybe when received packet outside of sequence numbers?
+        let completed = self.streams.push_packet(packet, recovered).map_err(|e| {
+            self.statistics.update(source_address, now, |link| match e {
+                NetworkError::DataConstructingError(DataConstructionError::FullWindow) => {
+                    count(&mut link.full_window)
+                }
+                NetworkError::DataConstructingError(DataConstructionError::WrongStreamId) => {
+                    count(&mut link.wrong_stream_id)
+                }
+                _ => {}
+            });
+            e
+        })?;
+        if completed.is_some() {
+            self.duplicates
+                .insert(source_address, stream_id.value(), now);
+        }
+
+        Ok(completed)
+    }
+
+    /// Drop streams not completed in time, returns how many were dropped
+    fn remove_expired_streams(&mut self, now: Instant) -> usize {
+        for source_address in self.streams.expired(now, self.reassembly_timeout) {
+            self.statistics
+                .update(source_address, now, |link| count(&mut link.timeouts));
+        }
+        self.streams.remove_expired(now, self.reassembly_timeout)
+    }
+
+    pub(crate) fn create_metadata(&self, stream: &CompletedStream<F>) -> ReceivedMessage {
+        ReceivedMessage {
+            source_address: stream.source_address,
+            destination_address: stream.destination_address,
+            stream_id: stream
+                .window
+                .stream_id()
+                .expect("Completed stream has at least one packet")
+                .value(),
+            packet_count: stream.window.len(),
+            recovered_packets: stream.recovered_packets,
+            received_at: Instant::now(),
+        }
+    }
+
+    /// Copy completely received message into the buffer
+    pub(crate) fn write_message(
+        &mut self,
+        stream: &CompletedStream<F>,
+        buffer: &mut [u8],
+    ) -> Result<usize, NetworkError> {
+        let mut compressed_buffer = [0u8; F::MAX_MESSAGE_SIZE];
+        stream
+            .window
+            .write_buffer(&mut compressed_buffer)
+            .expect("Completed stream is always completely received.");
+
+        let size = match self.checksum.verify(&compressed_buffer[..stream.size]) {
+            Some(size) => size,
+            None => {
+                error!("Message checksum does not match");
+                // Corrupted message was not delivered, so its resent copy should not be dropped
+                let stream_id = stream
+                    .window
+                    .stream_id()
+                    .expect("Completed stream has at least one packet");
+                self.duplicates
+                    .remove(stream.source_address, stream_id.value());
+                return Err(NetworkError::MessageChecksumMismatch);
             }
+        };
+
+        // Too small buffer is an error instead of a panic
+        let decompress_size = self
+            .compression
+            .decode_into(&compressed_buffer[..size], buffer)
+            .map_err(NetworkError::CodecError)?;
+
+        self.statistics
+            .update(stream.source_address, Instant::now(), |link| {
+                count(&mut link.messages_completed)
+            });
+        Ok(decompress_size)
+    }
+}
+
+impl<'a, F, R, C, P, const SLOTS: usize> TransportReceiver
+    for TransportReader<'a, F, R, C, P, SLOTS>
+where
+    F: PacketFormat,
+    R: BaseReader,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    const MAX_MESSAGE_SIZE: usize = F::MAX_MESSAGE_SIZE;
 
-            // FIXME how long to wait for missing packets?
-            // FIXME maybe when received packet outside of sequence numbers?
-            // FIXME or do we need some stream id and when it change we strip this stream?
-
-            let window_status = self.window.push_packet(packet)?;
-            if let Some(size) = window_status {
-                // FIXME what about this with packet64?
-                let mut compressed_buffer = [0u8; 16]; // Packet can hold up to 16bytes
-                self.window
-                    .write_buffer(&mut compressed_buffer)
-                    .expect("This should not happen as push is called just before.");
-
-                let decompressed = self
-                    .compression
-                    .decode(&compressed_buffer[..size])
-                    .map_err(NetworkError::CodecError)?;
-
-                let mut decompress_size = 0usize;
-                for (index, byte) in decompressed.enumerate() {
-                    buffer[index] = byte;
-                    decompress_size += 1;
+    async fn receive_bytes_with_metadata(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, ReceivedMessage), NetworkError> {
+        loop {
+            // Incomplete streams are kept between calls, only the stale ones are dropped
+            let received = match self.streams.next_deadline(self.reassembly_timeout) {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match with_timeout(remaining, self.receive_packet_with_report()).await {
+                        Ok(received) => received?,
+                        Err(_) => {
+                            error!("Stream was not completed in time, dropping it");
+                            self.remove_expired_streams(Instant::now());
+                            return Err(NetworkError::DataConstructingError(
+                                DataConstructionError::StreamTimeout,
+                            ));
+                        }
+                    }
                 }
+                None => self.receive_packet_with_report().await?,
+            };
+
+            if received.packet.is_control() {
+                trace!("Skipping control packet in non-acknowledged mode");
+                continue;
+            }
 
-                return Ok(decompress_size);
+            if let Some(stream) = self.push_packet(received.packet, received.recovered)? {
+                let size = self.write_message(&stream, buffer)?;
+                return Ok((size, self.create_metadata(&stream)));
             }
         }
     }
@@ -141,7 +481,7 @@ mod tests {
     use std::time::Duration;
     use std::vec::Vec;
 
-    use crate::packet::{PacketKind, PacketType};
+    use crate::packet::{Packet64, PacketKind};
     use crate::simple::receiver::SimpleReceiver;
     use crate::tests::init_logging_stdout;
     use codec::Identity;
@@ -203,7 +543,9 @@ mod tests {
             }
         }
 
-        fn create_receiver(&mut self) -> TransportReader<'_, DummyReader, Identity, Identity> {
+        fn create_receiver(
+            &mut self,
+        ) -> TransportReader<'_, Packet64, DummyReader, Identity, Identity> {
             TransportReader::new(
                 self.address.clone(),
                 &self.codec,
@@ -215,11 +557,11 @@ mod tests {
 
     async fn receiver_environment_single_packet<'a, C, F>(callback: C) -> std::io::Result<()>
     where
-        C: FnOnce(PacketType, DummyReceiver) -> F,
+        C: FnOnce(Packet64, DummyReceiver) -> F,
         F: Future<Output = std::io::Result<()>> + 'a,
     {
         init_logging_stdout();
-        let mut original_packet = PacketType::new()
+        let mut original_packet = Packet64::new()
             .with_kind(PacketKind::SelfContained)
             .with_source_address(0x05)
             .with_destination_address(0x01)
@@ -240,11 +582,11 @@ mod tests {
 
     async fn receiver_environment_three_packets<'a, C, F>(callback: C) -> std::io::Result<()>
     where
-        C: FnOnce(Vec<PacketType>, DummyReceiver) -> F,
+        C: FnOnce(Vec<Packet64>, DummyReceiver) -> F,
         F: Future<Output = std::io::Result<()>> + 'a,
     {
         init_logging_stdout();
-        let original_packet = PacketType::new()
+        let original_packet = Packet64::new()
             .with_kind(PacketKind::SelfContained)
             .with_source_address(0x05)
             .with_destination_address(0x01)
@@ -307,6 +649,245 @@ mod tests {
         .await
     }
 
+    #[async_test]
+    async fn test_receive_interleaved_streams() -> std::io::Result<()> {
+        init_logging_stdout();
+        let packet = |kind, source, sequence_number, payload| {
+            Packet64::new()
+                .with_kind(kind)
+                .with_source_address(source)
+                .with_destination_address(0x01)
+                .with_sequence_number(SequenceNumber::new(sequence_number))
+                .with_payload(payload)
+                .with_payload_used_index(0)
+                .with_updated_crc()
+        };
+
+        let packets = vec![
+            packet(PacketKind::Start, 0x05, 0, 0x01),
+            packet(PacketKind::Start, 0x06, 0, 0x11),
+            packet(PacketKind::End, 0x06, 1, 0x12),
+            packet(PacketKind::End, 0x05, 1, 0x02),
+        ];
+        let mut factory = DummyReceiver::new(
+            packets
+                .iter()
+                .map(|p| p.to_le_bytes())
+                .flatten()
+                .collect::<VecDeque<u8>>(),
+        );
+
+        let mut receiver = factory.create_receiver();
+        let mut receive_buffer = [0u8; 8];
+
+        // Message which completes first is delivered first
+        let read_size = timeout(
+            Duration::from_secs(5),
+            receiver.receive_bytes(&mut receive_buffer),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(&receive_buffer[..read_size], &[0x11, 0x12]);
+
+        let read_size = timeout(
+            Duration::from_secs(5),
+            receiver.receive_bytes(&mut receive_buffer),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(&receive_buffer[..read_size], &[0x01, 0x02]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn test_receive_drops_resent_copies() -> std::io::Result<()> {
+        receiver_environment_single_packet(|original_packet, _| async move {
+            let second_packet = original_packet
+                .with_stream_id(SequenceNumber::new(1))
+                .with_payload(0x1234)
+                .with_updated_crc();
+
+            // Writer sends every packet multiple times
+            let mut factory = DummyReceiver::new(
+                [
+                    original_packet,
+                    original_packet,
+                    original_packet,
+                    second_packet,
+                ]
+                .iter()
+                .map(|p| p.to_le_bytes())
+                .flatten()
+                .collect::<VecDeque<u8>>(),
+            );
+
+            let mut receiver = factory.create_receiver();
+            let mut receive_buffer = [0u8; 8];
+            for expected in [[0xcd, 0xab], [0x34, 0x12]] {
+                let read_size = timeout(
+                    Duration::from_secs(5),
+                    receiver.receive_bytes(&mut receive_buffer),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+                assert_eq!(&receive_buffer[..read_size], &expected);
+            }
+            assert_eq!(receiver.duplicates_dropped(), 2);
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_receive_with_metadata() -> std::io::Result<()> {
+        receiver_environment_three_packets(|_, mut factory| async move {
+            let mut receiver = factory.create_receiver();
+            let mut receive_buffer = [0u8; 8];
+            let (read_size, message) = timeout(
+                Duration::from_secs(3),
+                receiver.receive_bytes_with_metadata(&mut receive_buffer),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+            assert_eq!(read_size, 6);
+            assert_eq!(message.source_address, 0x05);
+            assert_eq!(message.destination_address, 0x01);
+            assert_eq!(message.stream_id, 0);
+            assert_eq!(message.packet_count, 3);
+            assert_eq!(message.recovered_packets, 0);
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_sniff_promiscuous() -> std::io::Result<()> {
+        receiver_environment_single_packet(|original_packet, _| async move {
+            let other_destination = original_packet
+                .with_destination_address(0x07)
+                .with_updated_crc();
+            // Single bit error is always detected by the CRC
+            let broken_crc = original_packet.with_payload(0xabcc);
+
+            let mut factory = DummyReceiver::new(
+                [other_destination, broken_crc]
+                    .iter()
+                    .map(|p| p.to_le_bytes())
+                    .flatten()
+                    .collect::<VecDeque<u8>>(),
+            );
+
+            let mut receiver = factory.create_receiver().with_promiscuous_mode(true);
+            let mut receive_buffer = [0u8; 8];
+
+            let sniffed = timeout(Duration::from_secs(2), receiver.sniff(&mut receive_buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(sniffed.packet.destination_address(), 0x07);
+            assert!(sniffed.crc_valid);
+            let (read_size, message) = sniffed.message.unwrap();
+            assert_eq!(&receive_buffer[..read_size], &[0xcd, 0xab]);
+            assert_eq!(message.destination_address, 0x07);
+
+            let sniffed = timeout(Duration::from_secs(2), receiver.sniff(&mut receive_buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(!sniffed.crc_valid);
+            assert!(sniffed.message.is_none());
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_sniff_reports_stream_errors() -> std::io::Result<()> {
+        receiver_environment_single_packet(|original_packet, _| async move {
+            let second_packet = original_packet
+                .with_stream_id(SequenceNumber::new(1))
+                .with_updated_crc();
+
+            let mut factory = DummyReceiver::new(
+                [original_packet, second_packet]
+                    .iter()
+                    .map(|p| p.to_le_bytes())
+                    .flatten()
+                    .collect::<VecDeque<u8>>(),
+            );
+
+            // Messages without the trailer never match the checksum
+            let mut receiver = factory
+                .create_receiver()
+                .with_promiscuous_mode(true)
+                .with_message_checksum(MessageChecksum::Crc32);
+            let mut receive_buffer = [0u8; 8];
+
+            for stream_id in [0, 1] {
+                let sniffed = timeout(Duration::from_secs(2), receiver.sniff(&mut receive_buffer))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(sniffed.packet.stream_id().value(), stream_id);
+                assert!(sniffed.crc_valid);
+                assert!(sniffed.message.is_none());
+                assert!(matches!(
+                    sniffed.error,
+                    Some(NetworkError::MessageChecksumMismatch)
+                ));
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_receive_incomplete_stream_timeout() -> std::io::Result<()> {
+        receiver_environment_three_packets(|packets, _| async move {
+            // End packet is lost
+            let mut factory = DummyReceiver::new(
+                packets[..2]
+                    .iter()
+                    .map(|p| p.to_le_bytes())
+                    .flatten()
+                    .collect::<VecDeque<u8>>(),
+            );
+
+            let mut receiver = factory
+                .create_receiver()
+                .with_reassembly_timeout(embassy_time::Duration::from_secs(2));
+            let mut receive_buffer = [0u8; 8];
+            let result = timeout(
+                Duration::from_secs(5),
+                receiver.receive_bytes(&mut receive_buffer),
+            )
+            .await
+            .unwrap();
+
+            assert!(matches!(
+                result,
+                Err(NetworkError::DataConstructingError(
+                    DataConstructionError::StreamTimeout
+                ))
+            ));
+            assert!(receiver.streams.is_empty());
+            assert_eq!(receiver.statistics().get(0x05).unwrap().timeouts, 1);
+
+            Ok(())
+        })
+        .await
+    }
+
     #[async_test]
     async fn test_receive_multiple_packets() -> std::io::Result<()> {
         receiver_environment_three_packets(|_, mut factory| async move {
@@ -334,4 +915,112 @@ mod tests {
         })
         .await
     }
+
+    #[async_test]
+    async fn test_receive_checksum_mismatch() -> std::io::Result<()> {
+        receiver_environment_three_packets(|_, mut factory| async move {
+            // Packets are fine, but the last two bytes are not a checksum of the rest
+            let mut receiver = factory
+                .create_receiver()
+                .with_message_checksum(MessageChecksum::Crc16);
+            let mut receive_buffer = [0u8; 8];
+            let result = timeout(
+                Duration::from_secs(2),
+                receiver.receive_bytes(&mut receive_buffer),
+            )
+            .await
+            .unwrap();
+
+            assert!(matches!(result, Err(NetworkError::MessageChecksumMismatch)));
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_receive_drops_short_frame() -> std::io::Result<()> {
+        receiver_environment_single_packet(|original_packet, _| async move {
+            // Packet whose zero padded beginning is the whole valid packet
+            let packet = (0u64..)
+                .map(|payload| {
+                    original_packet
+                        .with_payload(payload)
+                        .with_payload_used_index(0)
+                        .with_updated_crc()
+                })
+                .find(|packet| packet.to_le_bytes()[7] == 0)
+                .unwrap();
+
+            // Transmission ends before the last byte of the frame
+            let mut factory =
+                DummyReceiver::new(packet.to_le_bytes()[..7].iter().copied().collect());
+
+            let mut receiver = factory.create_receiver();
+            let mut receive_buffer = [0u8; 8];
+            let received = timeout(
+                Duration::from_secs(2),
+                receiver.receive_bytes(&mut receive_buffer),
+            )
+            .await;
+            assert!(received.is_err());
+            assert!(receiver.statistics().decode_errors() >= 1);
+            assert_eq!(receiver.statistics().crc_failures(), 0);
+            assert!(receiver.statistics().get(0x05).is_none());
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[async_test]
+    async fn test_link_statistics() -> std::io::Result<()> {
+        receiver_environment_single_packet(|original_packet, _| async move {
+            let broken_crc = original_packet.with_payload(0xabcc);
+            // Valid, but for another node
+            let other_node = original_packet
+                .with_destination_address(0x0e)
+                .with_updated_crc();
+            let mut factory = DummyReceiver::new(
+                [broken_crc, other_node, original_packet, original_packet]
+                    .iter()
+                    .map(|p| p.to_le_bytes())
+                    .flatten()
+                    .collect::<VecDeque<u8>>(),
+            );
+
+            let mut receiver = factory.create_receiver();
+            let mut receive_buffer = [0u8; 8];
+            timeout(
+                Duration::from_secs(3),
+                receiver.receive_bytes(&mut receive_buffer),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            // Broken frame is counted only globally
+            assert_eq!(receiver.statistics().crc_failures(), 1);
+
+            // Resent copy is dropped, the next message never comes
+            let _ = timeout(
+                Duration::from_secs(2),
+                receiver.receive_bytes(&mut receive_buffer),
+            )
+            .await;
+
+            // Frame for another node is not counted at all
+            let statistics = receiver.statistics().get(0x05).unwrap();
+            assert_eq!(statistics.frames_received, 2);
+            assert_eq!(statistics.codec_corrections, 0);
+            assert_eq!(statistics.duplicates, 1);
+            assert_eq!(statistics.messages_completed, 1);
+            assert!(receiver.statistics().get(0x06).is_none());
+
+            receiver.reset_statistics();
+            assert!(receiver.statistics().get(0x05).is_none());
+
+            Ok(())
+        })
+        .await
+    }
 }
@@ -0,0 +1,286 @@
+use embassy_time::{Duration, Instant};
+
+use crate::error::NetworkError;
+use crate::packet::PacketFormat;
+use crate::transport::window::Window;
+
+#[cfg(not(test))]
+use defmt::trace;
+
+#[cfg(test)]
+use log::trace;
+
+/// Completely received stream removed from the table
+pub struct CompletedStream<F>
+where
+    F: PacketFormat,
+{
+    pub source_address: u8,
+    pub destination_address: u8,
+    pub size: usize,
+    /// How many packets had errors corrected by the codec
+    pub recovered_packets: usize,
+    pub window: Window<F>,
+}
+
+struct Slot<F>
+where
+    F: PacketFormat,
+{
+    source_address: u8,
+    recovered_packets: usize,
+    window: Window<F>,
+}
+
+impl<F> Slot<F>
+where
+    F: PacketFormat,
+{
+    fn is_stream(&self, source_address: u8, stream_id: &F::StreamId) -> bool {
+        self.source_address == source_address && self.window.stream_id().as_ref() == Some(stream_id)
+    }
+}
+
+/// Streams being received at the same time, keyed by source address and stream id.
+///
+/// When all the slots are taken the oldest stream is dropped
+/// to make room for the new one.
+pub struct ReassemblyTable<F, const SLOTS: usize>
+where
+    F: PacketFormat,
+{
+    /// In order the streams started, the oldest first
+    slots: heapless::Vec<Slot<F>, SLOTS>,
+}
+
+impl<F, const SLOTS: usize> ReassemblyTable<F, SLOTS>
+where
+    F: PacketFormat,
+{
+    pub fn new() -> Self {
+        Self {
+            slots: heapless::Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn get(&self, source_address: u8, stream_id: &F::StreamId) -> Option<&Window<F>> {
+        self.slots
+            .iter()
+            .find(|slot| slot.is_stream(source_address, stream_id))
+            .map(|slot| &slot.window)
+    }
+
+    pub fn remove(&mut self, source_address: u8, stream_id: &F::StreamId) {
+        self.slots
+            .retain(|slot| !slot.is_stream(source_address, stream_id));
+    }
+
+    /// The earliest instant when some of the incomplete streams expires
+    pub fn next_deadline(&self, timeout: Duration) -> Option<Instant> {
+        self.slots
+            .iter()
+            .filter_map(|slot| slot.window.deadline(timeout))
+            .min()
+    }
+
+    /// Source addresses of the streams which were not completed in time
+    pub fn expired(&self, now: Instant, timeout: Duration) -> impl Iterator<Item = u8> + '_ {
+        self.slots
+            .iter()
+            .filter(move |slot| slot.window.is_expired(now, timeout))
+            .map(|slot| slot.source_address)
+    }
+
+    /// Drop streams which were not completed in time.
+    /// Returns how many streams were dropped.
+    pub fn remove_expired(&mut self, now: Instant, timeout: Duration) -> usize {
+        let before = self.slots.len();
+        self.slots
+            .retain(|slot| !slot.window.is_expired(now, timeout));
+        before - self.slots.len()
+    }
+
+    /// Put packet into the window of its stream, `recovered` tells if the codec corrected it.
+    /// Returns the stream once it is completely received.
+    pub fn push_packet(
+        &mut self,
+        packet: F,
+        recovered: bool,
+    ) -> Result<Option<CompletedStream<F>>, NetworkError> {
+        let source_address = packet.source_address();
+        let destination_address = packet.destination_address();
+        let stream_id = packet.stream_id();
+
+        let index = match self
+            .slots
+            .iter()
+            .position(|slot| slot.is_stream(source_address, &stream_id))
+        {
+            Some(index) => index,
+            None => {
+                if self.slots.is_full() {
+                    self.evict_oldest();
+                }
+
+                let slot = Slot {
+                    source_address,
+                    recovered_packets: 0,
+                    window: Window::new(),
+                };
+                // There is always a free slot after the eviction
+                let _ = self.slots.push(slot);
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        if recovered {
+            slot.recovered_packets += 1;
+        }
+
+        let result = slot.window.push_packet(packet);
+        if !matches!(result, Ok(None)) {
+            // Completed or broken stream does not need the slot anymore
+            let slot = self.slots.remove(index);
+            return result.map(|size| {
+                size.map(|size| CompletedStream {
+                    source_address: slot.source_address,
+                    destination_address,
+                    size,
+                    recovered_packets: slot.recovered_packets,
+                    window: slot.window,
+                })
+            });
+        }
+
+        Ok(None)
+    }
+
+    fn evict_oldest(&mut self) {
+        if !self.slots.is_empty() {
+            let slot = self.slots.remove(0);
+            trace!(
+                "Evicting incomplete stream from source = {}",
+                slot.source_address
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet64, PacketKind};
+    use sequence_number::SequenceNumber;
+
+    fn packet(kind: PacketKind, source: u8, stream_id: u8, sequence_number: u8) -> Packet64 {
+        Packet64::new()
+            .with_kind(kind)
+            .with_source_address(source)
+            .with_sequence_number(SequenceNumber::new(sequence_number))
+            .with_stream_id(SequenceNumber::new(stream_id))
+            .with_payload_bytes(&[source])
+            .with_payload_used_index(0)
+    }
+
+    #[test]
+    fn test_interleaved_streams() {
+        let mut table = ReassemblyTable::<Packet64, 2>::new();
+
+        assert!(table
+            .push_packet(packet(PacketKind::Start, 0x01, 1, 0), false)
+            .unwrap()
+            .is_none());
+        assert!(table
+            .push_packet(packet(PacketKind::Start, 0x02, 1, 5), false)
+            .unwrap()
+            .is_none());
+        assert_eq!(table.len(), 2);
+
+        // Second stream completes first
+        let completed = table
+            .push_packet(packet(PacketKind::End, 0x02, 1, 6), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.source_address, 0x02);
+        assert_eq!(completed.size, 2);
+        assert_eq!(completed.recovered_packets, 0);
+
+        let mut buffer = [0u8; 2];
+        completed.window.write_buffer(&mut buffer).unwrap();
+        assert_eq!(buffer, [0x02, 0x02]);
+
+        let completed = table
+            .push_packet(packet(PacketKind::End, 0x01, 1, 1), false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(completed.source_address, 0x01);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_evict_oldest() {
+        let mut table = ReassemblyTable::<Packet64, 2>::new();
+
+        table
+            .push_packet(packet(PacketKind::Start, 0x01, 1, 0), false)
+            .unwrap();
+        table
+            .push_packet(packet(PacketKind::Start, 0x02, 1, 0), false)
+            .unwrap();
+        table
+            .push_packet(packet(PacketKind::Start, 0x03, 1, 0), false)
+            .unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert!(table.get(0x01, &SequenceNumber::new(1)).is_none());
+        assert!(table.get(0x02, &SequenceNumber::new(1)).is_some());
+        assert!(table.get(0x03, &SequenceNumber::new(1)).is_some());
+
+        // Completing a stream keeps the order of the others
+        table
+            .push_packet(packet(PacketKind::End, 0x02, 1, 1), false)
+            .unwrap()
+            .unwrap();
+        table
+            .push_packet(packet(PacketKind::Start, 0x04, 1, 0), false)
+            .unwrap();
+        table
+            .push_packet(packet(PacketKind::Start, 0x05, 1, 0), false)
+            .unwrap();
+        assert!(table.get(0x03, &SequenceNumber::new(1)).is_none());
+        assert!(table.get(0x04, &SequenceNumber::new(1)).is_some());
+        assert!(table.get(0x05, &SequenceNumber::new(1)).is_some());
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut table = ReassemblyTable::<Packet64, 2>::new();
+        let timeout = Duration::from_secs(1);
+
+        table
+            .push_packet(packet(PacketKind::Start, 0x01, 1, 0), false)
+            .unwrap();
+        let deadline = table.next_deadline(timeout).unwrap();
+
+        assert_eq!(
+            table.remove_expired(deadline - Duration::from_millis(1), timeout),
+            0
+        );
+        assert_eq!(table.remove_expired(deadline, timeout), 1);
+        assert!(table.is_empty());
+        assert_eq!(table.next_deadline(timeout), None);
+    }
+}
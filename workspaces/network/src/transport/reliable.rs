@@ -0,0 +1,559 @@
+#[cfg(not(test))]
+use defmt::{error, trace};
+
+#[cfg(test)]
+use log::{error, trace};
+
+use embassy_time::{with_timeout, Duration};
+
+use crate::error::{DataConstructionError, NetworkError};
+use crate::packet::{PacketFormat, PacketKind};
+use crate::transport::control::ControlPacket;
+use crate::transport::message::ReceivedMessage;
+use crate::transport::reader::{ReceivedPacket, TransportReader};
+use crate::transport::writer::{max_payload_size, TransportWriter};
+use crate::transport::{TransportReceiver, TransportSender};
+use crate::Address;
+
+use codec::{Codec, CodecSize};
+use physical_layer::error::ReadError;
+use physical_layer::{BaseReader, BaseWriter};
+use sequence_number::SequenceNumberBitmap;
+
+#[derive(Clone)]
+pub struct AcknowledgeConfig {
+    /// How long to wait for ACK/NACK (sender) or for missing packets (receiver)
+    pub timeout: Duration,
+    /// How many times we try to retransmit (sender) or ask for retransmission
+    /// without getting any new packet (receiver)
+    pub retries: u8,
+}
+
+impl AcknowledgeConfig {
+    pub fn new(timeout: Duration, retries: u8) -> Self {
+        Self { timeout, retries }
+    }
+}
+
+impl Default for AcknowledgeConfig {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(2), 3)
+    }
+}
+
+/// Sender which waits for the receiver to confirm every message.
+///
+/// Half-duplex node needs a reader as well to receive ACK/NACK packets.
+/// Only the packets marked in the NACK bitmap are sent again, when there is no
+/// answer at all, the whole message is sent again.
+pub struct ReliableTransportWriter<'a, F, W, R, C, P>
+where
+    F: PacketFormat,
+{
+    writer: TransportWriter<'a, F, W, C, P>,
+    reader: TransportReader<'a, F, R, C, P>,
+    config: AcknowledgeConfig,
+}
+
+impl<'a, F, W, R, C, P> ReliableTransportWriter<'a, F, W, R, C, P>
+where
+    F: PacketFormat,
+    W: BaseWriter,
+    R: BaseReader,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    pub fn new(
+        writer: TransportWriter<'a, F, W, C, P>,
+        reader: TransportReader<'a, F, R, C, P>,
+        config: AcknowledgeConfig,
+    ) -> Self {
+        Self {
+            writer,
+            reader,
+            config,
+        }
+    }
+
+    /// Wait for ACK or NACK of the stream, other control packets are skipped
+    async fn wait_for_control(
+        &mut self,
+        stream_id: &F::StreamId,
+    ) -> Result<ControlPacket<F>, NetworkError> {
+        loop {
+            let packet = self.reader.receive_packet().await?;
+            if packet.source_address() != self.writer.address().destination_address {
+                continue;
+            }
+
+            match ControlPacket::from_packet(&packet) {
+                Some(control @ (ControlPacket::Ack { .. } | ControlPacket::Nack { .. }))
+                    if control.stream_id() == *stream_id =>
+                {
+                    return Ok(control)
+                }
+                _ => trace!("Skipping packet while waiting for acknowledgement"),
+            }
+        }
+    }
+}
+
+impl<'a, F, W, R, C, P> TransportSender for ReliableTransportWriter<'a, F, W, R, C, P>
+where
+    F: PacketFormat,
+    W: BaseWriter,
+    R: BaseReader,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    const MAX_MESSAGE_SIZE: usize = max_payload_size::<F, P>();
+
+    async fn send_bytes(&mut self, payload: &[u8]) -> Result<usize, NetworkError> {
+        let packets = self.writer.create_packets(payload)?;
+        let stream_id = match packets.first() {
+            Some(packet) => packet.stream_id(),
+            None => return Ok(0),
+        };
+
+        let mut sent_bytes = 0usize;
+        for packet in packets.iter() {
+            sent_bytes += self.writer.send_packet(packet).await?;
+        }
+
+        for _ in 0..self.config.retries {
+            let missing =
+                match with_timeout(self.config.timeout, self.wait_for_control(&stream_id)).await {
+                    Ok(Ok(ControlPacket::Ack { .. })) => return Ok(sent_bytes),
+                    Ok(Ok(ControlPacket::Nack { missing, .. })) => missing,
+                    Ok(Ok(_))
+                    | Ok(Err(NetworkError::ReceiverReaderError(ReadError::TimeoutError)))
+                    | Err(_) => {
+                        trace!("No acknowledgement received, sending whole message again");
+                        SequenceNumberBitmap::full()
+                    }
+                    Ok(Err(e)) => return Err(e),
+                };
+
+            for packet in packets.iter() {
+                if missing.contains(&packet.sequence_number()) {
+                    sent_bytes += self.writer.send_packet(packet).await?;
+                }
+            }
+        }
+
+        error!("Message was not acknowledged");
+        Err(NetworkError::AcknowledgeTimeout)
+    }
+}
+
+/// Receiver which confirms every received message.
+///
+/// When some packets are missing after the `End` packet or after
+/// the timeout, NACK with the bitmap of missing sequence numbers is sent back.
+pub struct ReliableTransportReader<'a, F, R, W, C, P>
+where
+    F: PacketFormat,
+{
+    reader: TransportReader<'a, F, R, C, P>,
+    writer: TransportWriter<'a, F, W, C, P>,
+    config: AcknowledgeConfig,
+
+    last_acknowledged: Option<(u8, F::StreamId)>,
+}
+
+impl<'a, F, R, W, C, P> ReliableTransportReader<'a, F, R, W, C, P>
+where
+    F: PacketFormat,
+    R: BaseReader,
+    W: BaseWriter,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    pub fn new(
+        reader: TransportReader<'a, F, R, C, P>,
+        writer: TransportWriter<'a, F, W, C, P>,
+        config: AcknowledgeConfig,
+    ) -> Self {
+        Self {
+            reader,
+            writer,
+            config,
+            last_acknowledged: None,
+        }
+    }
+
+    async fn send_control(
+        &mut self,
+        control: ControlPacket<F>,
+        destination: u8,
+    ) -> Result<(), NetworkError> {
+        let address = Address::new(self.reader.address().local_address, destination);
+        let packet = control
+            .to_packet(&address)
+            .ok_or(NetworkError::DataConstructingError(
+                DataConstructionError::UnsupportedControlPacket,
+            ))?;
+        self.writer.send_packet(&packet).await.map(|_| ())
+    }
+
+    fn create_nack(&self, source_address: u8, stream_id: &F::StreamId) -> Option<ControlPacket<F>> {
+        let window = self.reader.stream_window(source_address, stream_id)?;
+        let stream_id = stream_id.clone();
+
+        // Without both ends of the stream ask for the whole message
+        let missing = window
+            .missing_bitmap()
+            .unwrap_or_else(SequenceNumberBitmap::full);
+
+        Some(ControlPacket::Nack { stream_id, missing })
+    }
+
+    /// How many packets of the stream we already have
+    fn received_packets(&self, (source_address, stream_id): &(u8, F::StreamId)) -> usize {
+        self.reader
+            .stream_window(*source_address, stream_id)
+            .map_or(0, |window| window.len())
+    }
+
+    async fn receive_data_packet(&mut self, wait: bool) -> Result<ReceivedPacket<F>, NetworkError> {
+        loop {
+            let received = if wait {
+                with_timeout(
+                    self.config.timeout,
+                    self.reader.receive_packet_with_report(),
+                )
+                .await
+                .map_err(|_| NetworkError::ReceiverReaderError(ReadError::TimeoutError))??
+            } else {
+                self.reader.receive_packet_with_report().await?
+            };
+
+            if !received.packet.is_control() {
+                return Ok(received);
+            }
+        }
+    }
+}
+
+impl<'a, F, R, W, C, P> TransportReceiver for ReliableTransportReader<'a, F, R, W, C, P>
+where
+    F: PacketFormat,
+    R: BaseReader,
+    W: BaseWriter,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    const MAX_MESSAGE_SIZE: usize = F::MAX_MESSAGE_SIZE;
+
+    async fn receive_bytes_with_metadata(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, ReceivedMessage), NetworkError> {
+        // Stream we are currently waiting for
+        let mut receiving: Option<(u8, F::StreamId)> = None;
+        let mut retries = 0u8;
+
+        loop {
+            // We wait for missing packets only for limited time
+            let waiting_for_rest = receiving
+                .as_ref()
+                .map(|(source, stream_id)| self.reader.stream_window(*source, stream_id).is_some())
+                .unwrap_or(false);
+            let ReceivedPacket {
+                packet, recovered, ..
+            } = match self.receive_data_packet(waiting_for_rest).await {
+                Ok(received) => received,
+                Err(NetworkError::ReceiverReaderError(ReadError::TimeoutError))
+                    if waiting_for_rest =>
+                {
+                    let (source_address, stream_id) =
+                        receiving.clone().expect("We wait only for known stream");
+
+                    retries += 1;
+                    if retries > self.config.retries {
+                        error!("Sender did not send missing packets");
+                        self.reader.remove_stream(source_address, &stream_id);
+                        return Err(NetworkError::AcknowledgeTimeout);
+                    }
+
+                    if let Some(nack) = self.create_nack(source_address, &stream_id) {
+                        self.send_control(nack, source_address).await?;
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let stream = (packet.source_address(), packet.stream_id());
+            if self.last_acknowledged.as_ref() == Some(&stream) {
+                // Sender did not receive our ACK and is sending the message again
+                trace!("Received already acknowledged stream, sending ACK again");
+                self.send_control(
+                    ControlPacket::Ack {
+                        stream_id: stream.1,
+                    },
+                    stream.0,
+                )
+                .await?;
+                continue;
+            }
+
+            receiving = Some(stream.clone());
+            let is_end = matches!(packet.kind(), PacketKind::End);
+            let received_before = self.received_packets(&stream);
+
+            if let Some(completed) = self.reader.push_packet(packet, recovered)? {
+                // Corrupted message is not acknowledged, so the sender sends it again
+                let size = self.reader.write_message(&completed, buffer)?;
+
+                self.send_control(
+                    ControlPacket::Ack {
+                        stream_id: stream.1.clone(),
+                    },
+                    stream.0,
+                )
+                .await?;
+                self.last_acknowledged = Some(stream);
+
+                return Ok((size, self.reader.create_metadata(&completed)));
+            }
+
+            // Sender is making progress, only failures in a row count
+            if self.received_packets(&stream) > received_before {
+                retries = 0;
+            }
+
+            if is_end {
+                if let Some(nack) = self.create_nack(stream.0, &stream.1) {
+                    self.send_control(nack, stream.0).await?;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet128, Packet64};
+    use crate::tests::init_logging_stdout;
+
+    use async_std::task::block_on;
+    use codec::Identity;
+    use physical_layer::error::WriterError;
+    use simulated_channel::{ChannelConfig, NoiseConfig, SimulatedChannel};
+    use std::vec::Vec;
+
+    fn fast_channel(noise: NoiseConfig, seed: u64) -> SimulatedChannel {
+        SimulatedChannel::new(ChannelConfig {
+            bit_time: std::time::Duration::from_micros(100),
+            read_timeout: std::time::Duration::from_millis(200),
+            ..ChannelConfig::new(noise, seed)
+        })
+    }
+
+    /// Writer losing chosen frames, it remembers every frame it was asked to send
+    struct DroppingWriter<W> {
+        writer: W,
+        dropped: Vec<usize>,
+        frames: Vec<Vec<u8>>,
+    }
+
+    impl<W: BaseWriter> BaseWriter for DroppingWriter<W> {
+        async fn write_bytes_buffer(&mut self, buffer: &[u8]) -> Result<usize, WriterError> {
+            let frame = self.frames.len();
+            self.frames.push(Vec::from(buffer));
+            if self.dropped.contains(&frame) {
+                return Ok(buffer.len());
+            }
+            self.writer.write_bytes_buffer(buffer).await
+        }
+
+        async fn write_bytes_iterator<I: Iterator<Item = u8>>(
+            &mut self,
+            data: I,
+        ) -> Result<usize, WriterError> {
+            let frame: Vec<u8> = data.collect();
+            self.write_bytes_buffer(&frame).await
+        }
+    }
+
+    fn reliable_transfer<F>(
+        forward: SimulatedChannel,
+        backward: SimulatedChannel,
+    ) -> Option<Vec<u8>>
+    where
+        F: PacketFormat,
+        [(); Identity::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        let payload = [0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa];
+        reliable_transfer_with::<F, _>(&mut forward.writer(), &forward, &backward, &payload)
+    }
+
+    fn reliable_transfer_with<F, W>(
+        sender_writer: &mut W,
+        forward: &SimulatedChannel,
+        backward: &SimulatedChannel,
+        payload: &[u8],
+    ) -> Option<Vec<u8>>
+    where
+        F: PacketFormat,
+        W: BaseWriter,
+        [(); Identity::get_encode_const_size(F::SIZE)]: Sized,
+        [(); F::MAX_MESSAGE_SIZE]: Sized,
+    {
+        init_logging_stdout();
+
+        let codec = Identity::default();
+        let compression = Identity::default();
+        let config = AcknowledgeConfig::new(Duration::from_millis(500), 5);
+
+        let sender_address = Address::new(0x08, 0x03);
+        let receiver_address = Address::new(0x03, 0x08);
+
+        let mut sender_reader = backward.reader();
+        let (mut receiver_reader, mut receiver_writer) = (forward.reader(), backward.writer());
+
+        let mut sender = ReliableTransportWriter::<F, _, _, _, _>::new(
+            TransportWriter::new(
+                sender_address.clone(),
+                1,
+                &codec,
+                &compression,
+                sender_writer,
+            ),
+            TransportReader::new(
+                sender_address.clone(),
+                &codec,
+                &compression,
+                &mut sender_reader,
+            ),
+            config.clone(),
+        );
+        let mut receiver = ReliableTransportReader::<F, _, _, _, _>::new(
+            TransportReader::new(
+                receiver_address.clone(),
+                &codec,
+                &compression,
+                &mut receiver_reader,
+            ),
+            TransportWriter::new(
+                receiver_address.clone(),
+                1,
+                &codec,
+                &compression,
+                &mut receiver_writer,
+            ),
+            config,
+        );
+
+        block_on(async {
+            let mut read_buffer = [0x00u8; 32];
+            let (sent, received) = futures::join!(
+                sender.send_bytes(payload),
+                receiver.receive_bytes(&mut read_buffer)
+            );
+
+            sent.ok()?;
+            let read_bytes = received.ok()?;
+            Some(Vec::from(&read_buffer[..read_bytes]))
+        })
+    }
+
+    #[test]
+    fn test_reliable_lossless() {
+        let result = reliable_transfer::<Packet64>(
+            fast_channel(NoiseConfig::lossless(), 1),
+            fast_channel(NoiseConfig::lossless(), 2),
+        );
+        assert_eq!(
+            result,
+            Some(vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa])
+        );
+    }
+
+    #[test]
+    fn test_reliable_lossless_packet128() {
+        let result = reliable_transfer::<Packet128>(
+            fast_channel(NoiseConfig::lossless(), 1),
+            fast_channel(NoiseConfig::lossless(), 2),
+        );
+        assert_eq!(
+            result,
+            Some(vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa])
+        );
+    }
+
+    #[test]
+    fn test_reliable_lost_packets() {
+        let lossy = NoiseConfig {
+            packet_loss_probability: 0.3,
+            ..NoiseConfig::default()
+        };
+        let (forward, backward) = (fast_channel(lossy.clone(), 3), fast_channel(lossy, 4));
+
+        let result = reliable_transfer::<Packet64>(forward.clone(), backward.clone());
+        assert_eq!(
+            result,
+            Some(vec![0x01u8, 0x02, 0x03, 0x04, 0xff, 0xfe, 0xfd, 0xfc, 0xaa])
+        );
+
+        // Message has two packets, some of them had to be sent again
+        let forward = forward.statistics();
+        assert!(forward.noise.packets_lost > 0);
+        assert!(forward.transmissions > 2);
+    }
+
+    #[test]
+    fn test_reliable_selective_retransmission() {
+        let (forward, backward) = (
+            fast_channel(NoiseConfig::lossless(), 1),
+            fast_channel(NoiseConfig::lossless(), 2),
+        );
+        let mut sender_writer = DroppingWriter {
+            writer: forward.writer(),
+            dropped: vec![1, 3],
+            frames: Vec::new(),
+        };
+
+        // Five packets of Packet64
+        let payload: Vec<u8> = (0..25).collect();
+        let result = reliable_transfer_with::<Packet64, _>(
+            &mut sender_writer,
+            &forward,
+            &backward,
+            &payload,
+        );
+        assert_eq!(result, Some(payload));
+
+        // NACK after the `End` packet asks only for the lost ones
+        let frames = &sender_writer.frames;
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames[5], frames[1]);
+        assert_eq!(frames[6], frames[3]);
+    }
+
+    #[test]
+    fn test_reliable_nothing_acknowledged() {
+        let result = reliable_transfer::<Packet64>(
+            fast_channel(NoiseConfig::lossless(), 1),
+            fast_channel(
+                NoiseConfig {
+                    packet_loss_probability: 1.0,
+                    ..NoiseConfig::default()
+                },
+                2,
+            ),
+        );
+        assert_eq!(result, None);
+    }
+}
@@ -0,0 +1,210 @@
+use embassy_time::Instant;
+
+/// How many sources are tracked by default, the least recently heard one is replaced
+pub const DEFAULT_STATISTICS_SIZE: usize = 8;
+
+/// Counters of everything received from one source address.
+///
+/// Only frames with valid packet CRC addressed to us are counted, the source address
+/// of a broken frame can't be trusted. Broken frames are in `LinkStatisticsTable`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, defmt::Format)]
+pub struct LinkStatistics {
+    /// Valid frames addressed to us
+    pub frames_received: u32,
+    /// Frames the codec had to correct
+    pub codec_corrections: u32,
+    /// Symbols corrected by the codec in all the frames, see `codec::DecodeReport`
+    pub corrected_symbols: u32,
+    /// Packets of already delivered messages
+    pub duplicates: u32,
+    /// Streams not completed within the reassembly timeout
+    pub timeouts: u32,
+    pub full_window: u32,
+    pub wrong_stream_id: u32,
+    /// Messages delivered to the application
+    pub messages_completed: u32,
+}
+
+impl LinkStatistics {
+    /// Part of the frames which had to be corrected, `0.0` is a perfect link
+    pub fn frame_error_rate(&self) -> f32 {
+        if self.frames_received == 0 {
+            return 0.0;
+        }
+
+        self.codec_corrections.min(self.frames_received) as f32 / self.frames_received as f32
+    }
+}
+
+struct Link {
+    source_address: u8,
+    last_seen: Instant,
+    statistics: LinkStatistics,
+}
+
+/// Link statistics of every source heard by the reader
+pub struct LinkStatisticsTable<const SIZE: usize = DEFAULT_STATISTICS_SIZE> {
+    links: heapless::Vec<Link, SIZE>,
+    /// Frames the codec couldn't decode at all, so we don't know who sent them
+    decode_errors: u32,
+    /// Decoded frames with wrong packet CRC, their source address can't be trusted
+    crc_failures: u32,
+}
+
+impl<const SIZE: usize> Default for LinkStatisticsTable<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize> LinkStatisticsTable<SIZE> {
+    pub fn new() -> Self {
+        Self {
+            links: heapless::Vec::new(),
+            decode_errors: 0,
+            crc_failures: 0,
+        }
+    }
+
+    pub fn get(&self, source_address: u8) -> Option<&LinkStatistics> {
+        self.links
+            .iter()
+            .find(|link| link.source_address == source_address)
+            .map(|link| &link.statistics)
+    }
+
+    /// Source address and statistics of every tracked link
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &LinkStatistics)> {
+        self.links
+            .iter()
+            .map(|link| (link.source_address, &link.statistics))
+    }
+
+    pub fn decode_errors(&self) -> u32 {
+        self.decode_errors
+    }
+
+    pub fn crc_failures(&self) -> u32 {
+        self.crc_failures
+    }
+
+    pub fn remove(&mut self, source_address: u8) {
+        self.links
+            .retain(|link| link.source_address != source_address);
+    }
+
+    pub fn clear(&mut self) {
+        self.links.clear();
+        self.decode_errors = 0;
+        self.crc_failures = 0;
+    }
+
+    pub(crate) fn decode_error(&mut self) {
+        self.decode_errors = self.decode_errors.saturating_add(1);
+    }
+
+    pub(crate) fn crc_failure(&mut self) {
+        self.crc_failures = self.crc_failures.saturating_add(1);
+    }
+
+    pub(crate) fn reset_crc_failures(&mut self) {
+        self.crc_failures = 0;
+    }
+
+    /// Update statistics of the source, it starts to be tracked when it isn't yet
+    pub(crate) fn update<U>(&mut self, source_address: u8, now: Instant, update: U)
+    where
+        U: FnOnce(&mut LinkStatistics),
+    {
+        let index = match self
+            .links
+            .iter()
+            .position(|link| link.source_address == source_address)
+        {
+            Some(index) => index,
+            None => {
+                let link = Link {
+                    source_address,
+                    last_seen: now,
+                    statistics: LinkStatistics::default(),
+                };
+                if self.links.is_full() {
+                    let oldest = self
+                        .links
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, link)| link.last_seen)
+                        .map(|(index, _)| index)
+                        .expect("Full table is not empty");
+                    self.links[oldest] = link;
+                    oldest
+                } else {
+                    let _ = self.links.push(link);
+                    self.links.len() - 1
+                }
+            }
+        };
+
+        let link = &mut self.links[index];
+        link.last_seen = now;
+        update(&mut link.statistics);
+    }
+}
+
+/// Add one to the counter without overflowing
+pub(crate) fn count(counter: &mut u32) {
+    *counter = counter.saturating_add(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_time::Duration;
+
+    #[test]
+    fn test_update_and_evict() {
+        let mut table = LinkStatisticsTable::<2>::new();
+        let start = Instant::from_secs(1);
+
+        table.update(0x01, start, |link| count(&mut link.frames_received));
+        table.update(0x02, start + Duration::from_secs(1), |link| {
+            count(&mut link.frames_received);
+            count(&mut link.codec_corrections);
+        });
+        table.update(0x01, start + Duration::from_secs(2), |link| {
+            count(&mut link.frames_received)
+        });
+        assert_eq!(table.get(0x01).unwrap().frames_received, 2);
+        assert_eq!(table.get(0x02).unwrap().frame_error_rate(), 1.0);
+
+        // Source 0x02 was heard the longest time ago
+        table.update(0x03, start + Duration::from_secs(3), |link| {
+            count(&mut link.messages_completed)
+        });
+        assert!(table.get(0x02).is_none());
+        assert_eq!(table.get(0x03).unwrap().messages_completed, 1);
+        assert_eq!(table.iter().count(), 2);
+        table.remove(0x01);
+        assert!(table.get(0x01).is_none());
+        assert_eq!(table.iter().count(), 1);
+
+        table.decode_error();
+        table.crc_failure();
+        assert_eq!(table.decode_errors(), 1);
+        assert_eq!(table.crc_failures(), 1);
+        table.clear();
+        assert_eq!(table.iter().count(), 0);
+        assert_eq!(table.decode_errors(), 0);
+        assert_eq!(table.crc_failures(), 0);
+    }
+
+    #[test]
+    fn test_frame_error_rate() {
+        let mut statistics = LinkStatistics::default();
+        assert_eq!(statistics.frame_error_rate(), 0.0);
+
+        statistics.frames_received = 4;
+        statistics.codec_corrections = 1;
+        assert_eq!(statistics.frame_error_rate(), 0.25);
+    }
+}
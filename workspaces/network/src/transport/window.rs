@@ -1,20 +1,31 @@
 use crate::error::NetworkError::DataConstructingError;
 use crate::error::{DataConstructionError, NetworkError};
-use crate::packet::{PacketKind, PacketType, PACKET_TYPE_SN_SIZE, PACKET_TYPE_STREAM_ID_SIZE};
-use sequence_number::SequenceNumber;
+use crate::packet::{PacketBuffer, PacketFormat, PacketKind, MAX_PAYLOAD_SIZE};
+use embassy_time::{Duration, Instant};
+use sequence_number::{Sequence, SequenceNumberBitmap};
 
-pub struct Window<const SIZE: usize> {
-    buffer: heapless::Vec<PacketType, SIZE>,
+/// Packets of one stream, the window can hold the whole range of sequence numbers.
+pub struct Window<F>
+where
+    F: PacketFormat,
+{
+    buffer: F::Packets,
     base_received: bool,
-    receiving_stream_id: Option<SequenceNumber<{ PACKET_TYPE_STREAM_ID_SIZE }>>,
+    receiving_stream_id: Option<F::StreamId>,
+    /// When the first packet of the current stream was received
+    first_seen: Option<Instant>,
 }
 
-impl<const SIZE: usize> Window<SIZE> {
+impl<F> Window<F>
+where
+    F: PacketFormat,
+{
     pub fn new() -> Self {
         Self {
-            buffer: heapless::Vec::new(),
+            buffer: F::Packets::default(),
             base_received: false,
             receiving_stream_id: None,
+            first_seen: None,
         }
     }
 
@@ -22,14 +33,15 @@ impl<const SIZE: usize> Window<SIZE> {
         self.buffer.clear();
         self.base_received = false;
         self.receiving_stream_id = None;
+        self.first_seen = None;
     }
 
-    pub fn push_packet(&mut self, packet: PacketType) -> Result<Option<usize>, NetworkError> {
+    pub fn push_packet(&mut self, packet: F) -> Result<Option<usize>, NetworkError> {
         // FIXME when to return Error earlier then when the buffer is full?
 
         if let Some(ref v) = self.receiving_stream_id {
             // TODO support comparing reference
-            if packet.stream_id() != v.clone() {
+            if packet.stream_id() != *v {
                 return Err(NetworkError::DataConstructingError(
                     DataConstructionError::WrongStreamId,
                 ));
@@ -37,6 +49,8 @@ impl<const SIZE: usize> Window<SIZE> {
         }
 
         if self.buffer.is_empty() {
+            self.first_seen = Some(Instant::now());
+
             // TODO? || matches!(packet.kind(), PacketKind::End)
             self.buffer.push(packet).map_err(|_| {
                 NetworkError::DataConstructingError(DataConstructionError::FullWindow)
@@ -97,6 +111,71 @@ impl<const SIZE: usize> Window<SIZE> {
         Ok(None)
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// How many packets of the stream are in the window
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Instant after which the incomplete stream should be dropped.
+    /// `None` when the window is empty.
+    pub fn deadline(&self, timeout: Duration) -> Option<Instant> {
+        self.first_seen.map(|first_seen| first_seen + timeout)
+    }
+
+    pub fn is_expired(&self, now: Instant, timeout: Duration) -> bool {
+        self.deadline(timeout)
+            .map(|deadline| now >= deadline)
+            .unwrap_or(false)
+    }
+
+    /// Stream id of the packets currently held in the window
+    pub fn stream_id(&self) -> Option<F::StreamId> {
+        self.buffer.first().map(|packet| packet.stream_id())
+    }
+
+    /// Bitmap of sequence numbers missing between the `Start` and the `End` packet.
+    /// Returns `None` when we did not receive both of them yet,
+    /// so we can't tell which packets are missing.
+    pub fn missing_bitmap(&self) -> Option<SequenceNumberBitmap<F::SequenceNumber>> {
+        if self.buffer.len() < 2 || self.get_base_sequence_number().is_none() {
+            return None;
+        }
+        if !matches!(
+            self.buffer
+                .last()
+                .expect("There should be at least two elements.")
+                .kind(),
+            PacketKind::End
+        ) {
+            return None;
+        }
+
+        let mut missing = SequenceNumberBitmap::new();
+        let mut packets = self.buffer.iter();
+        let mut prev_sequence_number = packets
+            .next()
+            .expect("There is at least one packet")
+            .sequence_number();
+
+        for packet in packets {
+            let sequence_number = packet.sequence_number();
+            let mut expected = prev_sequence_number.clone();
+            expected.advance();
+
+            while expected != sequence_number {
+                missing.insert(&expected.advance());
+            }
+
+            prev_sequence_number = sequence_number;
+        }
+
+        Some(missing)
+    }
+
     pub fn write_buffer(&self, buffer: &mut [u8]) -> Result<(), ()> {
         if !self.is_completely_received() {
             return Err(());
@@ -105,7 +184,8 @@ impl<const SIZE: usize> Window<SIZE> {
         let mut index = 0usize;
         for packet in self.buffer.iter() {
             let used_bytes_len = packet.payload_used_index() as usize + 1;
-            let all_bytes = packet.payload().to_le_bytes();
+            let mut all_bytes = [0u8; MAX_PAYLOAD_SIZE];
+            packet.write_payload(&mut all_bytes);
             let bytes = &all_bytes[..used_bytes_len];
 
             for packet_index in 0usize..used_bytes_len {
@@ -117,7 +197,7 @@ impl<const SIZE: usize> Window<SIZE> {
         Ok(())
     }
 
-    fn get_base_sequence_number(&self) -> Option<SequenceNumber<PACKET_TYPE_SN_SIZE>> {
+    fn get_base_sequence_number(&self) -> Option<F::SequenceNumber> {
         if matches!(self.buffer[0].kind(), PacketKind::Start) {
             Some(self.buffer[0].sequence_number())
         } else {
@@ -172,3 +252,117 @@ impl<const SIZE: usize> Window<SIZE> {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{Packet128, Packet32, Packet64};
+    use core::mem::size_of;
+    use std::vec::Vec;
+
+    fn packet<F: PacketFormat>(kind: PacketKind, sequence_number: u8) -> F {
+        F::new()
+            .with_kind(kind)
+            .with_sequence_number(F::SequenceNumber::new(sequence_number))
+            .with_stream_id(F::StreamId::new(1))
+            .with_payload_used_index(0)
+    }
+
+    fn missing<F>(window: &Window<F>) -> Option<Vec<u8>>
+    where
+        F: PacketFormat,
+    {
+        window
+            .missing_bitmap()
+            .map(|missing| missing.iter().map(|v| v.value()).collect())
+    }
+
+    fn missing_bitmap<F>()
+    where
+        F: PacketFormat,
+    {
+        let mut window = Window::<F>::new();
+
+        window.push_packet(packet(PacketKind::Start, 2)).unwrap();
+        assert_eq!(missing(&window), None);
+
+        window.push_packet(packet(PacketKind::End, 6)).unwrap();
+        assert_eq!(missing(&window), Some(vec![3, 4, 5]));
+
+        window.push_packet(packet(PacketKind::Continue, 4)).unwrap();
+        assert_eq!(missing(&window), Some(vec![3, 5]));
+        assert_eq!(window.stream_id(), Some(F::StreamId::new(1)));
+
+        window.push_packet(packet(PacketKind::Continue, 3)).unwrap();
+        assert_eq!(
+            window.push_packet(packet(PacketKind::Continue, 5)).unwrap(),
+            Some(5)
+        );
+        assert_eq!(missing(&window), Some(vec![]));
+    }
+
+    #[test]
+    fn test_missing_bitmap() {
+        missing_bitmap::<Packet32>();
+        missing_bitmap::<Packet64>();
+        missing_bitmap::<Packet128>();
+    }
+
+    #[test]
+    fn test_expiration() {
+        let mut window = Window::<Packet64>::new();
+        let timeout = Duration::from_secs(5);
+        assert_eq!(window.deadline(timeout), None);
+        assert!(!window.is_expired(Instant::now(), timeout));
+
+        window.push_packet(packet(PacketKind::Start, 2)).unwrap();
+        let deadline = window.deadline(timeout).unwrap();
+        assert!(!window.is_expired(deadline - Duration::from_millis(1), timeout));
+        assert!(window.is_expired(deadline, timeout));
+
+        // Deadline is counted from the first packet of the stream
+        window.push_packet(packet(PacketKind::Continue, 3)).unwrap();
+        assert_eq!(window.deadline(timeout), Some(deadline));
+
+        window.clear();
+        assert_eq!(window.deadline(timeout), None);
+    }
+
+    #[test]
+    fn test_missing_bitmap_without_start() {
+        let mut window = Window::<Packet64>::new();
+
+        window.push_packet(packet(PacketKind::Continue, 3)).unwrap();
+        window.push_packet(packet(PacketKind::End, 5)).unwrap();
+        assert_eq!(missing(&window), None);
+    }
+
+    #[test]
+    fn test_window_size() {
+        assert_eq!(Packet32::WINDOW_SIZE, 8);
+        assert_eq!(Packet64::WINDOW_SIZE, 16);
+        assert_eq!(Packet128::WINDOW_SIZE, 32);
+    }
+
+    fn fill_window<F: PacketFormat>() {
+        let mut window = Window::<F>::new();
+        window.push_packet(packet(PacketKind::Start, 0)).unwrap();
+        for sequence_number in 1..F::WINDOW_SIZE as u8 {
+            window
+                .push_packet(packet(PacketKind::Continue, sequence_number))
+                .unwrap();
+        }
+        assert_eq!(window.len(), F::WINDOW_SIZE);
+    }
+
+    #[test]
+    fn test_storage_sized_by_format() {
+        fill_window::<Packet32>();
+        fill_window::<Packet64>();
+        fill_window::<Packet128>();
+
+        // Small formats don't pay for the window of the largest one
+        assert!(size_of::<Window<Packet32>>() < size_of::<Window<Packet64>>());
+        assert!(size_of::<Window<Packet64>>() < size_of::<Window<Packet128>>());
+    }
+}
@@ -4,29 +4,44 @@ use defmt::{error, trace};
 #[cfg(test)]
 use log::{error, trace};
 
-use crate::error::NetworkError;
-use crate::packet::{PACKET_TYPE_SN_SIZE, PACKET_TYPE_STREAM_ID_SIZE};
+use crate::error::{DataConstructionError, NetworkError};
+use crate::packet::{PacketBuffer, PacketFormat, MAX_PACKET_SIZE};
 use crate::packet_builder::PacketBuilder;
+use crate::transport::checksum::MessageChecksum;
 use crate::transport::TransportSender;
 use crate::Address;
 
-use codec::Codec;
+use codec::{Codec, CodecSize};
 use physical_layer::BaseWriter;
-use sequence_number::SequenceNumber;
+use sequence_number::Sequence;
 
-pub struct TransportWriter<'a, W, C, P> {
+/// Biggest message which always fits into one window of the format, whatever the message
+/// checksum is and however the compression makes it bigger. `F::MAX_MESSAGE_SIZE` is the
+/// capacity of the window, messages which compress well can be bigger than this.
+pub const fn max_payload_size<F: PacketFormat, P: ~const CodecSize>() -> usize {
+    let capacity = F::MAX_MESSAGE_SIZE.saturating_sub(MessageChecksum::MAX_SIZE);
+    let mut size = capacity;
+    while size > 0 && P::get_encode_const_size(size) > capacity {
+        size -= 1;
+    }
+    size
+}
+
+pub struct TransportWriter<'a, F: PacketFormat, W, C, P> {
     address: Address,
-    sequence_number: SequenceNumber<PACKET_TYPE_SN_SIZE>,
-    stream_id: SequenceNumber<PACKET_TYPE_STREAM_ID_SIZE>,
+    sequence_number: F::SequenceNumber,
+    stream_id: F::StreamId,
     resend: u8,
+    checksum: MessageChecksum,
 
     compression: &'a P,
     codec: &'a C,
     writer: &'a mut W,
 }
 
-impl<'a, W, C, P> TransportWriter<'a, W, C, P>
+impl<'a, F, W, C, P> TransportWriter<'a, F, W, C, P>
 where
+    F: PacketFormat,
     W: BaseWriter,
     C: Codec,
     P: Codec,
@@ -40,49 +55,111 @@ where
     ) -> Self {
         Self {
             address,
-            sequence_number: SequenceNumber::new(0),
-            stream_id: SequenceNumber::new(0),
+            sequence_number: F::SequenceNumber::new(0),
+            stream_id: F::StreamId::new(0),
             resend,
+            checksum: MessageChecksum::None,
             compression,
             codec,
             writer,
         }
     }
+
+    /// Append checksum of the whole message, the reader has to use the same one
+    pub fn with_message_checksum(mut self, checksum: MessageChecksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    pub(crate) fn address(&self) -> &Address {
+        &self.address
+    }
+
+    pub(crate) fn codec(&self) -> &'a C {
+        self.codec
+    }
 }
 
-impl<'a, W, C, P> TransportSender for TransportWriter<'a, W, C, P>
+impl<'a, F, W, C, P> TransportWriter<'a, F, W, C, P>
 where
+    F: PacketFormat,
     W: BaseWriter,
-    C: Codec,
+    C: Codec + ~const CodecSize,
     P: Codec,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
 {
-    async fn send_bytes(&mut self, payload: &[u8]) -> Result<usize, NetworkError> {
-        let mut sent_bytes = 0usize;
+    /// Compress the payload, append the checksum and split it into packets of a new stream.
+    /// Fails when the message does not fit into one window.
+    pub(crate) fn create_packets(&mut self, payload: &[u8]) -> Result<F::Packets, NetworkError> {
+        // Compressed message bigger than this won't fit into the window anyway
+        let mut compressed = [0u8; F::MAX_MESSAGE_SIZE];
+        let compressed_size = self
+            .compression
+            .encode_into(payload, &mut compressed)
+            .map_err(NetworkError::CodecError)?;
 
-        let packet_builder = PacketBuilder::new(
+        let packet_builder = PacketBuilder::<F, _, _>::new(
             &self.address,
             &mut self.sequence_number,
             self.stream_id.advance(),
-            self.compression
-                .encode(payload)
-                .map_err(NetworkError::CodecError)?,
+            self.checksum
+                .append(compressed[..compressed_size].iter().copied()),
         );
 
+        let mut packets = F::Packets::default();
         for packet in packet_builder {
-            trace!("Sending packet = {:?}", packet);
-            let data = packet.to_le_bytes();
-            // trace!("Writing buffer = {:#04x}", data);
-            for _ in 0..self.resend {
-                // TODO don't re-encode the same data multiple times
-                // When encoder raise an error we should just stop, as the same data won't be sent at all
-                let encoded_data = self.codec.encode(&data).map_err(NetworkError::CodecError)?;
-                // FIXME we compute same packet multiple times in retransmision
-                sent_bytes += self
-                    .writer
-                    .write_bytes_iterator(encoded_data)
-                    .await
-                    .map_err(NetworkError::SenderWriterError)?
-            }
+            packets.push(packet).map_err(|_| {
+                NetworkError::DataConstructingError(DataConstructionError::FullWindow)
+            })?;
+        }
+
+        Ok(packets)
+    }
+
+    /// Encode single packet once and write it `resend` times
+    pub(crate) async fn send_packet(&mut self, packet: &F) -> Result<usize, NetworkError> {
+        trace!("Sending packet = {:?}", packet);
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        let size = packet.write_le_bytes(&mut buffer);
+
+        // When encoder raise an error we should just stop, as the same data won't be sent at all
+        let mut frame = [0u8; C::get_encode_const_size(F::SIZE)];
+        let frame_size = self
+            .codec
+            .encode_into(&buffer[..size], &mut frame)
+            .map_err(NetworkError::CodecError)?;
+
+        let mut sent_bytes = 0usize;
+        for _ in 0..self.resend {
+            sent_bytes += self
+                .writer
+                .write_bytes_buffer(&frame[..frame_size])
+                .await
+                .map_err(NetworkError::SenderWriterError)?
+        }
+
+        Ok(sent_bytes)
+    }
+}
+
+impl<'a, F, W, C, P> TransportSender for TransportWriter<'a, F, W, C, P>
+where
+    F: PacketFormat,
+    W: BaseWriter,
+    C: Codec + ~const CodecSize,
+    P: Codec + ~const CodecSize,
+    [(); C::get_encode_const_size(F::SIZE)]: Sized,
+    [(); F::MAX_MESSAGE_SIZE]: Sized,
+{
+    const MAX_MESSAGE_SIZE: usize = max_payload_size::<F, P>();
+
+    async fn send_bytes(&mut self, payload: &[u8]) -> Result<usize, NetworkError> {
+        let mut sent_bytes = 0usize;
+
+        let packets = self.create_packets(payload)?;
+        for packet in packets.iter() {
+            sent_bytes += self.send_packet(packet).await?;
         }
 
         Ok(sent_bytes)
@@ -0,0 +1,64 @@
+use core::cell::Cell;
+
+use critical_section::Mutex;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::CarrierSense;
+
+/// Time of the last edge seen by a reader.
+///
+/// Readers given `with_carrier_detect` mark every edge/byte they see here, so the
+/// writer on the same device knows somebody else transmits. Edges are seen only
+/// while the reader is reading, so it should run all the time.
+///
+/// It can be a `static` shared by the reader and writer tasks (or an interrupt handler).
+pub struct CarrierDetect {
+    last_edge: Mutex<Cell<Option<Instant>>>,
+}
+
+impl Default for CarrierDetect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CarrierDetect {
+    /// Const, so it can be used to initialize a `static`
+    pub const fn new() -> Self {
+        Self {
+            last_edge: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    pub fn edge_seen(&self) {
+        let now = Instant::now();
+        critical_section::with(|cs| self.last_edge.borrow(cs).set(Some(now)));
+    }
+
+    pub fn seen_within(&self, window: Duration) -> bool {
+        match critical_section::with(|cs| self.last_edge.borrow(cs).get()) {
+            Some(instant) => instant.elapsed() <= window,
+            None => false,
+        }
+    }
+}
+
+/// Channel is busy when the reader saw an edge during the slot
+pub struct EdgeCarrierSense<'a> {
+    detect: &'a CarrierDetect,
+    slot: Duration,
+}
+
+impl<'a> EdgeCarrierSense<'a> {
+    /// Slot should be longer than the longest gap between edges in a transmission
+    pub fn new(detect: &'a CarrierDetect, slot: Duration) -> Self {
+        Self { detect, slot }
+    }
+}
+
+impl<'a> CarrierSense for EdgeCarrierSense<'a> {
+    async fn is_channel_busy(&mut self) -> bool {
+        Timer::after(self.slot).await;
+        self.detect.seen_within(self.slot)
+    }
+}
@@ -0,0 +1,274 @@
+use crate::error::WriterError;
+use crate::{BaseWriter, CarrierSense};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsmaConfig {
+    /// Backoff is chosen randomly from `1..=window` slots, the window starts here
+    pub min_window: u16,
+    /// Window doubles every time the channel is busy, up to this value
+    pub max_window: u16,
+    /// How many backoffs to try before giving up with `WriterError::ChannelBusy`
+    pub max_attempts: u8,
+}
+
+impl Default for CsmaConfig {
+    fn default() -> Self {
+        Self {
+            min_window: 4,
+            max_window: 64,
+            max_attempts: 8,
+        }
+    }
+}
+
+/// Listen-before-talk wrapper around any writer.
+///
+/// Before every transmission the writer listens for a random number of slots,
+/// when somebody else transmits meanwhile it backs off with a doubled window.
+/// It can't avoid all collisions (hidden nodes, both starting in the same slot),
+/// it only makes them less likely.
+pub struct CsmaWriter<W, S> {
+    writer: W,
+    sense: S,
+    config: CsmaConfig,
+    random: u32,
+}
+
+impl<W, S> CsmaWriter<W, S>
+where
+    W: BaseWriter,
+    S: CarrierSense,
+{
+    const DEFAULT_SEED: u32 = 0x2545_f491;
+
+    pub fn new(writer: W, sense: S) -> Self {
+        Self {
+            writer,
+            sense,
+            config: CsmaConfig::default(),
+            random: Self::DEFAULT_SEED,
+        }
+    }
+
+    pub fn with_config(mut self, config: CsmaConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Every device should use a different seed (e.g. its address),
+    /// otherwise they pick the same backoff and collide anyway
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        // Zero state would produce only zeroes
+        self.random = if seed == 0 { Self::DEFAULT_SEED } else { seed };
+        self
+    }
+
+    pub fn into_inner(self) -> (W, S) {
+        (self.writer, self.sense)
+    }
+
+    async fn wait_for_clear_channel(&mut self) -> Result<(), WriterError> {
+        let max_window = self.config.max_window.max(1);
+        let mut window = self.config.min_window.clamp(1, max_window);
+        let mut paused = false;
+
+        for _ in 0..self.config.max_attempts {
+            // Backoff takes all its slots even when the channel gets busy,
+            // otherwise the attempts would run out during a single transmission
+            let slots = 1 + self.next_random() % window as u32;
+            let mut busy = false;
+            for _ in 0..slots {
+                busy |= self.sense.is_channel_busy().await;
+            }
+
+            if !busy {
+                self.writer.resume_transmission();
+                return Ok(());
+            }
+
+            if !paused {
+                self.writer.pause_transmission();
+                paused = true;
+            }
+            window = window.saturating_mul(2).min(max_window);
+        }
+
+        if paused {
+            self.writer.resume_transmission();
+        }
+        Err(WriterError::ChannelBusy)
+    }
+
+    /// xorshift32, it only has to spread the backoffs
+    fn next_random(&mut self) -> u32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random
+    }
+}
+
+impl<W, S> BaseWriter for CsmaWriter<W, S>
+where
+    W: BaseWriter,
+    S: CarrierSense,
+{
+    async fn init(&mut self) {
+        self.writer.init().await
+    }
+
+    async fn write_bytes_buffer(&mut self, buffer: &[u8]) -> Result<usize, WriterError> {
+        self.wait_for_clear_channel().await?;
+        self.writer.write_bytes_buffer(buffer).await
+    }
+
+    async fn write_bytes_iterator<I: Iterator<Item = u8>>(
+        &mut self,
+        data: I,
+    ) -> Result<usize, WriterError> {
+        self.wait_for_clear_channel().await?;
+        self.writer.write_bytes_iterator(data).await
+    }
+
+    fn pause_transmission(&mut self) {
+        self.writer.pause_transmission()
+    }
+
+    fn resume_transmission(&mut self) {
+        self.writer.resume_transmission()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::test_utils::block_on;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct MockWriter {
+        written: Vec<u8>,
+        paused: usize,
+        resumed: usize,
+    }
+
+    impl BaseWriter for MockWriter {
+        async fn write_bytes_buffer(&mut self, buffer: &[u8]) -> Result<usize, WriterError> {
+            self.write_bytes_iterator(buffer.iter().copied()).await
+        }
+
+        async fn write_bytes_iterator<I: Iterator<Item = u8>>(
+            &mut self,
+            data: I,
+        ) -> Result<usize, WriterError> {
+            let size = self.written.len();
+            self.written.extend(data);
+            Ok(self.written.len() - size)
+        }
+
+        fn pause_transmission(&mut self) {
+            self.paused += 1;
+        }
+
+        fn resume_transmission(&mut self) {
+            self.resumed += 1;
+        }
+    }
+
+    /// Channel is busy for the first `busy_slots` slots
+    struct MockSense {
+        busy_slots: usize,
+        slots: usize,
+    }
+
+    impl MockSense {
+        fn busy_for(busy_slots: usize) -> Self {
+            Self {
+                busy_slots,
+                slots: 0,
+            }
+        }
+    }
+
+    impl CarrierSense for MockSense {
+        async fn is_channel_busy(&mut self) -> bool {
+            self.slots += 1;
+            self.slots <= self.busy_slots
+        }
+    }
+
+    #[test]
+    fn test_clear_channel() {
+        let mut csma = CsmaWriter::new(MockWriter::default(), MockSense::busy_for(0));
+
+        assert_eq!(block_on(csma.write_bytes_buffer(&[0x01, 0x02])).unwrap(), 2);
+
+        let (writer, sense) = csma.into_inner();
+        assert_eq!(writer.written, [0x01, 0x02]);
+        assert_eq!(writer.paused, 0);
+        // Backoff is taken from the first window
+        assert!((1..=CsmaConfig::default().min_window as usize).contains(&sense.slots));
+    }
+
+    #[test]
+    fn test_backoff_while_busy() {
+        let config = CsmaConfig {
+            min_window: 2,
+            max_window: 8,
+            max_attempts: 8,
+        };
+        let mut csma =
+            CsmaWriter::new(MockWriter::default(), MockSense::busy_for(5)).with_config(config);
+
+        assert_eq!(block_on(csma.write_bytes_buffer(&[0xaa])).unwrap(), 1);
+
+        let (writer, sense) = csma.into_inner();
+        assert_eq!(writer.written, [0xaa]);
+        // Transmission is paused once and resumed right before sending
+        assert_eq!(writer.paused, 1);
+        assert_eq!(writer.resumed, 1);
+        assert!(sense.slots > 5);
+    }
+
+    #[test]
+    fn test_window_doubles_up_to_max() {
+        let config = CsmaConfig {
+            min_window: 1,
+            max_window: 4,
+            max_attempts: 4,
+        };
+        let mut csma = CsmaWriter::new(MockWriter::default(), MockSense::busy_for(usize::MAX))
+            .with_config(config);
+
+        assert!(matches!(
+            block_on(csma.write_bytes_buffer(&[0xaa])),
+            Err(WriterError::ChannelBusy)
+        ));
+
+        let (writer, sense) = csma.into_inner();
+        assert!(writer.written.is_empty());
+        assert_eq!(writer.resumed, 1);
+        // Windows 1, 2, 4 and 4 slots, at least one slot each
+        assert!((4..=1 + 2 + 4 + 4).contains(&sense.slots));
+    }
+
+    #[test]
+    fn test_seed_changes_backoff() {
+        let slots = |seed| {
+            let mut csma =
+                CsmaWriter::new(MockWriter::default(), MockSense::busy_for(0)).with_seed(seed);
+            block_on(csma.write_bytes_buffer(&[0xaa])).unwrap();
+            csma.into_inner().1.slots
+        };
+
+        assert_eq!(slots(1), 2);
+        assert_eq!(slots(2), 3);
+        // Zero seed falls back to the default one instead of getting stuck
+        assert_eq!(
+            slots(0),
+            slots(CsmaWriter::<MockWriter, MockSense>::DEFAULT_SEED)
+        );
+    }
+}
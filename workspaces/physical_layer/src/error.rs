@@ -17,6 +17,8 @@ impl ReadError {
 #[derive(Format, Debug)]
 pub enum WriterError {
     RuntimeError,
+    /// Channel was still busy after all backoff attempts
+    ChannelBusy,
 }
 
 impl WriterError {
@@ -0,0 +1,99 @@
+use crate::error::ReadError;
+
+/// Source of the bytes of one frame
+pub(crate) trait ByteReader {
+    async fn read_byte(&mut self) -> Result<u8, ReadError>;
+}
+
+/// Read one frame into the buffer, see `BaseReader::read_bytes_buffer`.
+/// When the transmission ends (`ReadError::TimeoutError`) before the buffer is full,
+/// bytes read before it are returned as a shorter frame. Other errors are returned.
+pub(crate) async fn read_frame<R: ByteReader>(
+    reader: &mut R,
+    buffer: &mut [u8],
+) -> Result<usize, ReadError> {
+    for (index, element) in buffer.iter_mut().enumerate() {
+        *element = match reader.read_byte().await {
+            Ok(byte) => byte,
+            Err(ReadError::TimeoutError) if index > 0 => return Ok(index),
+            Err(e) => return Err(e),
+        };
+    }
+    Ok(buffer.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::block_on;
+
+    /// Fails with the error once all the bytes are read
+    struct MockReader<'a> {
+        bytes: &'a [u8],
+        error: fn() -> ReadError,
+    }
+
+    impl<'a> MockReader<'a> {
+        fn new(bytes: &'a [u8]) -> Self {
+            Self {
+                bytes,
+                error: || ReadError::TimeoutError,
+            }
+        }
+    }
+
+    impl<'a> ByteReader for MockReader<'a> {
+        async fn read_byte(&mut self) -> Result<u8, ReadError> {
+            let (byte, rest) = self.bytes.split_first().ok_or_else(self.error)?;
+            self.bytes = rest;
+            Ok(*byte)
+        }
+    }
+
+    #[test]
+    fn test_full_frame() {
+        let mut reader = MockReader::new(&[0x01, 0x02, 0x03]);
+        let mut buffer = [0u8; 2];
+
+        assert_eq!(block_on(read_frame(&mut reader, &mut buffer)).unwrap(), 2);
+        assert_eq!(buffer, [0x01, 0x02]);
+        // Rest of the transmission is the next frame
+        assert_eq!(block_on(read_frame(&mut reader, &mut buffer)).unwrap(), 1);
+        assert_eq!(buffer[0], 0x03);
+    }
+
+    #[test]
+    fn test_partial_frame_on_timeout() {
+        let mut reader = MockReader::new(&[0xab, 0xcd]);
+        let mut buffer = [0u8; 8];
+
+        assert_eq!(block_on(read_frame(&mut reader, &mut buffer)).unwrap(), 2);
+        assert_eq!(&buffer[..2], &[0xab, 0xcd]);
+    }
+
+    #[test]
+    fn test_timeout_without_frame() {
+        let mut reader = MockReader::new(&[]);
+        let mut buffer = [0u8; 8];
+
+        assert!(matches!(
+            block_on(read_frame(&mut reader, &mut buffer)),
+            Err(ReadError::TimeoutError)
+        ));
+    }
+
+    #[test]
+    fn test_error_in_frame() {
+        let mut reader = MockReader {
+            bytes: &[0xab, 0xcd],
+            error: || ReadError::RuntimeError,
+        };
+        let mut buffer = [0u8; 8];
+
+        // Only the timeout ends the frame, broken reading is not a shorter frame
+        assert!(matches!(
+            block_on(read_frame(&mut reader, &mut buffer)),
+            Err(ReadError::RuntimeError)
+        ));
+    }
+}
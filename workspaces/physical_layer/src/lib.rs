@@ -2,8 +2,13 @@
 #![allow(incomplete_features)]
 #![feature(async_fn_in_trait)]
 
+pub mod csma;
 pub mod error;
+#[cfg(any(feature = "embassy", test))]
+mod frame;
 
+#[cfg(feature = "embassy")]
+pub mod carrier;
 #[cfg(feature = "embassy")]
 pub mod manchester;
 #[cfg(feature = "embassy")]
@@ -15,6 +20,11 @@ pub mod utils;
 
 pub trait BaseReader {
     async fn init(&mut self) {} // FIXME call inits before using reader (not inside the reader)
+    /// Read one frame into the buffer, returns its size.
+    ///
+    /// Frame ends when the buffer is full or when the transmission ends (the next byte
+    /// does not come in time), so it can be shorter than the buffer. Error is returned
+    /// only when not even one byte was received.
     async fn read_bytes_buffer(&mut self, buffer: &mut [u8]) -> Result<usize, error::ReadError>;
 }
 
@@ -26,9 +36,32 @@ pub trait BaseWriter {
         data: I,
     ) -> Result<usize, error::WriterError>;
 
-    // TODO these methods could be used on devices
-    // TODO which have reader and writer at the same time
-    // TODO to do a really basic collision avoidance
+    /// Called by `csma::CsmaWriter` when it backs off because the channel is busy
     fn pause_transmission(&mut self) {}
+    /// Called by `csma::CsmaWriter` right before it starts transmitting
     fn resume_transmission(&mut self) {}
 }
+
+/// Carrier detection used for listen-before-talk
+pub trait CarrierSense {
+    /// Listen to the channel for one slot, returns `true` when somebody else is transmitting
+    async fn is_channel_busy(&mut self) -> bool;
+}
+
+#[cfg(test)]
+mod test_utils {
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use futures::pin_mut;
+    use futures::task::noop_waker;
+
+    /// Mocks used in the tests never wait, one poll finishes the future
+    pub fn block_on<F: Future>(future: F) -> F::Output {
+        pin_mut!(future);
+        let waker = noop_waker();
+        match future.poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("Mocks should never wait"),
+        }
+    }
+}
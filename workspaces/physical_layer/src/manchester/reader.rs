@@ -1,4 +1,6 @@
+use crate::carrier::CarrierDetect;
 use crate::error::ReadError;
+use crate::frame::{read_frame, ByteReader};
 use crate::utils::SharedPin;
 use crate::BaseReader;
 use defmt::{debug, trace};
@@ -11,6 +13,7 @@ use embassy_time::{with_timeout, Duration, Timer};
 pub struct ManchesterReader<'a, P: Pin> {
     pin: SharedPin<'a, ExtiInput<'a, P>>,
     timing: ManchesterTiming,
+    carrier: Option<&'a CarrierDetect>,
 }
 
 impl<'a, P: Pin> ManchesterReader<'a, P> {
@@ -18,6 +21,19 @@ impl<'a, P: Pin> ManchesterReader<'a, P> {
         Self {
             pin,
             timing: create_manchester_timing(data_timing),
+            carrier: None,
+        }
+    }
+
+    /// Mark every received byte, see `carrier::EdgeCarrierSense`
+    pub fn with_carrier_detect(mut self, carrier: &'a CarrierDetect) -> Self {
+        self.carrier = Some(carrier);
+        self
+    }
+
+    fn byte_seen(&self) {
+        if let Some(carrier) = self.carrier {
+            carrier.edge_seen();
         }
     }
 
@@ -29,6 +45,7 @@ impl<'a, P: Pin> ManchesterReader<'a, P> {
             if let Some(byte) = decoder.next(self.pin.is_high()) {
                 debug!("We should not receive byte in this branch in manchester");
                 Timer::after(self.timing.decoding_end_wait).await;
+                self.byte_seen();
                 return Ok(byte);
             }
 
@@ -37,28 +54,46 @@ impl<'a, P: Pin> ManchesterReader<'a, P> {
             Timer::after(self.timing.decoding_end_wait).await;
 
             if let Some(byte) = result {
+                self.byte_seen();
                 return Ok(byte);
             }
 
+            // Line did not change for a whole byte, the transmission ended
             no_byte_iteration += 1;
             if no_byte_iteration >= 8 {
-                return Err(ReadError::OutOfTiming);
+                return Err(ReadError::TimeoutError);
             }
         }
     }
 }
 
+/// Decoder state of the frame being read
+struct FrameDecoder<'r, 'a, P: Pin> {
+    reader: &'r mut ManchesterReader<'a, P>,
+    decoder: DecoderBool,
+}
+
+impl<'r, 'a, P: Pin> ByteReader for FrameDecoder<'r, 'a, P> {
+    async fn read_byte(&mut self) -> Result<u8, ReadError> {
+        let byte = with_timeout(
+            self.reader.timing.decoding_timeout,
+            self.reader.read_byte(&mut self.decoder),
+        )
+        .await
+        .map_err(|_| ReadError::TimeoutError)??;
+        // trace!("Manchester reader received byte = {:#04x}", byte);
+        Ok(byte)
+    }
+}
+
 impl<'a, P: Pin> BaseReader for ManchesterReader<'a, P> {
     async fn read_bytes_buffer(&mut self, buffer: &mut [u8]) -> Result<usize, ReadError> {
-        let mut decoder = DecoderBool::new(BitOrder::LittleEndian);
-        for element in buffer.iter_mut() {
-            // FIXME should ve return error or just success with less bytes?
-            *element = with_timeout(self.timing.decoding_timeout, self.read_byte(&mut decoder))
-                .await
-                .map_err(|_| ReadError::TimeoutError)??;
-            // trace!("Manchester reader received byte = {:#04x}", element);
-        }
-        // FIXME this is wrong
-        Ok(buffer.len())
+        let mut frame = FrameDecoder {
+            reader: self,
+            decoder: DecoderBool::new(BitOrder::LittleEndian),
+        };
+        let size = read_frame(&mut frame, buffer).await?;
+        trace!("Manchester frame of {} bytes", size);
+        Ok(size)
     }
 }
@@ -6,7 +6,9 @@ use embassy_stm32::exti::ExtiInput;
 use embassy_stm32::gpio::Pin;
 use embassy_time::{with_timeout, Duration, Instant};
 
+use crate::carrier::CarrierDetect;
 use crate::error::ReadError;
+use crate::frame::{read_frame, ByteReader};
 use crate::pwm::sync::SyncSequence;
 use crate::pwm::writer::WriterTiming;
 use crate::utils::SharedPin;
@@ -36,6 +38,11 @@ impl ReaderTiming {
     pub fn adjust_to_sync_marker(&mut self, marker: &SyncSequence) {
         self.upper_threshold = marker.ones + marker.zeroes
     }
+
+    /// Longest time one byte of a frame can take, the frame ends when no byte comes within it
+    pub fn byte_timeout(&self) -> Duration {
+        self.upper_threshold * 8
+    }
 }
 
 impl Default for ReaderTiming {
@@ -140,12 +147,23 @@ pub trait PwmReader: crate::BaseReader {
 pub struct PinPwmReader<'a, P: Pin, const INVERT: bool = false> {
     timing: ReaderTiming,
     pin: SharedPin<'a, ExtiInput<'a, P>>,
+    carrier: Option<&'a CarrierDetect>,
 }
 
 impl<'a, P: Pin, const INVERT: bool> PinPwmReader<'a, P, INVERT> {
     #[allow(clippy::result_unit_err)]
     pub fn new(timing: ReaderTiming, pin: SharedPin<'a, ExtiInput<'a, P>>) -> Result<Self, ()> {
-        Ok(Self { timing, pin })
+        Ok(Self {
+            timing,
+            pin,
+            carrier: None,
+        })
+    }
+
+    /// Mark every edge, see `carrier::EdgeCarrierSense`
+    pub fn with_carrier_detect(mut self, carrier: &'a CarrierDetect) -> Self {
+        self.carrier = Some(carrier);
+        self
     }
 
     pub fn into_stream(self) -> impl Stream<Item = u8> + 'a {
@@ -155,9 +173,37 @@ impl<'a, P: Pin, const INVERT: bool> PinPwmReader<'a, P, INVERT> {
     }
 }
 
+/// Bytes of one frame, the first one is awaited as long as it takes
+struct PwmFrame<'r, R: PwmReader> {
+    reader: &'r mut R,
+    started: bool,
+}
+
+impl<'r, R: PwmReader> ByteReader for PwmFrame<'r, R> {
+    async fn read_byte(&mut self) -> Result<u8, ReadError> {
+        if !self.started {
+            self.started = true;
+            return self.reader.read_byte().await;
+        }
+
+        with_timeout(
+            self.reader.get_timing().byte_timeout(),
+            self.reader.read_byte(),
+        )
+        .await
+        .map_err(|_| ReadError::TimeoutError)?
+    }
+}
+
 impl<'a, P: Pin, const INVERT: bool> crate::BaseReader for PinPwmReader<'a, P, INVERT> {
     async fn read_bytes_buffer(&mut self, buffer: &mut [u8]) -> Result<usize, ReadError> {
-        self.read_bytes(buffer.len(), buffer).await
+        let mut frame = PwmFrame {
+            reader: self,
+            started: false,
+        };
+        let size = read_frame(&mut frame, buffer).await?;
+        trace!("PWM frame of {} bytes", size);
+        Ok(size)
     }
 }
 
@@ -170,6 +216,9 @@ impl<'a, P: Pin, const INVERT: bool> PwmReader for PinPwmReader<'a, P, INVERT> {
             self.pin.wait_for_rising_edge().await;
         }
         let start_time = Instant::now();
+        if let Some(carrier) = self.carrier {
+            carrier.edge_seen();
+        }
 
         if INVERT {
             with_timeout(self.timing.upper_threshold, self.pin.wait_for_rising_edge())
@@ -0,0 +1,197 @@
+use core::fmt::{Debug, Formatter};
+use core::marker::PhantomData;
+
+use crate::Sequence;
+
+/// Set of sequence numbers stored as a bitmap, bit `n` is for sequence number `n`.
+/// Modulo of the sequence number can be at most 64.
+pub struct SequenceNumberBitmap<S> {
+    bits: u64,
+    _sequence: PhantomData<S>,
+}
+
+impl<S: Sequence> SequenceNumberBitmap<S> {
+    /// How many bytes are needed to store the bitmap
+    pub const BYTES: usize = (S::MODULO as usize).div_ceil(8);
+
+    const MASK: u64 = if S::MODULO >= 64 {
+        u64::MAX
+    } else {
+        (1u64 << S::MODULO) - 1
+    };
+
+    pub fn new() -> Self {
+        Self::from_bits(0)
+    }
+
+    /// Bitmap containing all the sequence numbers
+    pub fn full() -> Self {
+        Self::from_bits(u64::MAX)
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            bits: bits & Self::MASK,
+            _sequence: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn insert(&mut self, sequence_number: &S) {
+        self.bits |= 1u64 << sequence_number.value();
+    }
+
+    pub fn remove(&mut self, sequence_number: &S) {
+        self.bits &= !(1u64 << sequence_number.value());
+    }
+
+    pub fn contains(&self, sequence_number: &S) -> bool {
+        (self.bits & (1u64 << sequence_number.value())) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub fn complement(&self) -> Self {
+        Self::from_bits(!self.bits)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = S> + '_ {
+        (0..S::MODULO)
+            .filter(|value| (self.bits & (1u64 << value)) != 0)
+            .map(S::new)
+    }
+
+    /// Write the bitmap as little endian bytes.
+    /// Returns number of written bytes (`Self::BYTES`).
+    pub fn write_bytes(&self, buffer: &mut [u8]) -> usize {
+        let bytes = self.bits.to_le_bytes();
+        buffer[..Self::BYTES].copy_from_slice(&bytes[..Self::BYTES]);
+        Self::BYTES
+    }
+
+    pub fn from_bytes(buffer: &[u8]) -> Self {
+        let mut bytes = [0u8; 8];
+        bytes[..Self::BYTES].copy_from_slice(&buffer[..Self::BYTES]);
+        Self::from_bits(u64::from_le_bytes(bytes))
+    }
+}
+
+impl<S: Sequence> Default for SequenceNumberBitmap<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Derives would require `S` to implement the traits as well
+impl<S> Clone for SequenceNumberBitmap<S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for SequenceNumberBitmap<S> {}
+
+impl<S> PartialEq for SequenceNumberBitmap<S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<S> Eq for SequenceNumberBitmap<S> {}
+
+impl<S: Sequence> FromIterator<S> for SequenceNumberBitmap<S> {
+    fn from_iter<T: IntoIterator<Item = S>>(iter: T) -> Self {
+        let mut bitmap = Self::new();
+        for sequence_number in iter {
+            bitmap.insert(&sequence_number);
+        }
+        bitmap
+    }
+}
+
+impl<S: Sequence> Debug for SequenceNumberBitmap<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SequenceNumberBitmap<{}>[{:#b}]", S::MODULO, self.bits)
+    }
+}
+
+impl<S: Sequence> defmt::Format for SequenceNumberBitmap<S> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "SequenceNumberBitmap<{}>[{:#b}]", S::MODULO, self.bits)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::SequenceNumber;
+    use std::vec::Vec;
+
+    type SN = SequenceNumber<16>;
+    type Bitmap = SequenceNumberBitmap<SN>;
+
+    #[test]
+    fn test_insert_remove() {
+        let mut bitmap = Bitmap::new();
+        assert!(bitmap.is_empty());
+
+        bitmap.insert(&SN::new(3));
+        bitmap.insert(&SN::new(15));
+        assert!(bitmap.contains(&SN::new(3)));
+        assert!(bitmap.contains(&SN::new(15)));
+        assert!(!bitmap.contains(&SN::new(4)));
+        assert_eq!(bitmap.len(), 2);
+
+        bitmap.remove(&SN::new(3));
+        assert!(!bitmap.contains(&SN::new(3)));
+        assert_eq!(bitmap.len(), 1);
+    }
+
+    #[test]
+    fn test_complement() {
+        let bitmap: Bitmap = [0u8, 1, 2].into_iter().map(SN::new).collect();
+        let complement = bitmap.complement();
+
+        assert_eq!(complement.len(), 13);
+        assert!(!complement.contains(&SN::new(1)));
+        assert!(complement.contains(&SN::new(3)));
+        assert_eq!(Bitmap::full().complement(), Bitmap::new());
+    }
+
+    #[test]
+    fn test_iter() {
+        let bitmap: Bitmap = [7u8, 2, 9].into_iter().map(SN::new).collect();
+        let values: Vec<u8> = bitmap.iter().map(|v| v.value()).collect();
+        assert_eq!(values, vec![2, 7, 9]);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        assert_eq!(Bitmap::BYTES, 2);
+        assert_eq!(SequenceNumberBitmap::<SequenceNumber<8>>::BYTES, 1);
+        assert_eq!(SequenceNumberBitmap::<SequenceNumber<64>>::BYTES, 8);
+
+        let bitmap: Bitmap = [0u8, 8, 15].into_iter().map(SN::new).collect();
+        let mut buffer = [0u8; 4];
+        assert_eq!(bitmap.write_bytes(&mut buffer), 2);
+        assert_eq!(buffer, [0x01, 0x81, 0x00, 0x00]);
+        assert_eq!(Bitmap::from_bytes(&buffer), bitmap);
+    }
+
+    #[test]
+    fn test_full_64() {
+        let bitmap = SequenceNumberBitmap::<SequenceNumber<64>>::full();
+        assert_eq!(bitmap.len(), 64);
+        assert!(bitmap.contains(&SequenceNumber::new(63)));
+    }
+}
@@ -3,6 +3,10 @@
 use core::cmp::Ordering;
 use core::fmt::{Debug, Formatter};
 
+mod bitmap;
+
+pub use bitmap::SequenceNumberBitmap;
+
 #[derive(Clone)]
 pub struct SequenceNumber<const MODULO: u8> {
     value: u8,
@@ -160,6 +164,18 @@ impl<const MODULO: u8> From<u64> for SequenceNumber<MODULO> {
     }
 }
 
+impl<const MODULO: u8> From<SequenceNumber<MODULO>> for u128 {
+    fn from(value: SequenceNumber<MODULO>) -> Self {
+        value.value() as u128
+    }
+}
+
+impl<const MODULO: u8> From<u128> for SequenceNumber<MODULO> {
+    fn from(value: u128) -> Self {
+        Self::new((value % (MODULO as u128)) as u8)
+    }
+}
+
 impl<const MODULO: u8> From<u8> for SequenceNumber<MODULO> {
     fn from(value: u8) -> Self {
         Self::new(value)
@@ -193,6 +209,60 @@ impl<const MODULO: u8> PartialOrd for SequenceNumber<MODULO> {
     }
 }
 
+/// Interface shared by sequence numbers with any modulo.
+/// Lets the code which is generic over the packet format work with its sequence numbers.
+pub trait Sequence: Clone + PartialEq + Debug + defmt::Format + AsRef<Self> {
+    const MODULO: u8;
+
+    fn new(value: u8) -> Self;
+    fn value(&self) -> u8;
+    fn advance(&mut self) -> Self;
+    fn positive_distance(&self, other: &Self) -> u8;
+    fn compare(&self, other: &Self, first_element: &Self) -> Ordering;
+    fn is_sorted_asc(&self, sequence: impl Iterator<Item = impl AsRef<Self>>) -> bool;
+    fn get_insertion_order_ascending(
+        &self,
+        sequence: impl Iterator<Item = impl AsRef<Self>>,
+        first_element: Option<&Self>,
+    ) -> Option<usize>;
+}
+
+impl<const MODULO: u8> Sequence for SequenceNumber<MODULO> {
+    const MODULO: u8 = MODULO;
+
+    fn new(value: u8) -> Self {
+        SequenceNumber::new(value)
+    }
+
+    fn value(&self) -> u8 {
+        SequenceNumber::value(self)
+    }
+
+    fn advance(&mut self) -> Self {
+        SequenceNumber::advance(self)
+    }
+
+    fn positive_distance(&self, other: &Self) -> u8 {
+        SequenceNumber::positive_distance(self, other)
+    }
+
+    fn compare(&self, other: &Self, first_element: &Self) -> Ordering {
+        SequenceNumber::compare(self, other, first_element)
+    }
+
+    fn is_sorted_asc(&self, sequence: impl Iterator<Item = impl AsRef<Self>>) -> bool {
+        SequenceNumber::is_sorted_asc(self, sequence)
+    }
+
+    fn get_insertion_order_ascending(
+        &self,
+        sequence: impl Iterator<Item = impl AsRef<Self>>,
+        first_element: Option<&Self>,
+    ) -> Option<usize> {
+        SequenceNumber::get_insertion_order_ascending(self, sequence, first_element)
+    }
+}
+
 #[cfg(test)]
 #[macro_use]
 extern crate std;
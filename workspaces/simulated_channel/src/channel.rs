@@ -0,0 +1,625 @@
+use std::collections::{HashMap, VecDeque};
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_std::task::sleep;
+use futures::task::noop_waker;
+
+use physical_layer::error::{ReadError, WriterError};
+use physical_layer::{BaseReader, BaseWriter, CarrierSense};
+
+use crate::noise::{NoiseConfig, NoiseModel, NoiseStatistics};
+
+#[derive(Clone, Debug)]
+pub struct ChannelConfig {
+    pub noise: NoiseConfig,
+    /// Seed for the noise generator
+    pub seed: u64,
+    /// Air time of one (manchester half) bit
+    pub bit_time: Duration,
+    /// How long reader waits for any data before `ReadError::TimeoutError`
+    pub read_timeout: Duration,
+}
+
+impl ChannelConfig {
+    pub fn new(noise: NoiseConfig, seed: u64) -> Self {
+        Self {
+            noise,
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            noise: NoiseConfig::lossless(),
+            seed: 0,
+            bit_time: Duration::from_millis(1),
+            read_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStatistics {
+    pub noise: NoiseStatistics,
+    pub transmissions: usize,
+    pub collisions: usize,
+}
+
+#[derive(Clone, Copy)]
+enum AirSymbol {
+    Bit(bool),
+    Stop,
+}
+
+/// Time which moves only when everything on the channel waits, see `SimulatedChannel::block_on`
+struct VirtualTime {
+    now: Duration,
+    /// Earliest deadline somebody waits for
+    next_wakeup: Option<Duration>,
+}
+
+struct ChannelState {
+    air: VecDeque<AirSymbol>,
+    /// Absolute position of the first symbol in `air`
+    air_start: usize,
+    /// Absolute position of the next symbol for every reader, symbols read by all of them are dropped
+    readers: HashMap<usize, usize>,
+    next_reader: usize,
+    /// Some bits were written after the last stop symbol
+    pending_bits: bool,
+
+    transmitters: usize,
+
+    noise: NoiseModel,
+    transmissions: usize,
+    collisions: usize,
+
+    /// `None` when the channel runs in real time
+    virtual_time: Option<VirtualTime>,
+}
+
+impl ChannelState {
+    fn air_end(&self) -> usize {
+        self.air_start + self.air.len()
+    }
+
+    /// Write a bit at given absolute position.
+    /// When there is already something on that position (another transmitter
+    /// is on the air) bits are combined the same way as OOK carriers would be.
+    fn put_bit(&mut self, cursor: &mut usize, bit: bool) {
+        let Self {
+            air,
+            air_start,
+            pending_bits,
+            noise,
+            ..
+        } = self;
+
+        noise.transmit_bit(bit, |bit| {
+            // Receiver could already consume part of the colliding transmission
+            let position = (*cursor).max(*air_start);
+            let index = position - *air_start;
+
+            match air.get_mut(index) {
+                Some(AirSymbol::Bit(value)) => *value |= bit,
+                Some(AirSymbol::Stop) => air.insert(index, AirSymbol::Bit(bit)),
+                None => air.push_back(AirSymbol::Bit(bit)),
+            }
+
+            *pending_bits = true;
+            *cursor = position + 1;
+        });
+    }
+
+    fn add_reader(&mut self) -> usize {
+        let id = self.next_reader;
+        self.next_reader += 1;
+        // Reader hears only what is transmitted after it was created
+        let end = self.air_end();
+        self.readers.insert(id, end);
+        id
+    }
+
+    fn remove_reader(&mut self, id: usize) {
+        self.readers.remove(&id);
+        self.drop_read_symbols();
+    }
+
+    fn pop_symbol(&mut self, reader: usize) -> Option<AirSymbol> {
+        let cursor = self.readers.get_mut(&reader)?;
+        let symbol = self.air.get(*cursor - self.air_start).copied();
+        if symbol.is_some() {
+            *cursor += 1;
+            self.drop_read_symbols();
+        }
+        symbol
+    }
+
+    fn drop_read_symbols(&mut self) {
+        let oldest = self
+            .readers
+            .values()
+            .copied()
+            .min()
+            .unwrap_or_else(|| self.air_end());
+        while self.air_start < oldest && self.air.pop_front().is_some() {
+            self.air_start += 1;
+        }
+    }
+}
+
+/// Simulated 433MHz OOK channel shared by any number of readers and writers.
+///
+/// Data are manchester encoded the same way as `physical_layer::manchester`
+/// does it and every bit goes through the `NoiseModel`. When two writers
+/// transmit at the same time their bits are OR-ed together. Every reader hears
+/// everything transmitted after it was created, like every receiver in range of
+/// the real radio, so a node also hears its own transmissions.
+#[derive(Clone)]
+pub struct SimulatedChannel {
+    config: ChannelConfig,
+    state: Arc<Mutex<ChannelState>>,
+}
+
+impl SimulatedChannel {
+    pub fn new(config: ChannelConfig) -> Self {
+        let state = ChannelState {
+            air: VecDeque::new(),
+            air_start: 0,
+            readers: HashMap::new(),
+            next_reader: 0,
+            pending_bits: false,
+            transmitters: 0,
+            noise: NoiseModel::new(config.noise.clone(), config.seed),
+            transmissions: 0,
+            collisions: 0,
+            virtual_time: None,
+        };
+
+        Self {
+            config,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    pub fn lossless() -> Self {
+        Self::new(ChannelConfig::default())
+    }
+
+    /// Run the channel in virtual time instead of the real one, futures using it
+    /// have to be run by `block_on`. Timing of the transmissions then does not depend
+    /// on the scheduling of the host, so every run is the same.
+    pub fn with_virtual_time(self) -> Self {
+        self.lock().virtual_time = Some(VirtualTime {
+            now: Duration::ZERO,
+            next_wakeup: None,
+        });
+        self
+    }
+
+    /// Run the future on the channel with virtual time. Whenever the future waits,
+    /// the time jumps to the next deadline, so only one task can use the channel
+    /// (use `join!` for more readers and writers).
+    ///
+    /// Panics when the future waits for anything else than the channel.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        // Whole future is polled after every step of the time anyway
+        let waker = noop_waker();
+        let mut context = Context::from_waker(&waker);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+
+            let mut state = self.lock();
+            let time = state
+                .virtual_time
+                .as_mut()
+                .expect("Channel does not run in virtual time");
+            time.now = time
+                .next_wakeup
+                .take()
+                .expect("Future waits for something else than the channel");
+        }
+    }
+
+    pub fn reader(&self) -> SimulatedReader {
+        SimulatedReader {
+            id: self.lock().add_reader(),
+            channel: self.clone(),
+        }
+    }
+
+    pub fn writer(&self) -> SimulatedWriter {
+        SimulatedWriter {
+            channel: self.clone(),
+        }
+    }
+
+    pub fn carrier_sense(&self) -> SimulatedCarrierSense {
+        SimulatedCarrierSense {
+            channel: self.clone(),
+        }
+    }
+
+    /// Somebody is transmitting right now
+    pub fn is_busy(&self) -> bool {
+        self.lock().transmitters > 0
+    }
+
+    pub fn statistics(&self) -> ChannelStatistics {
+        let state = self.lock();
+        ChannelStatistics {
+            noise: state.noise.statistics().clone(),
+            transmissions: state.transmissions,
+            collisions: state.collisions,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        self.state.lock().unwrap()
+    }
+
+    async fn sleep(&self, duration: Duration) {
+        let now = self.lock().virtual_time.as_ref().map(|time| time.now);
+        let deadline = match now {
+            Some(now) => now + duration,
+            None => return sleep(duration).await,
+        };
+
+        poll_fn(|_| {
+            let mut state = self.lock();
+            let time = state
+                .virtual_time
+                .as_mut()
+                .expect("Virtual time can't be turned off");
+            if time.now >= deadline {
+                return Poll::Ready(());
+            }
+            time.next_wakeup = Some(time.next_wakeup.map_or(deadline, |next| next.min(deadline)));
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+pub struct SimulatedReader {
+    id: usize,
+    channel: SimulatedChannel,
+}
+
+impl Drop for SimulatedReader {
+    fn drop(&mut self) {
+        self.channel.lock().remove_reader(self.id);
+    }
+}
+
+impl BaseReader for SimulatedReader {
+    async fn read_bytes_buffer(&mut self, buffer: &mut [u8]) -> Result<usize, ReadError> {
+        let poll_time = self.channel.config.bit_time * 10;
+        let max_empty_polls =
+            (self.channel.config.read_timeout.as_micros() / poll_time.as_micros().max(1)).max(1);
+
+        let mut decoder = manchester::DecoderBool::new(manchester::BitOrder::LittleEndian);
+        let mut index = 0usize;
+        let mut empty_polls = 0u128;
+
+        while index < buffer.len() {
+            let symbol = self.channel.lock().pop_symbol(self.id);
+
+            match symbol {
+                Some(AirSymbol::Bit(bit)) => {
+                    empty_polls = 0;
+                    if let Some(byte) = decoder.next(bit) {
+                        buffer[index] = byte;
+                        index += 1;
+                    }
+                }
+                Some(AirSymbol::Stop) => {
+                    if index > 0 {
+                        return Ok(index);
+                    }
+                    // Nothing decoded from this transmission (e.g. half a byte), start over
+                    decoder = manchester::DecoderBool::new(manchester::BitOrder::LittleEndian);
+                }
+                None => {
+                    empty_polls += 1;
+                    if empty_polls > max_empty_polls {
+                        // Transmission ended without the stop symbol
+                        return if index > 0 {
+                            Ok(index)
+                        } else {
+                            Err(ReadError::TimeoutError)
+                        };
+                    }
+                    self.channel.sleep(poll_time).await;
+                }
+            }
+        }
+
+        Ok(index)
+    }
+}
+
+pub struct SimulatedWriter {
+    channel: SimulatedChannel,
+}
+
+impl BaseWriter for SimulatedWriter {
+    async fn write_bytes_buffer(&mut self, buffer: &[u8]) -> Result<usize, WriterError> {
+        self.write_bytes_iterator(buffer.iter().copied()).await
+    }
+
+    async fn write_bytes_iterator<I: Iterator<Item = u8>>(
+        &mut self,
+        data: I,
+    ) -> Result<usize, WriterError> {
+        let (lost, mut cursor) = {
+            let mut state = self.channel.lock();
+            state.transmissions += 1;
+            if state.transmitters > 0 {
+                state.collisions += 1;
+            }
+            state.transmitters += 1;
+
+            (state.noise.lose_packet(), state.air_end())
+        };
+
+        let mut written = 0usize;
+        let mut half_bits = 0usize;
+        let encoder =
+            manchester::EncoderBoolIterator::new(data, manchester::BitOrder::LittleEndian);
+        for bit in encoder {
+            if !lost {
+                self.channel.lock().put_bit(&mut cursor, bit);
+            }
+
+            half_bits += 1;
+            if half_bits % 16 == 0 {
+                written += 1;
+            }
+            self.channel.sleep(self.channel.config.bit_time).await;
+        }
+
+        let mut state = self.channel.lock();
+        state.transmitters -= 1;
+        if state.transmitters == 0 && state.pending_bits {
+            state.air.push_back(AirSymbol::Stop);
+            state.pending_bits = false;
+        }
+
+        Ok(written)
+    }
+}
+
+/// Carrier detection for `physical_layer::csma::CsmaWriter`, one slot is air time of one byte
+pub struct SimulatedCarrierSense {
+    channel: SimulatedChannel,
+}
+
+impl CarrierSense for SimulatedCarrierSense {
+    async fn is_channel_busy(&mut self) -> bool {
+        // Listen for the whole slot even when it's busy, it's what measures the backoff
+        let mut busy = false;
+        for _ in 0..16 {
+            busy |= self.channel.is_busy();
+            self.channel.sleep(self.channel.config.bit_time).await;
+        }
+        busy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::noise::NoiseConfig;
+    use async_std::task::block_on;
+    use physical_layer::csma::{CsmaConfig, CsmaWriter};
+
+    fn fast_config(noise: NoiseConfig) -> ChannelConfig {
+        ChannelConfig {
+            bit_time: Duration::from_micros(10),
+            read_timeout: Duration::from_millis(50),
+            ..ChannelConfig::new(noise, 7)
+        }
+    }
+
+    #[test]
+    fn test_lossless_transfer() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig::lossless()));
+        let mut writer = channel.writer();
+        let mut reader = channel.reader();
+
+        block_on(async {
+            let payload = [0x01u8, 0x02, 0xab, 0xcd];
+            writer.write_bytes_buffer(&payload).await.unwrap();
+
+            let mut buffer = [0u8; 8];
+            let size = reader.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &payload[..]);
+
+            assert!(matches!(
+                reader.read_bytes_buffer(&mut buffer).await,
+                Err(ReadError::TimeoutError)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_every_reader_hears_frame() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig::lossless()));
+        let mut writer = channel.writer();
+        let mut reader_a = channel.reader();
+        let mut reader_b = channel.reader();
+
+        block_on(async {
+            let payload = [0x12u8, 0x34, 0x56];
+            writer.write_bytes_buffer(&payload).await.unwrap();
+
+            let mut buffer = [0u8; 8];
+            let size = reader_a.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &payload[..]);
+
+            // Reader created after the transmission doesn't hear it
+            let mut reader_late = channel.reader();
+            assert!(reader_late.read_bytes_buffer(&mut buffer).await.is_err());
+
+            let size = reader_b.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &payload[..]);
+        });
+    }
+
+    #[test]
+    fn test_lost_packet() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig {
+            packet_loss_probability: 1.0,
+            ..NoiseConfig::default()
+        }));
+        let mut writer = channel.writer();
+        let mut reader = channel.reader();
+
+        block_on(async {
+            writer.write_bytes_buffer(&[0x01, 0x02]).await.unwrap();
+
+            let mut buffer = [0u8; 2];
+            assert!(reader.read_bytes_buffer(&mut buffer).await.is_err());
+        });
+
+        assert_eq!(channel.statistics().noise.packets_lost, 1);
+    }
+
+    #[test]
+    fn test_collision() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig::lossless()));
+        let mut writer_a = channel.writer();
+        let mut writer_b = channel.writer();
+        let mut reader = channel.reader();
+
+        block_on(async {
+            // Polled together, so both start on the air at the same time whatever the scheduling is
+            let (written_a, written_b) = futures::join!(
+                writer_a.write_bytes_buffer(&[0x0fu8, 0x0f]),
+                writer_b.write_bytes_buffer(&[0xf0u8, 0xf0])
+            );
+            assert!(written_a.is_ok() && written_b.is_ok());
+
+            // Both transmissions overlap so only one frame is on the air
+            let mut buffer = [0u8; 8];
+            assert!(reader.read_bytes_buffer(&mut buffer).await.is_ok());
+            assert!(reader.read_bytes_buffer(&mut buffer).await.is_err());
+        });
+
+        let statistics = channel.statistics();
+        assert_eq!(statistics.transmissions, 2);
+        assert_eq!(statistics.collisions, 1);
+    }
+
+    #[test]
+    fn test_csma_defers_to_transmission() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig::lossless()));
+        let mut writer_a = channel.writer();
+        let mut writer_b = CsmaWriter::new(channel.writer(), channel.carrier_sense());
+        let mut reader = channel.reader();
+
+        block_on(async {
+            let transmission_a = async_std::task::spawn(async move {
+                writer_a.write_bytes_buffer(&[0x0fu8; 8]).await.unwrap();
+            });
+            while !channel.is_busy() {
+                sleep(Duration::from_micros(10)).await;
+            }
+            writer_b.write_bytes_buffer(&[0xf0u8, 0xf0]).await.unwrap();
+            transmission_a.await;
+
+            // Writer B waited, so both frames are on the air one after another
+            let mut buffer = [0u8; 8];
+            let size = reader.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &[0x0fu8; 8]);
+            let size = reader.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &[0xf0u8, 0xf0]);
+        });
+
+        assert_eq!(channel.statistics().collisions, 0);
+    }
+
+    #[test]
+    fn test_csma_writers_back_off() {
+        // Virtual time, so the backoffs don't depend on how the host schedules the sleeps
+        let channel =
+            SimulatedChannel::new(fast_config(NoiseConfig::lossless())).with_virtual_time();
+        let mut writer_a = CsmaWriter::new(channel.writer(), channel.carrier_sense()).with_seed(1);
+        let mut writer_b = CsmaWriter::new(channel.writer(), channel.carrier_sense()).with_seed(2);
+        let mut reader = channel.reader();
+
+        channel.block_on(async {
+            // Both start to listen at the same time, writer A picks the shorter backoff
+            let (written_a, written_b) = futures::join!(
+                writer_a.write_bytes_buffer(&[0x0fu8; 4]),
+                writer_b.write_bytes_buffer(&[0xf0u8; 4])
+            );
+            assert_eq!(written_a.unwrap(), 4);
+            assert_eq!(written_b.unwrap(), 4);
+
+            let mut buffer = [0u8; 8];
+            let size = reader.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &[0x0fu8; 4]);
+            let size = reader.read_bytes_buffer(&mut buffer).await.unwrap();
+            assert_eq!(&buffer[..size], &[0xf0u8; 4]);
+        });
+
+        let statistics = channel.statistics();
+        assert_eq!(statistics.transmissions, 2);
+        assert_eq!(statistics.collisions, 0);
+    }
+
+    #[test]
+    fn test_virtual_time_read_timeout() {
+        let channel =
+            SimulatedChannel::new(fast_config(NoiseConfig::lossless())).with_virtual_time();
+        let mut reader = channel.reader();
+
+        let mut buffer = [0u8; 2];
+        assert!(matches!(
+            channel.block_on(reader.read_bytes_buffer(&mut buffer)),
+            Err(ReadError::TimeoutError)
+        ));
+        assert!(channel.lock().virtual_time.as_ref().unwrap().now >= channel.config.read_timeout);
+    }
+
+    #[test]
+    fn test_csma_gives_up() {
+        let channel = SimulatedChannel::new(fast_config(NoiseConfig::lossless()));
+        let mut writer_a = channel.writer();
+        let mut writer_b =
+            CsmaWriter::new(channel.writer(), channel.carrier_sense()).with_config(CsmaConfig {
+                min_window: 1,
+                max_window: 1,
+                max_attempts: 2,
+            });
+
+        block_on(async {
+            let transmission_a = async_std::task::spawn(async move {
+                writer_a.write_bytes_buffer(&[0x0fu8; 16]).await.unwrap();
+            });
+            while !channel.is_busy() {
+                sleep(Duration::from_micros(10)).await;
+            }
+            assert!(matches!(
+                writer_b.write_bytes_buffer(&[0xf0u8]).await,
+                Err(WriterError::ChannelBusy)
+            ));
+            transmission_a.await;
+        });
+
+        assert_eq!(channel.statistics().transmissions, 1);
+    }
+}
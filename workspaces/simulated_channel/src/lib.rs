@@ -0,0 +1,28 @@
+#![allow(incomplete_features)]
+#![feature(async_fn_in_trait)]
+#![cfg_attr(target_os = "none", no_std)]
+
+//! Host-side simulation of the 433MHz radio channel.
+//!
+//! `SimulatedChannel` implements `physical_layer::BaseReader` and `physical_layer::BaseWriter`
+//! so it can replace real radio in tests of the `network` crate. Errors on the channel are
+//! described by `NoiseConfig` and generated from a seed, so every run is reproducible.
+//!
+//! The crate needs `std`, it is empty when built for the embedded target.
+
+#[cfg(not(target_os = "none"))]
+pub mod channel;
+#[cfg(not(target_os = "none"))]
+pub mod noise;
+#[cfg(not(target_os = "none"))]
+pub mod random;
+
+#[cfg(not(target_os = "none"))]
+pub use channel::{
+    ChannelConfig, ChannelStatistics, SimulatedCarrierSense, SimulatedChannel, SimulatedReader,
+    SimulatedWriter,
+};
+#[cfg(not(target_os = "none"))]
+pub use noise::{BurstConfig, NoiseConfig, NoiseModel, NoiseStatistics};
+#[cfg(not(target_os = "none"))]
+pub use random::XorShiftRng;
@@ -0,0 +1,266 @@
+use crate::random::XorShiftRng;
+
+/// Two state (Gilbert-Elliott) burst error model.
+///
+/// Channel is either in a good state where only `NoiseConfig::bit_flip_probability`
+/// applies, or in a bad state where bits are flipped with `flip_probability`.
+#[derive(Clone, Debug)]
+pub struct BurstConfig {
+    /// Probability of going from good to bad state on each bit
+    pub enter_probability: f64,
+    /// Probability of going from bad to good state on each bit
+    pub exit_probability: f64,
+    /// Probability of flipping a bit while in bad state
+    pub flip_probability: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct NoiseConfig {
+    /// Probability of flipping every single bit on the air
+    pub bit_flip_probability: f64,
+    /// Optional burst errors on top of the random bit flips
+    pub burst: Option<BurstConfig>,
+    /// Probability of losing a single bit (receiver misses an edge)
+    pub bit_drop_probability: f64,
+    /// Probability of losing a whole transmission (one `write_bytes_*` call)
+    pub packet_loss_probability: f64,
+    /// Receiver clock difference in parts per million.
+    /// Positive value means receiver samples faster and sees some bits twice,
+    /// negative value means receiver samples slower and misses some bits.
+    pub clock_skew_ppm: i32,
+}
+
+impl NoiseConfig {
+    /// Channel without any noise
+    pub fn lossless() -> Self {
+        Self::default()
+    }
+
+    /// Channel with random independent bit flips only
+    pub fn with_bit_flips(bit_flip_probability: f64) -> Self {
+        Self {
+            bit_flip_probability,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoiseStatistics {
+    pub bits_transmitted: usize,
+    pub bits_flipped: usize,
+    pub bits_dropped: usize,
+    pub bits_duplicated: usize,
+    pub packets_lost: usize,
+}
+
+/// Applies errors described by `NoiseConfig` to a stream of bits.
+///
+/// All the randomness comes from a seeded generator so the same
+/// seed and the same input always produce the same output.
+pub struct NoiseModel {
+    config: NoiseConfig,
+    rng: XorShiftRng,
+
+    in_burst: bool,
+    skew_accumulator: i64,
+
+    statistics: NoiseStatistics,
+}
+
+impl NoiseModel {
+    const PPM: i64 = 1_000_000;
+
+    pub fn new(config: NoiseConfig, seed: u64) -> Self {
+        Self {
+            config,
+            rng: XorShiftRng::new(seed),
+            in_burst: false,
+            skew_accumulator: 0,
+            statistics: NoiseStatistics::default(),
+        }
+    }
+
+    pub fn config(&self) -> &NoiseConfig {
+        &self.config
+    }
+
+    pub fn statistics(&self) -> &NoiseStatistics {
+        &self.statistics
+    }
+
+    /// Decide if the next whole transmission is lost
+    pub fn lose_packet(&mut self) -> bool {
+        let lost = self.rng.chance(self.config.packet_loss_probability);
+        if lost {
+            self.statistics.packets_lost += 1;
+        }
+        lost
+    }
+
+    /// Push one bit through the channel.
+    /// The `output` is called zero times (dropped bit), once or twice (clock skew).
+    pub fn transmit_bit(&mut self, bit: bool, mut output: impl FnMut(bool)) {
+        self.statistics.bits_transmitted += 1;
+
+        if self.rng.chance(self.config.bit_drop_probability) {
+            self.statistics.bits_dropped += 1;
+            return;
+        }
+
+        let bit = bit ^ self.flip();
+
+        self.skew_accumulator += self.config.clock_skew_ppm as i64;
+        if self.skew_accumulator >= Self::PPM {
+            self.skew_accumulator -= Self::PPM;
+            self.statistics.bits_duplicated += 1;
+            output(bit);
+        } else if self.skew_accumulator <= -Self::PPM {
+            self.skew_accumulator += Self::PPM;
+            self.statistics.bits_dropped += 1;
+            return;
+        }
+
+        output(bit);
+    }
+
+    /// Flip bits of the data in place using only flip and burst errors.
+    /// This is useful to test codecs directly without any physical layer.
+    pub fn corrupt_bytes(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            for index in 0..8 {
+                self.statistics.bits_transmitted += 1;
+                if self.flip() {
+                    *byte ^= 1 << index;
+                }
+            }
+        }
+    }
+
+    fn flip(&mut self) -> bool {
+        let probability = if let Some(burst) = &self.config.burst {
+            self.in_burst = if self.in_burst {
+                !self.rng.chance(burst.exit_probability)
+            } else {
+                self.rng.chance(burst.enter_probability)
+            };
+
+            if self.in_burst {
+                burst.flip_probability
+            } else {
+                self.config.bit_flip_probability
+            }
+        } else {
+            self.config.bit_flip_probability
+        };
+
+        let flipped = self.rng.chance(probability);
+        if flipped {
+            self.statistics.bits_flipped += 1;
+        }
+        flipped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::vec::Vec;
+
+    fn transmit_all(model: &mut NoiseModel, bits: &[bool]) -> Vec<bool> {
+        let mut result = Vec::new();
+        for bit in bits.iter().copied() {
+            model.transmit_bit(bit, |b| result.push(b));
+        }
+        result
+    }
+
+    fn test_bits() -> Vec<bool> {
+        (0..1000).map(|v| v % 3 == 0).collect()
+    }
+
+    #[test]
+    fn test_lossless_channel() {
+        let bits = test_bits();
+        let mut model = NoiseModel::new(NoiseConfig::lossless(), 1);
+
+        assert_eq!(transmit_all(&mut model, &bits), bits);
+        assert!(!model.lose_packet());
+        assert_eq!(model.statistics().bits_transmitted, bits.len());
+    }
+
+    #[test]
+    fn test_same_seed_is_reproducible() {
+        let bits = test_bits();
+        let config = NoiseConfig {
+            bit_flip_probability: 0.05,
+            bit_drop_probability: 0.01,
+            ..NoiseConfig::default()
+        };
+
+        let mut model_a = NoiseModel::new(config.clone(), 1234);
+        let mut model_b = NoiseModel::new(config, 1234);
+
+        let result_a = transmit_all(&mut model_a, &bits);
+        assert_ne!(result_a, bits);
+        assert_eq!(result_a, transmit_all(&mut model_b, &bits));
+        assert_eq!(model_a.statistics(), model_b.statistics());
+    }
+
+    #[test]
+    fn test_clock_skew() {
+        let bits = test_bits();
+
+        let mut fast = NoiseModel::new(
+            NoiseConfig {
+                clock_skew_ppm: 10_000, // 1%
+                ..NoiseConfig::default()
+            },
+            1,
+        );
+        assert_eq!(transmit_all(&mut fast, &bits).len(), bits.len() + 10);
+
+        let mut slow = NoiseModel::new(
+            NoiseConfig {
+                clock_skew_ppm: -10_000,
+                ..NoiseConfig::default()
+            },
+            1,
+        );
+        assert_eq!(transmit_all(&mut slow, &bits).len(), bits.len() - 10);
+    }
+
+    #[test]
+    fn test_packet_loss() {
+        let mut model = NoiseModel::new(
+            NoiseConfig {
+                packet_loss_probability: 1.0,
+                ..NoiseConfig::default()
+            },
+            1,
+        );
+
+        assert!(model.lose_packet());
+        assert_eq!(model.statistics().packets_lost, 1);
+    }
+
+    #[test]
+    fn test_burst_errors() {
+        let mut model = NoiseModel::new(
+            NoiseConfig {
+                burst: Some(BurstConfig {
+                    enter_probability: 1.0,
+                    exit_probability: 0.0,
+                    flip_probability: 1.0,
+                }),
+                ..NoiseConfig::default()
+            },
+            1,
+        );
+
+        let mut data = [0x00u8, 0xff];
+        model.corrupt_bytes(&mut data);
+        assert_eq!(data, [0xff, 0x00]);
+        assert_eq!(model.statistics().bits_flipped, 16);
+    }
+}